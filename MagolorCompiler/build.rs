@@ -0,0 +1,19 @@
+use std::env;
+use std::process::Command;
+
+// Stamps the binary with the build metadata `magolor --version` reports
+fn main() {
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=MAGOLOR_GIT_HASH={}", git_hash);
+    println!("cargo:rustc-env=MAGOLOR_HOST={}", env::var("TARGET").unwrap());
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+}
@@ -0,0 +1,37 @@
+pub mod modules {
+    pub mod tokenizer;
+    pub mod parser;
+    pub mod IR;
+    pub mod validate;
+    pub mod visitor;
+    pub mod passes;
+    pub mod compiler;
+    pub mod cli;
+    pub mod compiledb;
+    pub mod diagnostics;
+    pub mod types;
+    pub mod typeck;
+    pub mod query;
+    pub mod index;
+    pub mod rename;
+    pub mod treeshake;
+    pub mod callgraph;
+    pub mod codegen;
+    pub mod builtins;
+    pub mod consteval;
+    pub mod runtime;
+    pub mod source;
+    pub mod interface;
+    pub mod prelude;
+    pub mod stdlib;
+    pub mod bindings;
+    #[cfg(feature = "python")]
+    pub mod python;
+}
+
+pub use modules::compiler::Compiler;
+pub use modules::codegen::CodegenOptions;
+pub use modules::diagnostics::{Diagnostic, Diagnostics, Severity, Span};
+pub use modules::passes::AstPass;
+pub use modules::types::Type;
+pub use modules::index::{Symbol, SymbolIndex};
@@ -1,41 +1,249 @@
 
 use std::env;
 use std::fs;
-use std::process::Command;
-use anyhow::Result;
-use logos::Logos;
+use std::panic;
+use std::process::ExitCode;
+use anyhow::{Context, Result, anyhow};
 
+use MagolorCompiler::Compiler;
+use MagolorCompiler::modules::cli::{self, CliCommand, CompileOptions, PrintRequest, UsageError};
+use MagolorCompiler::modules::codegen::{self, CodegenOptions, Target};
+use MagolorCompiler::modules::{bindings, compiledb, compiler, rename};
+use MagolorCompiler::modules::diagnostics::Diagnostics;
+use MagolorCompiler::modules::interface::ModuleInterface;
+use MagolorCompiler::modules::parser::AST;
+use MagolorCompiler::modules::source::{SourceFile, SourceMap};
 
+// Exit statuses build tools can rely on. clap exits with 2 itself for bad
+// arguments, and a panic anywhere in the compiler exits with 101.
+const EXIT_COMPILE_ERROR: u8 = 1;
+const EXIT_USAGE: u8 = 2;
 
-mod modules {
-    pub mod tokenizer;
-    pub mod parser;
-    pub mod IR;
+fn main() -> ExitCode {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        eprintln!("error: internal compiler error; this is a bug in magolor, not in your program");
+        default_hook(info);
+    }));
+
+    match run(cli::parse_args(env::args().collect())) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            if e.is::<UsageError>() {
+                ExitCode::from(EXIT_USAGE)
+            } else {
+                ExitCode::from(EXIT_COMPILE_ERROR)
+            }
+        }
+    }
 }
 
-fn main() -> Result<()> {
-    // Grab command-line arguments
-    let args: Vec<String> = env::args().collect();
+fn run(command: CliCommand) -> Result<()> {
+    match command {
+        CliCommand::Compile(batch) => compile_batch(batch),
+        CliCommand::Print {
+            requests,
+            input,
+            codegen,
+        } => print_info(&requests, input.as_deref(), &codegen),
+        CliCommand::Refs { input, symbol } => refs(&input, &symbol),
+        CliCommand::Rename {
+            input,
+            line,
+            col,
+            new_name,
+        } => rename(&input, line, col, &new_name),
+    }
+}
+
+fn print_diagnostics(sources: &SourceMap, diagnostics: &Diagnostics) {
+    for diagnostic in diagnostics.sorted() {
+        eprintln!("{}", sources.render(diagnostic));
+    }
+}
 
-    if args.len() < 2 {
-        println!("Usage: {} <source file>", args[0]);
-        return Ok(());
+fn compile_batch(batch: Vec<CompileOptions>) -> Result<()> {
+    // Flag problems are the same for every input; report them before
+    // compiling anything
+    for options in &batch {
+        cli::check_options(options)?;
     }
 
-    let filename = &args[1];
+    if let [options] = batch.as_slice() {
+        return compile(options.clone());
+    }
+
+    let total = batch.len();
+    let mut failed = 0;
+    for options in batch {
+        if let Err(e) = compile(options) {
+            eprintln!("error: {:#}", e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!("{} of {} inputs failed to compile", failed, total));
+    }
+    Ok(())
+}
+
+fn compile(options: CompileOptions) -> Result<()> {
+    let filename = &options.input;
 
     // Read the source file
     println!("{}",filename);
-    let source = fs::read_to_string(filename)?;
+    let source = SourceFile::open(filename).with_context(|| format!("cannot read {}", filename))?;
+
+    if options.codegen.unroll_loops == Some(true)
+        && matches!(options.codegen.opt_level.as_deref(), None | Some("0"))
+    {
+        eprintln!("warning: --unroll-loops has no effect without -O1 or higher");
+    }
 
-    let tokens = modules::tokenizer::tokenizeFile(&source);
+    let mut compiler = Compiler::new();
+    compiler.set_codegen_options(options.codegen.clone());
+    compiler.set_dump_after(options.dump_after.clone());
+    compiler.set_emit_call_graph(options.emit_call_graph);
+    compiler.set_sandbox(options.sandbox);
+    compiler.set_prelude(!options.no_prelude);
+    compiler.set_keyword_case(options.keyword_case);
 
-    let AST = modules::parser::parseTokens(&tokens);
+    let AST = compiler.parse_source(source);
 
     println!("{:?}", AST);
 
-    modules::IR::compile(AST);
+    let has_main = AST
+        .iter()
+        .any(|node| matches!(node, AST::FuncDef { name, .. } if name == "main"));
+
+    let ir = compiler.compile_ast(AST);
+
+    let diagnostics = compiler.take_diagnostics();
+    print_diagnostics(compiler.source_map(), &diagnostics);
+
+    let Some(ir) = ir else {
+        return Err(anyhow!("compilation failed with {} error(s)", diagnostics.error_count()));
+    };
+
+    match &options.output {
+        Some(path) => fs::write(path, ir)?,
+        None => eprint!("{}", ir),
+    }
+
+    if options.emit_js_glue {
+        let base = options.output.as_deref().unwrap_or(filename);
+        let glue_path = std::path::Path::new(base).with_extension("js");
+        fs::write(&glue_path, codegen::JS_GLUE)?;
+    }
+
+    if let Some(graph) = compiler.take_call_graph() {
+        let base = options.output.as_deref().unwrap_or(filename);
+        fs::write(std::path::Path::new(base).with_extension("dot"), graph)?;
+    }
+
+    if let Some(interface) = compiler.interface() {
+        interface
+            .write(&ModuleInterface::path_for(filename))
+            .map_err(|e| anyhow!(e))?;
+
+        if options.emit_rust_bindings {
+            let base = options.output.as_deref().unwrap_or(filename);
+            fs::write(std::path::Path::new(base).with_extension("rs"), bindings::rust(&interface))?;
+        }
+    }
+
+    // A plain native build is just `clang <ir-file>`, which needs no note
+    if !options.codegen.clang_args().is_empty() || options.codegen.staticlib {
+        let ir_file = options.output.as_deref().unwrap_or("<ir-file>");
+        let commands = options.codegen.build_commands(ir_file, filename, has_main);
+        eprintln!("note: build with `{}`", commands.join(" && "));
+    }
+
+    if let Some(db_path) = &options.compile_db {
+        let entry = compiledb::CompileCommand::new(
+            filename,
+            &options.arguments,
+            options.output.as_deref(),
+        );
+        compiledb::record(db_path, entry).map_err(|e| anyhow!(e))?;
+    }
 
     Ok(())
 }
 
+fn print_info(requests: &[PrintRequest], input: Option<&str>, codegen: &CodegenOptions) -> Result<()> {
+    for request in requests {
+        match request {
+            PrintRequest::TargetList => {
+                for target in Target::ALL {
+                    println!("{}", target.name());
+                }
+            }
+            PrintRequest::TargetTriple => println!("{}", codegen.target.resolved_triple()),
+            PrintRequest::Sysroot => {
+                // The prefix the compiler is installed under: <sysroot>/bin/magolor
+                let exe = env::current_exe()?;
+                let sysroot = exe
+                    .parent()
+                    .and_then(|bin| bin.parent())
+                    .ok_or_else(|| anyhow!("cannot determine the sysroot from {}", exe.display()))?;
+                println!("{}", sysroot.display());
+            }
+            PrintRequest::SearchPaths => {
+                println!("{}", compiler::import_search_path(input.unwrap_or("")).display());
+            }
+            PrintRequest::Linker => println!("{}", codegen.linker_command().join(" ")),
+        }
+    }
+    Ok(())
+}
+
+fn refs(filename: &str, symbol: &str) -> Result<()> {
+    let mut compiler = Compiler::new();
+    let source = SourceFile::open(filename).with_context(|| format!("cannot read {}", filename))?;
+    let ast = compiler.parse_source(source);
+    compiler.analyze(&ast);
+
+    let sources = compiler.source_map();
+    let index = compiler.symbol_index(filename).expect("analysis just ran");
+    let found = index.lookup(symbol);
+    if found.is_empty() {
+        return Err(anyhow!("no symbol named '{}' in {}", symbol, filename));
+    }
+
+    for (sym, entry) in found {
+        if let Some((path, line, col)) = entry.definition.and_then(|def| sources.location(def.start)) {
+            println!("{}:{}:{}: definition of {}", path, line, col, sym);
+        }
+        for reference in &entry.references {
+            if let Some((path, line, col)) = sources.location(reference.start) {
+                println!("{}:{}:{}: reference to {}", path, line, col, sym);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn rename(filename: &str, line: usize, col: usize, new_name: &str) -> Result<()> {
+    let mut compiler = Compiler::new();
+    let source = SourceFile::open(filename).with_context(|| format!("cannot read {}", filename))?;
+    let ast = compiler.parse_source(source);
+    compiler.analyze(&ast);
+
+    let sources = compiler.source_map();
+    let offset = sources
+        .offset_at(filename, line, col)
+        .ok_or_else(|| anyhow!("{}:{}:{} is outside the file", filename, line, col))?;
+
+    let index = compiler.symbol_index(filename).expect("analysis just ran");
+    let edits = rename::rename(sources, index, offset, new_name).map_err(|e| anyhow!(e))?;
+    // Unmap the sources before overwriting the files underneath them
+    drop(compiler);
+    for (path, text) in edits {
+        fs::write(path, text)?;
+    }
+
+    Ok(())
+}
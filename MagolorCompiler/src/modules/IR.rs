@@ -1,155 +1,731 @@
+use crate::modules::codegen::{CodegenOptions, Sanitizer, Target, Toolchain};
+use crate::modules::interface::{INIT_FN, ModuleInterface, init_symbol, is_exported};
 use crate::modules::parser::{AST, ASTValue};
+use crate::modules::prelude;
+use crate::modules::types::Type;
+use crate::modules::runtime;
+use crate::modules::visitor::{AstVisitor, walk_ast, walk_value};
 use inkwell::AddressSpace;
-use inkwell::IntPredicate;
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::basic_block::BasicBlock;
+use inkwell::builder::Builder;
+use inkwell::{FloatPredicate, IntPredicate};
+use inkwell::GlobalVisibility;
 use inkwell::context::Context;
+use inkwell::debug_info::{AsDIScope, DIFlags, DIFlagsConstants, DWARFEmissionKind, DWARFSourceLanguage};
+use inkwell::intrinsics::Intrinsic;
+use inkwell::module::{FlagBehavior, Linkage, Module};
+use inkwell::targets::TargetTriple;
 use inkwell::types::IntType;
 use inkwell::types::*;
 use inkwell::values::IntValue;
 use inkwell::values::*;
 use inkwell::values::{FunctionValue, PointerValue};
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+// Where the module came from and the line each function starts on. With
+// this, functions carry debug locations that coverage tools can attribute
+// execution counts to.
+#[derive(Debug, Clone, Default)]
+pub struct DebugSource {
+    pub file: String,
+    pub directory: String,
+    pub function_lines: HashMap<String, u32>,
+}
+
+// A scope's locals (parameters and `let`s) as slots in declaration order.
+// The AST still refers to variables by name, so every reference is a linear
+// scan of the scope's names, not an O(1) index; that needs a resolution
+// pass giving each VarRef its slot. Redeclaring a name reuses its slot,
+// like the HashMap this replaced.
+#[derive(Default)]
+pub struct Locals<'ctx> {
+    names: Vec<String>,
+    slots: Vec<(PointerValue<'ctx>, VarType)>,
+}
+
+impl<'ctx> Locals<'ctx> {
+    pub fn new() -> Self {
+        Locals {
+            names: Vec::new(),
+            slots: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, name: String, slot: (PointerValue<'ctx>, VarType)) {
+        match self.slot_of(&name) {
+            Some(index) => self.slots[index] = slot,
+            None => {
+                self.names.push(name);
+                self.slots.push(slot);
+            }
+        }
+    }
+
+    fn slot_of(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&(PointerValue<'ctx>, VarType)> {
+        self.slot_of(name).map(|index| &self.slots[index])
+    }
+}
+
+// Everything the compile_* helpers share: the LLVM handles, the types they
+// keep asking for, and the runtime functions generated code calls into.
+// Built once per module, so helpers take one reference instead of a builder,
+// a context and whichever types they happen to need.
+pub struct CodegenCtx<'ctx> {
+    pub context: &'ctx Context,
+    pub module: Module<'ctx>,
+    pub builder: Builder<'ctx>,
+
+    pub i32_type: IntType<'ctx>,
+    pub i64_type: IntType<'ctx>,
+    pub f32_type: FloatType<'ctx>,
+    pub f64_type: FloatType<'ctx>,
+    pub bool_type: IntType<'ctx>,
+    pub i8_type: IntType<'ctx>,
+    pub i8_ptr: PointerType<'ctx>,
+
+    pub target: Target,
+    // Only consulted for Target::Native
+    pub toolchain: Toolchain,
+
+    // puts for console.print, write and exit for the stack check; see
+    // runtime.rs for what backs them on each target
+    pub puts_fn: FunctionValue<'ctx>,
+    pub write_fn: FunctionValue<'ctx>,
+    pub exit_fn: FunctionValue<'ctx>,
+
+    // The module's constant strings, one private global per distinct text;
+    // see const_str
+    strings: RefCell<HashMap<String, GlobalValue<'ctx>>>,
+}
+
+impl<'ctx> CodegenCtx<'ctx> {
+    pub fn new(context: &'ctx Context, name: &str, target: Target, toolchain: Toolchain) -> Self {
+        let module = context.create_module(name);
+        let builder = context.create_builder();
+        if let Some(triple) = target.triple() {
+            module.set_triple(&TargetTriple::create(triple));
+        }
+
+        let runtime = runtime::define_runtime(context, &module, target);
+
+        CodegenCtx {
+            context,
+            module,
+            builder,
+            i32_type: context.i32_type(),
+            i64_type: context.i64_type(),
+            f32_type: context.f32_type(),
+            f64_type: context.f64_type(),
+            bool_type: context.bool_type(),
+            i8_type: context.i8_type(),
+            i8_ptr: context.ptr_type(AddressSpace::default()),
+            target,
+            toolchain,
+            puts_fn: runtime.puts,
+            write_fn: runtime.write,
+            exit_fn: runtime.exit,
+            strings: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // Pointer to a NUL-terminated constant holding `text`. Every use of the
+    // same text shares one global, named .str.N in the order texts are
+    // first seen.
+    pub fn const_str(&self, text: &str) -> PointerValue<'ctx> {
+        let mut strings = self.strings.borrow_mut();
+        if let Some(global) = strings.get(text) {
+            return global.as_pointer_value();
+        }
+
+        let value = self.context.const_string(text.as_bytes(), true);
+        let global = self
+            .module
+            .add_global(value.get_type(), None, &format!(".str.{}", strings.len()));
+        global.set_initializer(&value);
+        global.set_constant(true);
+        global.set_linkage(Linkage::Private);
+        global.set_unnamed_addr(true);
+        strings.insert(text.to_string(), global);
+        global.as_pointer_value()
+    }
+
+    pub fn basic_type(&self, var_type: VarType) -> BasicTypeEnum<'ctx> {
+        match var_type {
+            VarType::Int32 | VarType::UInt32 => self.i32_type.into(),
+            VarType::Int64 | VarType::UInt64 => self.i64_type.into(),
+            VarType::Float32 => self.f32_type.into(),
+            VarType::Float64 => self.f64_type.into(),
+            VarType::Bool => self.bool_type.into(),
+            VarType::Char | VarType::UInt8 => self.i8_type.into(),
+            VarType::Str => self.i8_ptr.into(),
+        }
+    }
+
+    // Inverse of basic_type
+    pub fn var_type(&self, ty: BasicTypeEnum<'ctx>) -> VarType {
+        match ty {
+            BasicTypeEnum::IntType(int) => match int.get_bit_width() {
+                1 => VarType::Bool,
+                8 => VarType::Char,
+                64 => VarType::Int64,
+                _ => VarType::Int32,
+            },
+            BasicTypeEnum::FloatType(float) if float == self.f64_type => VarType::Float64,
+            BasicTypeEnum::FloatType(_) => VarType::Float32,
+            _ => VarType::Str,
+        }
+    }
+
+    pub fn type_of(&self, ty: &Type) -> BasicTypeEnum<'ctx> {
+        match ty {
+            Type::I32 => self.i32_type.into(),
+            Type::I64 | Type::U64 => self.i64_type.into(),
+            Type::U32 => self.i32_type.into(),
+            Type::F32 => self.f32_type.into(),
+            Type::F64 => self.f64_type.into(),
+            Type::Bool => self.bool_type.into(),
+            Type::Char | Type::U8 => self.i8_type.into(),
+            Type::Str => self.i8_ptr.into(),
+            Type::Void => panic!("Unsupported parameter type: void"),
+        }
+    }
+
+    // The C ABI passes and returns a bool or u8 in a register zero-extended
+    // from its LLVM type, and Rust and C callers rely on it (see --emit
+    // rust-bindings), so these get `zeroext` as clang gives them. A char is
+    // ASCII, which comes out the same zero- or sign-extended.
+    pub fn extend_small_ints(&self, function: FunctionValue<'ctx>, params: &[Type], ret: Type) {
+        let zeroext = |loc| {
+            let kind = Attribute::get_named_enum_kind_id("zeroext");
+            function.add_attribute(loc, self.context.create_enum_attribute(kind, 0));
+        };
+        for (i, ty) in params.iter().enumerate() {
+            if matches!(ty, Type::Bool | Type::U8) {
+                zeroext(AttributeLoc::Param(i as u32));
+            }
+        }
+        if matches!(ret, Type::Bool | Type::U8) {
+            zeroext(AttributeLoc::Return);
+        }
+    }
+
+    // Void functions still return an i32 (always 0) so callers can treat
+    // every call as producing a value
+    pub fn fn_type(&self, ret: Type, params: &[BasicMetadataTypeEnum<'ctx>]) -> FunctionType<'ctx> {
+        match ret {
+            Type::Void => self.i32_type.fn_type(params, false),
+            ty => self.type_of(&ty).fn_type(params, false),
+        }
+    }
+}
+
+// The strings the program's literals turn into: string literals wherever
+// they appear, and the text console.print shows for literal numbers and
+// bools. compile() pools these before any function body so the globals
+// follow source order; strings codegen makes up itself (format strings,
+// stack overflow messages) are pooled on first use.
+struct LiteralCollector {
+    strings: Vec<String>,
+}
+
+impl AstVisitor for LiteralCollector {
+    fn visit_ast(&mut self, node: &AST) {
+        if let AST::Call { object, method, args } = node {
+            if object == "console" && method == "print" {
+                for arg in args {
+                    let text = match arg {
+                        ASTValue::Int(n) => n.to_string(),
+                        ASTValue::Int64(n) => n.to_string(),
+                        ASTValue::UInt8(n) => n.to_string(),
+                        ASTValue::UInt32(n) => n.to_string(),
+                        ASTValue::UInt64(n) => n.to_string(),
+                        ASTValue::Float32(f) => f.to_string(),
+                        ASTValue::Float64(f) => f.to_string(),
+                        ASTValue::Bool(b) => b.to_string(),
+                        ASTValue::Char(c) => (*c as char).to_string(),
+                        _ => continue,
+                    };
+                    self.strings.push(text);
+                }
+            }
+        }
+        walk_ast(self, node);
+    }
+
+    fn visit_value(&mut self, value: &ASTValue) {
+        if let ASTValue::Str(s) = value {
+            self.strings.push(s.clone());
+        }
+        walk_value(self, value);
+    }
+}
+
+// Jump targets for `break` and `continue` inside the innermost loop
+#[derive(Debug, Clone, Copy)]
+pub struct LoopTargets<'ctx> {
+    pub continue_block: BasicBlock<'ctx>,
+    pub break_block: BasicBlock<'ctx>,
+}
+
+// State for compiling one function body: where the builder is, which
+// variables are visible, and which loops enclose the current statement.
+// Statement kinds that need any of that (loops, defer, ...) get it from
+// here instead of growing another parameter on every compile_* function.
+pub struct FunctionCodegen<'a, 'ctx> {
+    pub cg: &'a CodegenCtx<'ctx>,
+    pub functions: &'a HashMap<String, FunctionValue<'ctx>>,
+    // The functions that return an unsigned type, which their LLVM
+    // signatures can't say
+    pub unsigned_returns: &'a HashSet<String>,
+    pub function: FunctionValue<'ctx>,
+    // Innermost block last. Each block gets its own scope so a `let` inside
+    // an if body can't be used after the branches merge, where its alloca
+    // wouldn't dominate the use.
+    scopes: Vec<Locals<'ctx>>,
+    loops: Vec<LoopTargets<'ctx>>,
+}
+
+impl<'a, 'ctx> FunctionCodegen<'a, 'ctx> {
+    pub fn new(
+        cg: &'a CodegenCtx<'ctx>,
+        functions: &'a HashMap<String, FunctionValue<'ctx>>,
+        unsigned_returns: &'a HashSet<String>,
+        function: FunctionValue<'ctx>,
+    ) -> Self {
+        FunctionCodegen {
+            cg,
+            functions,
+            unsigned_returns,
+            function,
+            scopes: vec![Locals::new()],
+            loops: Vec::new(),
+        }
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&(PointerValue<'ctx>, VarType)> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    // The method `value.method(...)` calls, and the variable it's called on,
+    // if `value` is a variable whose type's impl has a method by that name
+    pub fn method<'n>(&self, name: &'n str) -> Option<(FunctionValue<'ctx>, &'n str)> {
+        let (receiver, method) = name.split_once('.')?;
+        let (_, var_type) = self.lookup(receiver)?;
+        let function = self.functions.get(&format!("{}.{}", var_type.name(), method))?;
+        Some((*function, receiver))
+    }
+
+    // Whether `value` has one of the unsigned types. They have the same LLVM
+    // types as the signed ones, so this is what picks unsigned division,
+    // comparison and conversion for them.
+    pub fn is_unsigned(&self, value: &ASTValue) -> bool {
+        match value {
+            ASTValue::UInt8(_) | ASTValue::UInt32(_) | ASTValue::UInt64(_) => true,
+            ASTValue::VarRef(name) => self.lookup(name).is_some_and(|(_, var_type)| var_type.is_unsigned()),
+            ASTValue::Add(lhs, rhs)
+            | ASTValue::Sub(lhs, rhs)
+            | ASTValue::Mul(lhs, rhs)
+            | ASTValue::Div(lhs, rhs)
+            | ASTValue::Rem(lhs, rhs) => self.is_unsigned(lhs) || self.is_unsigned(rhs),
+            ASTValue::FuncCall { name, args } => match prelude::lookup(name) {
+                // math.max and the like return their arguments' type
+                Some(builtin) => {
+                    matches!(builtin.params, prelude::Params::Numeric(_))
+                        && args.iter().any(|arg| self.is_unsigned(arg))
+                }
+                None => {
+                    let method = name
+                        .split_once('.')
+                        .and_then(|(receiver, method)| Some((self.lookup(receiver)?.1, method)));
+                    match method {
+                        Some((var_type, method)) => {
+                            self.unsigned_returns.contains(&format!("{}.{}", var_type.name(), method))
+                        }
+                        None => self.unsigned_returns.contains(name),
+                    }
+                }
+            },
+            _ => false,
+        }
+    }
+
+    pub fn declare(&mut self, name: String, slot: (PointerValue<'ctx>, VarType)) {
+        self.scopes
+            .last_mut()
+            .expect("function has no scope")
+            .insert(name, slot);
+    }
+
+    // Compiles `body` in a fresh scope nested in the current one
+    pub fn compile_block(&mut self, body: &[AST]) {
+        self.scopes.push(Locals::new());
+        self.compile_statements(body);
+        self.scopes.pop();
+    }
+
+    pub fn append_block(&self, name: &str) -> BasicBlock<'ctx> {
+        self.cg.context.append_basic_block(self.function, name)
+    }
+
+    pub fn position_at_end(&self, block: BasicBlock<'ctx>) {
+        self.cg.builder.position_at_end(block);
+    }
+
+    // Whether the block being filled already ends in a branch or return, so
+    // nothing more may be appended to it
+    pub fn is_terminated(&self) -> bool {
+        self.cg
+            .builder
+            .get_insert_block()
+            .is_some_and(|block| block.get_terminator().is_some())
+    }
+
+    // The stack pointer at the top of a loop body, to rewind to with
+    // restore_stack before the next pass
+    fn save_stack(&self) -> BasicValueEnum<'ctx> {
+        let stacksave = Intrinsic::find("llvm.stacksave")
+            .and_then(|i| i.get_declaration(&self.cg.module, &[self.cg.i8_ptr.into()]))
+            .expect("llvm.stacksave is missing");
+        self.cg.builder
+            .build_call(stacksave, &[], "loop_sp")
+            .expect("build_call failed")
+            .try_as_basic_value()
+            .left()
+            .expect("llvm.stacksave returned void")
+    }
+
+    fn restore_stack(&self, sp: BasicValueEnum<'ctx>) {
+        let stackrestore = Intrinsic::find("llvm.stackrestore")
+            .and_then(|i| i.get_declaration(&self.cg.module, &[self.cg.i8_ptr.into()]))
+            .expect("llvm.stackrestore is missing");
+        self.cg.builder
+            .build_call(stackrestore, &[sp.into()], "")
+            .expect("build_call failed");
+    }
+
+    // Runs `body` with `targets` as the destination of break/continue
+    pub fn in_loop(&mut self, targets: LoopTargets<'ctx>, body: impl FnOnce(&mut Self)) {
+        self.loops.push(targets);
+        body(self);
+        self.loops.pop();
+    }
+
+    pub fn current_loop(&self) -> Option<LoopTargets<'ctx>> {
+        self.loops.last().copied()
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub enum VarType {
     Int32,
     Int64,
+    // Same LLVM types as the signed ones; the variant is what says to use
+    // unsigned division, comparison and extension on them
+    UInt8,
+    UInt32,
+    UInt64,
     Float32,
     Float64,
     Bool,
+    Char,
     Str,
 }
 
-pub fn compile(ast: Vec<AST>) {
-    // Create context, module, builder once
-    let context = Context::create();
-    let module = context.create_module("magolor");
-    let builder = context.create_builder();
+impl VarType {
+    pub fn of(ty: Type) -> VarType {
+        match ty {
+            Type::I32 => VarType::Int32,
+            Type::I64 => VarType::Int64,
+            Type::U8 => VarType::UInt8,
+            Type::U32 => VarType::UInt32,
+            Type::U64 => VarType::UInt64,
+            Type::F32 => VarType::Float32,
+            Type::F64 => VarType::Float64,
+            Type::Bool => VarType::Bool,
+            Type::Char => VarType::Char,
+            Type::Str => VarType::Str,
+            Type::Void => panic!("no variable has type void"),
+        }
+    }
+
+    // The unsigned type of the same width, for a value that's unsigned but
+    // whose VarType was worked out from its LLVM type
+    pub fn unsigned(self) -> VarType {
+        match self {
+            VarType::Int32 => VarType::UInt32,
+            VarType::Int64 => VarType::UInt64,
+            VarType::Char => VarType::UInt8,
+            other => other,
+        }
+    }
 
-    // Basic types
-    let i32_type = context.i32_type();
-    let i64_type = context.i64_type();
-    let f32_type = context.f32_type();
-    let f64_type = context.f64_type();
-    let bool_type = context.bool_type();
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, VarType::UInt8 | VarType::UInt32 | VarType::UInt64)
+    }
+
+    // As the type is spelled in source
+    pub fn name(&self) -> &'static str {
+        match self {
+            VarType::Int32 => "i32",
+            VarType::Int64 => "i64",
+            VarType::UInt8 => "u8",
+            VarType::UInt32 => "u32",
+            VarType::UInt64 => "u64",
+            VarType::Float32 => "f32",
+            VarType::Float64 => "f64",
+            VarType::Bool => "bool",
+            VarType::Char => "char",
+            VarType::Str => "string",
+        }
+    }
+}
+
+// Version of the LLVM we're linked against, for --version
+pub fn llvm_version() -> String {
+    let (major, minor, patch) = inkwell::support::get_llvm_version();
+    format!("{}.{}.{}", major, minor, patch)
+}
 
-    let i8_ptr = context.ptr_type(AddressSpace::from(0));
+pub fn compile(
+    ast: Vec<AST>,
+    module_name: &str,
+    options: &CodegenOptions,
+    debug: Option<&DebugSource>,
+    imports: &[ModuleInterface],
+) -> String {
+    let context = Context::create();
+    let cg = CodegenCtx::new(&context, "magolor", options.target, options.toolchain);
 
-    // Prepare C's puts function for console.print
-    let puts_type = i32_type.fn_type(&[i8_ptr.into()], false);
-    let puts_fn = module.add_function("puts", puts_type, None);
+    let mut literals = LiteralCollector { strings: Vec::new() };
+    literals.visit_program(&ast);
+    for text in &literals.strings {
+        cg.const_str(text);
+    }
 
-    // Global symbol table for functions
+    let debug_info = debug.map(|source| {
+        cg.module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            cg.i32_type.const_int(3, false),
+        );
+        cg.module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &source.file,
+            &source.directory,
+            "magolor",
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        )
+    });
+
+    // A program's initializers run before its main. A --no-main library is
+    // linked into a host that knows nothing about init, so it runs its own.
+    let run_init = options.no_main
+        || ast
+            .iter()
+            .any(|node| matches!(node, AST::FuncDef { name, .. } if name == "main"));
+
+    // Global symbol table for functions. Only ever used for lookups: LLVM
+    // prints functions in the order they're added, so everything below walks
+    // the source (or the sorted interfaces) rather than this map, which is
+    // what keeps the IR identical from one run to the next.
     let mut functions: HashMap<String, FunctionValue> = HashMap::new();
+    let mut unsigned_returns = HashSet::new();
 
-    // First pass: declare all functions
+    // First pass: declare all functions, in source order
     for node in &ast {
         if let AST::FuncDef {
             name,
             params,
             return_type,
+            attributes,
+            public,
             ..
         } = node
         {
-            let mut param_types = Vec::new();
-
-            // Convert parameter types
-            for (_, param_type) in params {
-                let llvm_type = match param_type.as_str() {
-                    "i32" => i32_type.into(),
-                    "i64" => i64_type.into(),
-                    "f32" => f32_type.into(),
-                    "f64" => f64_type.into(),
-                    "bool" => bool_type.into(),
-                    "str" => i8_ptr.into(),
-                    _ => panic!("Unsupported parameter type: {}", param_type),
+            let param_tys: Vec<Type> = params
+                .iter()
+                .map(|(_, param_type)| {
+                    Type::from_name(param_type)
+                        .unwrap_or_else(|| panic!("Unsupported parameter type: {}", param_type))
+                })
+                .collect();
+            let param_types: Vec<BasicMetadataTypeEnum> = param_tys.iter().map(|ty| cg.type_of(ty).into()).collect();
+
+            let ret = match return_type.as_deref() {
+                None => Type::Void,
+                Some(rt) => Type::from_name(rt)
+                    .unwrap_or_else(|| panic!("Unsupported return type: {}", rt)),
+            };
+
+            let function = cg.module.add_function(name, cg.fn_type(ret, &param_types), None);
+            cg.extend_small_ints(function, &param_tys, ret);
+            if ret.is_unsigned() {
+                unsigned_returns.insert(name.clone());
+            }
+
+            // Probe every page of large frames so they can't jump over the guard page
+            function.add_attribute(
+                AttributeLoc::Function,
+                context.create_string_attribute("probe-stack", "inline-asm"),
+            );
+
+            // ASan instruments whatever carries this attribute when clang lowers
+            // the module. UBSan's checks are inserted by a C front end, and
+            // nothing we generate can trip them yet, so it only needs its runtime.
+            if options.sanitizes(Sanitizer::Address) {
+                let kind = Attribute::get_named_enum_kind_id("sanitize_address");
+                function.add_attribute(AttributeLoc::Function, context.create_enum_attribute(kind, 0));
+            }
+
+            // #[inline] / #[inline(never)] map straight onto the LLVM function attributes
+            for attr in attributes {
+                if attr.name != "inline" {
+                    continue;
+                }
+                let kind_name = match attr.args.first().map(String::as_str) {
+                    Some("never") => "noinline",
+                    _ => "alwaysinline",
                 };
-                param_types.push(llvm_type);
+                let kind = Attribute::get_named_enum_kind_id(kind_name);
+                function.add_attribute(AttributeLoc::Function, context.create_enum_attribute(kind, 0));
             }
 
-            // Determine return type
-            let ret_type = match return_type.as_deref() {
-                Some("void") | None => i32_type.fn_type(&param_types, false),
-                Some("i32") => i32_type.fn_type(&param_types, false),
-                Some("i64") => i64_type.fn_type(&param_types, false),
-                Some("f32") => f32_type.fn_type(&param_types, false),
-                Some("f64") => f64_type.fn_type(&param_types, false),
-                Some("bool") => bool_type.fn_type(&param_types, false),
-                Some("str") => i8_ptr.fn_type(&param_types, false),
-                Some(rt) => panic!("Unsupported return type: {}", rt),
-            };
+            // Only main and `pub` functions are visible outside the object,
+            // so the rest can be inlined or dropped and never clash with
+            // another module's. Every module can have an init, so it stays
+            // local even if `pub`; the module's init wrapper is what gets
+            // exported.
+            if !is_exported(name, *public) {
+                function.set_linkage(Linkage::Internal);
+            }
 
-            let function = module.add_function(name, ret_type, None);
+            // For linking against C: #[weak] lets a strong definition of the
+            // same symbol elsewhere take precedence, and #[visibility] sets
+            // the symbol's visibility in a shared object. typeck only allows
+            // them on exported functions.
+            for attr in attributes {
+                match attr.name.as_str() {
+                    "weak" => function.set_linkage(Linkage::WeakAny),
+                    "visibility" => {
+                        let visibility = match attr.args.first().map(String::as_str) {
+                            Some("hidden") => GlobalVisibility::Hidden,
+                            Some("protected") => GlobalVisibility::Protected,
+                            _ => GlobalVisibility::Default,
+                        };
+                        function.as_global_value().set_visibility(visibility);
+                    }
+                    _ => {}
+                }
+            }
+
+            functions.insert(name.clone(), function);
+        }
+    }
+
+    // Functions from other modules only need a declaration; the linker
+    // resolves them against that module's object. Declared in `use` order,
+    // then by name within each interface.
+    for interface in imports {
+        for (name, sig) in &interface.functions {
+            if functions.contains_key(name) {
+                continue;
+            }
+            let param_types: Vec<BasicMetadataTypeEnum> =
+                sig.params.iter().map(|(_, ty)| cg.type_of(ty).into()).collect();
+            let fn_type = cg.fn_type(sig.ret, &param_types);
+            if sig.ret.is_unsigned() {
+                unsigned_returns.insert(name.clone());
+            }
+            let function = cg.module.add_function(name, fn_type, None);
+            let param_tys: Vec<Type> = sig.params.iter().map(|(_, ty)| *ty).collect();
+            cg.extend_small_ints(function, &param_tys, sig.ret);
             functions.insert(name.clone(), function);
         }
     }
 
-    // Second pass: compile function bodies
+    // Second pass: compile function bodies, again in source order
     for node in ast {
         match node {
             AST::FuncDef {
                 name, params, body, ..
             } => {
-                let function = functions.get(&name).unwrap();
-                let basic_block = context.append_basic_block(*function, "entry");
-                builder.position_at_end(basic_block);
+                let function = functions[&name];
+                let mut fcg = FunctionCodegen::new(&cg, &functions, &unsigned_returns, function);
+                let basic_block = context.append_basic_block(function, "entry");
+                cg.builder.position_at_end(basic_block);
+
+                // Statements don't carry positions yet, so the whole body is
+                // attributed to the line the function starts on
+                if let (Some((dibuilder, compile_unit)), Some(source)) = (&debug_info, debug) {
+                    let line = source.function_lines.get(&name).copied().unwrap_or(0);
+                    let file = compile_unit.get_file();
+                    let subroutine_type =
+                        dibuilder.create_subroutine_type(file, None, &[], DIFlags::PUBLIC);
+                    let subprogram = dibuilder.create_function(
+                        compile_unit.as_debug_info_scope(),
+                        &name,
+                        None,
+                        file,
+                        line,
+                        subroutine_type,
+                        false,
+                        true,
+                        line,
+                        DIFlags::PUBLIC,
+                        false,
+                    );
+                    function.set_subprogram(subprogram);
+                    let location = dibuilder.create_debug_location(
+                        &context,
+                        line,
+                        1,
+                        subprogram.as_debug_info_scope(),
+                        None,
+                    );
+                    cg.builder.set_current_debug_location(location);
+                }
 
-                // Local symbol table for this function
-                let mut variables: HashMap<String, (PointerValue, VarType)> = HashMap::new();
+                fcg.emit_stack_check(&name);
 
                 // Add parameters to symbol table
                 for (i, (param_name, param_type)) in params.iter().enumerate() {
                     let param_value = function.get_nth_param(i as u32).unwrap();
-                    let var_type = match param_type.as_str() {
-                        "i32" => VarType::Int32,
-                        "i64" => VarType::Int64,
-                        "f32" => VarType::Float32,
-                        "f64" => VarType::Float64,
-                        "bool" => VarType::Bool,
-                        "str" => VarType::Str,
-                        _ => panic!("Unsupported parameter type: {}", param_type),
-                    };
+                    // Spelled however the source spells it, `string` or `str`
+                    let ty = Type::from_name(param_type)
+                        .unwrap_or_else(|| panic!("Unsupported parameter type: {}", param_type));
+                    let var_type = VarType::of(ty);
 
                     // Allocate space for parameter and store it
-                    let alloca = match var_type {
-                        VarType::Int32 => builder
-                            .build_alloca(i32_type, param_name)
-                            .expect("alloca failed"),
-                        VarType::Int64 => builder
-                            .build_alloca(i64_type, param_name)
-                            .expect("alloca failed"),
-                        VarType::Float32 => builder
-                            .build_alloca(f32_type, param_name)
-                            .expect("alloca failed"),
-                        VarType::Float64 => builder
-                            .build_alloca(f64_type, param_name)
-                            .expect("alloca failed"),
-                        VarType::Bool => builder
-                            .build_alloca(bool_type, param_name)
-                            .expect("alloca failed"),
-                        VarType::Str => builder
-                            .build_alloca(i8_ptr, param_name)
-                            .expect("alloca failed"),
-                    };
-                    builder.build_store(alloca, param_value);
-                    variables.insert(param_name.clone(), (alloca, var_type));
+                    let alloca = cg
+                        .builder
+                        .build_alloca(cg.basic_type(var_type), param_name)
+                        .expect("alloca failed");
+                    cg.builder.build_store(alloca, param_value);
+                    fcg.declare(param_name.clone(), (alloca, var_type));
                 }
 
-                // Compile function body
-                // Compile function body
-                compile_statements(
-                    &body,
-                    &builder,
-                    &context,
-                    function, // Add this line - the current function
-                    &puts_fn,
-                    &mut variables,
-                    &functions,
-                );
+                // Parameters and top-level lets share the function's scope
+                fcg.compile_statements(&body);
 
                 // Add return if not present
-                let last_block = builder.get_insert_block().unwrap();
-                if last_block.get_terminator().is_none() {
-                    builder.build_return(Some(&i32_type.const_int(0, false)));
+                if !fcg.is_terminated() {
+                    cg.builder.build_return(Some(&cg.i32_type.const_int(0, false)));
                 }
             }
 
@@ -166,1488 +742,1247 @@ pub fn compile(ast: Vec<AST>) {
         }
     }
 
-    // Print LLVM IR
-    module.print_to_stderr();
-}
+    define_module_init(&cg, module_name, functions.get(INIT_FN).copied(), imports, run_init);
 
-fn compile_statements<'ctx>(
-    statements: &[AST],
-    builder: &inkwell::builder::Builder<'ctx>,
-    context: &'ctx Context,
-    current_function: &FunctionValue<'ctx>,
-    puts_fn: &FunctionValue<'ctx>,
-    variables: &mut HashMap<String, (PointerValue<'ctx>, VarType)>,
-    _functions: &HashMap<String, inkwell::values::FunctionValue<'ctx>>, // Add lifetime here
-) {
-    let i32_type = context.i32_type();
-    let i64_type = context.i64_type();
-    let f32_type = context.f32_type();
-    let f64_type = context.f64_type();
-    let bool_type = context.bool_type();
-    let i8_ptr = context.ptr_type(AddressSpace::from(0));
-
-    for stmt in statements {
-        match stmt {
-            AST::Call {
-                object,
-                method,
-                args,
-            } => {
-                if object == "console" && method == "print" {
-                    for arg in args {
-                        match arg {
-                            AST::VarRef(name) => {
-                                if let Some((var_ptr, var_type)) = variables.get(name) {
-                                    let val = match var_type {
-                                        VarType::Str => builder
-                                            .build_load(i8_ptr, *var_ptr, name)
-                                            .expect("load failed"),
-                                        VarType::Int32 => {
-                                            let int_val = builder
-                                                .build_load(i32_type, *var_ptr, name)
-                                                .expect("load failed");
-                                            // Convert to string for printing
-                                            let num_str = format!("%d"); // Using format string for printf-style
-                                            let c_string = builder
-                                                .build_global_string_ptr(&num_str, "tmp")
-                                                .expect("global str failed");
-                                            c_string.as_pointer_value().into()
-                                        }
-                                        VarType::Int64 => {
-                                            let int_val = builder
-                                                .build_load(i64_type, *var_ptr, name)
-                                                .expect("load failed");
-                                            let num_str = format!("%lld"); // Format for long long
-                                            let c_string = builder
-                                                .build_global_string_ptr(&num_str, "tmp")
-                                                .expect("global str failed");
-                                            c_string.as_pointer_value().into()
-                                        }
-                                        VarType::Float32 => {
-                                            let float_val = builder
-                                                .build_load(f32_type, *var_ptr, name)
-                                                .expect("load failed");
-                                            let num_str = format!("%.2f"); // Format for float
-                                            let c_string = builder
-                                                .build_global_string_ptr(&num_str, "tmp")
-                                                .expect("global str failed");
-                                            c_string.as_pointer_value().into()
-                                        }
-                                        VarType::Float64 => {
-                                            let float_val = builder
-                                                .build_load(f64_type, *var_ptr, name)
-                                                .expect("load failed");
-                                            let num_str = format!("%.2lf"); // Format for double
-                                            let c_string = builder
-                                                .build_global_string_ptr(&num_str, "tmp")
-                                                .expect("global str failed");
-                                            c_string.as_pointer_value().into()
-                                        }
-                                        VarType::Bool => {
-                                            let bool_val = builder
-                                                .build_load(bool_type, *var_ptr, name)
-                                                .expect("load failed");
-                                            // Convert bool to "true" or "false" string
-                                            let true_str = builder
-                                                .build_global_string_ptr("true", "true_str")
-                                                .expect("global str failed");
-                                            let false_str = builder
-                                                .build_global_string_ptr("false", "false_str")
-                                                .expect("global str failed");
-
-                                            let selected = builder
-                                                .build_select(
-                                                    bool_val.into_int_value(),
-                                                    true_str.as_pointer_value(),
-                                                    false_str.as_pointer_value(),
-                                                    "bool_str_select",
-                                                )
-                                                .expect("select failed");
-                                            selected.into()
-                                        }
-                                    };
+    if let Some((dibuilder, _)) = &debug_info {
+        dibuilder.finalize();
+    }
 
-                                    builder.build_call(*puts_fn, &[val.into()], "call_puts");
-                                } else {
-                                    panic!("Unknown variable {}", name);
-                                }
-                            }
+    // Invalid IR is a bug here, not in the program, so it stops the compiler
+    // instead of turning into a confusing error from clang later
+    if let Err(message) = cg.module.verify() {
+        panic!("generated invalid LLVM IR for '{}':\n{}", module_name, message);
+    }
 
-                            AST::Literal(ASTValue::Str(s)) => {
-                                let c_string = builder
-                                    .build_global_string_ptr(s, "tmp")
-                                    .expect("global str failed");
-                                builder.build_call(
-                                    *puts_fn,
-                                    &[c_string.as_pointer_value().into()],
-                                    "call_puts",
-                                );
-                            }
-                            AST::Literal(ASTValue::Int(n)) => {
-                                let num_str = n.to_string();
-                                let c_string = builder
-                                    .build_global_string_ptr(&num_str, "tmp")
-                                    .expect("global int str failed");
-                                builder.build_call(
-                                    *puts_fn,
-                                    &[c_string.as_pointer_value().into()],
-                                    "call_puts",
-                                );
-                            }
-                            AST::Literal(ASTValue::Float32(f)) => {
-                                let num_str = f.to_string();
-                                let c_string = builder
-                                    .build_global_string_ptr(&num_str, "tmp")
-                                    .expect("global float str failed");
-                                builder.build_call(
-                                    *puts_fn,
-                                    &[c_string.as_pointer_value().into()],
-                                    "call_puts",
-                                );
-                            }
-                            AST::Literal(ASTValue::Float64(f)) => {
-                                let num_str = f.to_string();
-                                let c_string = builder
-                                    .build_global_string_ptr(&num_str, "tmp")
-                                    .expect("global float str failed");
-                                builder.build_call(
-                                    *puts_fn,
-                                    &[c_string.as_pointer_value().into()],
-                                    "call_puts",
-                                );
-                            }
-                            AST::Literal(ASTValue::Int64(n)) => {
-                                let num_str = n.to_string();
-                                let c_string = builder
-                                    .build_global_string_ptr(&num_str, "tmp")
-                                    .expect("global int64 str failed");
-                                builder.build_call(
-                                    *puts_fn,
-                                    &[c_string.as_pointer_value().into()],
-                                    "call_puts",
-                                );
-                            }
-                            AST::Literal(ASTValue::Bool(b)) => {
-                                let bool_str = if *b { "true" } else { "false" };
-                                let c_string = builder
-                                    .build_global_string_ptr(bool_str, "tmp")
-                                    .expect("global bool str failed");
-                                builder.build_call(
-                                    *puts_fn,
-                                    &[c_string.as_pointer_value().into()],
-                                    "call_puts",
-                                );
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-            }
+    // Hand the textual LLVM IR back to the driver
+    cg.module.print_to_string().to_string()
+}
 
-            AST::VarDecl(ty, name, value) => match value {
-                ASTValue::LessThan(lhs, rhs) => {
-                    build_comparison_var(
-                        builder,
-                        variables,
-                        _functions,
-                        ty,
-                        name,
-                        lhs,
-                        rhs,
-                        IntPredicate::SLT,
-                        i32_type,
-                    );
-                }
-                ASTValue::LessEqual(lhs, rhs) => {
-                    build_comparison_var(
-                        builder,
-                        variables,
-                        _functions,
-                        ty,
-                        name,
-                        lhs,
-                        rhs,
-                        IntPredicate::SLE,
-                        i32_type,
-                    );
-                }
-                ASTValue::GreaterThan(lhs, rhs) => {
-                    build_comparison_var(
-                        builder,
-                        variables,
-                        _functions,
-                        ty,
-                        name,
-                        lhs,
-                        rhs,
-                        IntPredicate::SGT,
-                        i32_type,
-                    );
-                }
-                ASTValue::GreaterEqual(lhs, rhs) => {
-                    build_comparison_var(
-                        builder,
-                        variables,
-                        _functions,
-                        ty,
-                        name,
-                        lhs,
-                        rhs,
-                        IntPredicate::SGE,
-                        i32_type,
-                    );
-                }
-                ASTValue::Equal(lhs, rhs) => {
-                    build_comparison_var(
-                        builder,
-                        variables,
-                        _functions,
-                        ty,
-                        name,
-                        lhs,
-                        rhs,
-                        IntPredicate::EQ,
-                        i32_type,
-                    );
-                }
-                ASTValue::EqualEqual(lhs, rhs) => {
-                    build_comparison_var(
-                        builder,
-                        variables,
-                        _functions,
-                        ty,
-                        name,
-                        lhs,
-                        rhs,
-                        IntPredicate::EQ,
-                        i32_type,
-                    );
-                }
-                ASTValue::NotEqual(lhs, rhs) => {
-                    build_comparison_var(
-                        builder,
-                        variables,
-                        _functions,
-                        ty,
-                        name,
-                        lhs,
-                        rhs,
-                        IntPredicate::NE,
-                        i32_type,
-                    );
-                }
-                ASTValue::Str(s) => {
-                    println!("Declare var: {} {} = \"{}\"", ty, name, s);
+// Defines init_symbol(module) when the module or one of its imports has an
+// init(): it runs the imports' wrappers in `use` order, then this module's
+// init, guarded so a module imported along several paths is initialized
+// once. With `run_init` the wrapper goes in llvm.global_ctors so the whole
+// chain runs at load time; modules leave that to whoever imports them.
+fn define_module_init<'ctx>(
+    cg: &CodegenCtx<'ctx>,
+    module_name: &str,
+    init: Option<FunctionValue<'ctx>>,
+    imports: &[ModuleInterface],
+    run_init: bool,
+) {
+    let imported: Vec<&ModuleInterface> = imports.iter().filter(|i| i.init).collect();
+    if init.is_none() && imported.is_empty() {
+        return;
+    }
 
-                    let var_alloca = builder.build_alloca(i8_ptr, name).expect("alloca failed");
-                    let c_string = builder
-                        .build_global_string_ptr(s, &format!("{}_str", name))
-                        .expect("global str failed");
-                    builder.build_store(var_alloca, c_string.as_pointer_value());
+    let symbol = init_symbol(module_name);
+    let wrapper_type = cg.context.void_type().fn_type(&[], false);
+    let wrapper = cg.module.add_function(&symbol, wrapper_type, None);
+
+    let done = cg.module.add_global(cg.bool_type, None, &format!("{}.done", symbol));
+    done.set_initializer(&cg.bool_type.const_zero());
+    done.set_linkage(Linkage::Private);
+
+    let entry = cg.context.append_basic_block(wrapper, "entry");
+    let run = cg.context.append_basic_block(wrapper, "run");
+    let exit = cg.context.append_basic_block(wrapper, "exit");
+
+    cg.builder.position_at_end(entry);
+    let already = cg.builder
+        .build_load(cg.bool_type, done.as_pointer_value(), "already")
+        .expect("build_load failed")
+        .into_int_value();
+    cg.builder
+        .build_conditional_branch(already, exit, run)
+        .expect("build_cond_br failed");
+
+    cg.builder.position_at_end(run);
+    cg.builder
+        .build_store(done.as_pointer_value(), cg.bool_type.const_all_ones())
+        .expect("build_store failed");
+    for interface in imported {
+        let import_symbol = init_symbol(&interface.module);
+        let import_wrapper = cg
+            .module
+            .get_function(&import_symbol)
+            .unwrap_or_else(|| cg.module.add_function(&import_symbol, wrapper_type, None));
+        cg.builder.build_call(import_wrapper, &[], "").expect("build_call failed");
+    }
+    if let Some(init) = init {
+        cg.builder.build_call(init, &[], "").expect("build_call failed");
+    }
+    cg.builder.build_unconditional_branch(exit).expect("build_br failed");
 
-                    variables.insert(name.clone(), (var_alloca, VarType::Str));
-                }
-                ASTValue::Int(n) => {
-                    println!("Declare var: {} {} = {}", ty, name, n);
-
-                    // Determine the correct integer type based on the declared type
-                    let (var_alloca, var_type) = match ty.as_str() {
-                        "i32" => {
-                            let alloca =
-                                builder.build_alloca(i32_type, name).expect("alloca failed");
-                            let int_val = i32_type.const_int(*n as u64, false);
-                            builder.build_store(alloca, int_val);
-                            (alloca, VarType::Int32)
-                        }
-                        "i64" => {
-                            let alloca =
-                                builder.build_alloca(i64_type, name).expect("alloca failed");
-                            let int_val = i64_type.const_int(*n as u64, false);
-                            builder.build_store(alloca, int_val);
-                            (alloca, VarType::Int64)
-                        }
-                        _ => {
-                            // Default to i32 for backward compatibility
-                            let alloca =
-                                builder.build_alloca(i32_type, name).expect("alloca failed");
-                            let int_val = i32_type.const_int(*n as u64, false);
-                            builder.build_store(alloca, int_val);
-                            (alloca, VarType::Int32)
-                        }
-                    };
+    cg.builder.position_at_end(exit);
+    cg.builder.build_return(None).expect("build_return failed");
 
-                    variables.insert(name.clone(), (var_alloca, var_type));
-                }
-                ASTValue::Int64(n) => {
-                    println!("Declare var: {} {} = {}", ty, name, n);
-
-                    // Determine the correct integer type based on the declared type
-                    let (var_alloca, var_type) = match ty.as_str() {
-                        "i32" => {
-                            let alloca =
-                                builder.build_alloca(i32_type, name).expect("alloca failed");
-                            let int_val = i32_type.const_int(*n as u64, false);
-                            builder.build_store(alloca, int_val);
-                            (alloca, VarType::Int32)
-                        }
-                        "i64" => {
-                            let alloca =
-                                builder.build_alloca(i64_type, name).expect("alloca failed");
-                            let int_val = i64_type.const_int(*n as u64, false);
-                            builder.build_store(alloca, int_val);
-                            (alloca, VarType::Int64)
-                        }
-                        _ => {
-                            // Default to i64 for Int64 literals
-                            let alloca =
-                                builder.build_alloca(i64_type, name).expect("alloca failed");
-                            let int_val = i64_type.const_int(*n as u64, false);
-                            builder.build_store(alloca, int_val);
-                            (alloca, VarType::Int64)
-                        }
-                    };
+    if run_init {
+        // { priority, function, data } with the default priority C uses
+        let ctor_type = cg.context.struct_type(
+            &[cg.i32_type.into(), cg.i8_ptr.into(), cg.i8_ptr.into()],
+            false,
+        );
+        let ctor = ctor_type.const_named_struct(&[
+            cg.i32_type.const_int(65535, false).into(),
+            wrapper.as_global_value().as_pointer_value().into(),
+            cg.i8_ptr.const_null().into(),
+        ]);
+        let ctors = cg.module.add_global(ctor_type.array_type(1), None, "llvm.global_ctors");
+        ctors.set_initializer(&ctor_type.const_array(&[ctor]));
+        ctors.set_linkage(Linkage::Appending);
+    }
+}
 
-                    variables.insert(name.clone(), (var_alloca, var_type));
-                }
-                ASTValue::Float32(f) => {
-                    println!("Declare var: {} {} = {}", ty, name, f);
-
-                    // Determine the correct float type based on the declared type
-                    let (var_alloca, var_type) = match ty.as_str() {
-                        "f32" => {
-                            let alloca =
-                                builder.build_alloca(f32_type, name).expect("alloca failed");
-                            let float_val = f32_type.const_float(*f as f64);
-                            builder.build_store(alloca, float_val);
-                            (alloca, VarType::Float32)
-                        }
-                        "f64" => {
-                            let alloca =
-                                builder.build_alloca(f64_type, name).expect("alloca failed");
-                            let float_val = f64_type.const_float(*f as f64);
-                            builder.build_store(alloca, float_val);
-                            (alloca, VarType::Float64)
-                        }
-                        _ => {
-                            // Default to f32 for Float32 literals
-                            let alloca =
-                                builder.build_alloca(f32_type, name).expect("alloca failed");
-                            let float_val = f32_type.const_float(*f as f64);
-                            builder.build_store(alloca, float_val);
-                            (alloca, VarType::Float32)
-                        }
-                    };
+impl<'a, 'ctx> FunctionCodegen<'a, 'ctx> {
+    // main records where the stack starts; every other function compares its own
+    // stack pointer against that on entry and exits with "stack overflow in
+    // function f" instead of running into the guard page and segfaulting
+    fn emit_stack_check(&self, name: &str) {
+        // Every module defines the base, weakly, so objects linked into one
+        // program share it and only the one with main sets it
+        let stack_base = self.cg.module.get_global("__magolor_stack_base").unwrap_or_else(|| {
+            let global = self.cg.module.add_global(self.cg.i64_type, None, "__magolor_stack_base");
+            global.set_initializer(&self.cg.i64_type.const_zero());
+            global.set_linkage(Linkage::WeakAny);
+            global
+        });
+
+        let stacksave = Intrinsic::find("llvm.stacksave")
+            .and_then(|i| i.get_declaration(&self.cg.module, &[self.cg.i8_ptr.into()]))
+            .expect("llvm.stacksave is missing");
+        let sp = self.cg.builder
+            .build_call(stacksave, &[], "sp")
+            .expect("build_call failed")
+            .try_as_basic_value()
+            .left()
+            .expect("llvm.stacksave returned void")
+            .into_pointer_value();
+        let sp = self.cg.builder
+            .build_ptr_to_int(sp, self.cg.i64_type, "sp_int")
+            .expect("build_ptr_to_int failed");
+
+        if name == "main" {
+            self.cg.builder
+                .build_store(stack_base.as_pointer_value(), sp)
+                .expect("build_store failed");
+            return;
+        }
 
-                    variables.insert(name.clone(), (var_alloca, var_type));
-                }
-                ASTValue::Float64(f) => {
-                    println!("Declare var: {} {} = {}", ty, name, f);
-
-                    // Determine the correct float type based on the declared type
-                    let (var_alloca, var_type) = match ty.as_str() {
-                        "f32" => {
-                            let alloca =
-                                builder.build_alloca(f32_type, name).expect("alloca failed");
-                            let float_val = f32_type.const_float(*f);
-                            builder.build_store(alloca, float_val);
-                            (alloca, VarType::Float32)
-                        }
-                        "f64" => {
-                            let alloca =
-                                builder.build_alloca(f64_type, name).expect("alloca failed");
-                            let float_val = f64_type.const_float(*f);
-                            builder.build_store(alloca, float_val);
-                            (alloca, VarType::Float64)
-                        }
-                        _ => {
-                            // Default to f64 for Float64 literals
-                            let alloca =
-                                builder.build_alloca(f64_type, name).expect("alloca failed");
-                            let float_val = f64_type.const_float(*f);
-                            builder.build_store(alloca, float_val);
-                            (alloca, VarType::Float64)
-                        }
-                    };
+        let base = self.cg.builder
+            .build_load(self.cg.i64_type, stack_base.as_pointer_value(), "stack_base")
+            .expect("build_load failed")
+            .into_int_value();
+        // The base stays 0 when main isn't ours (e.g. linked into a C program)
+        let has_base = self.cg.builder
+            .build_int_compare(IntPredicate::NE, base, self.cg.i64_type.const_zero(), "has_base")
+            .expect("build_int_compare failed");
+        let used = self.cg.builder.build_int_sub(base, sp, "stack_used").expect("build_int_sub failed");
+        let too_deep = self.cg.builder
+            .build_int_compare(
+                IntPredicate::UGT,
+                used,
+                self.cg.i64_type.const_int(self.cg.target.stack_limit(), false),
+                "too_deep",
+            )
+            .expect("build_int_compare failed");
+        let overflowed = self.cg.builder
+            .build_and(has_base, too_deep, "stack_overflow")
+            .expect("build_and failed");
+
+        let overflow_bb = self.append_block("stack_overflow");
+        let body_bb = self.append_block("body");
+        self.cg.builder
+            .build_conditional_branch(overflowed, overflow_bb, body_bb)
+            .expect("Failed to build conditional branch");
+
+        self.position_at_end(overflow_bb);
+        let message = format!("stack overflow in function {}\n", name);
+        let message_ptr = self.cg.const_str(&message);
+        self.cg.builder
+            .build_call(
+                self.cg.write_fn,
+                &[
+                    self.cg.i32_type.const_int(2, false).into(),
+                    message_ptr.into(),
+                    self.cg.i64_type.const_int(message.len() as u64, false).into(),
+                ],
+                "report_overflow",
+            )
+            .expect("build_call failed");
+        self.cg.builder
+            .build_call(self.cg.exit_fn, &[self.cg.i32_type.const_int(1, false).into()], "")
+            .expect("build_call failed");
+        self.cg.builder.build_unreachable().expect("build_unreachable failed");
+
+        self.position_at_end(body_bb);
+    }
 
-                    variables.insert(name.clone(), (var_alloca, var_type));
+    fn compile_statements(&mut self, statements: &[AST]) {
+        for stmt in statements {
+            // Whatever follows a return or a noreturn call never runs, and
+            // the block it would go in is already closed
+            if self.is_terminated() {
+                break;
+            }
+            match stmt {
+                AST::Call {
+                    object,
+                    method,
+                    args,
+                } => {
+                    self.compile_operand(&ASTValue::FuncCall {
+                        name: format!("{}.{}", object, method),
+                        args: args.clone(),
+                    });
                 }
-                ASTValue::Bool(b) => {
-                    println!("Declare var: {} {} = {}", ty, name, b);
 
-                    let var_alloca = builder
-                        .build_alloca(bool_type, name)
-                        .expect("alloca failed");
-                    let bool_val = bool_type.const_int(if *b { 1 } else { 0 }, false);
-                    builder.build_store(var_alloca, bool_val);
+                AST::VarDecl(ty, name, value, _) => match value {
+                    ASTValue::LessThan(..)
+                    | ASTValue::GreaterThan(..)
+                    | ASTValue::LessEqual(..)
+                    | ASTValue::GreaterEqual(..)
+                    | ASTValue::Equal(..)
+                    | ASTValue::EqualEqual(..)
+                    | ASTValue::NotEqual(..)
+                    | ASTValue::Add(..)
+                    | ASTValue::Sub(..)
+                    | ASTValue::Mul(..)
+                    | ASTValue::Div(..)
+                    | ASTValue::Rem(..)
+                    | ASTValue::Neg(..)
+                    | ASTValue::And(..)
+                    | ASTValue::Or(..)
+                    | ASTValue::Not(..)
+                    | ASTValue::UInt8(_)
+                    | ASTValue::UInt32(_)
+                    | ASTValue::UInt64(_) => self.declare_value(ty, name, value),
+                    ASTValue::FuncCall { name: func_name, .. }
+                        if prelude::lookup(func_name).is_some() || self.method(func_name).is_some() =>
+                    {
+                        self.declare_value(ty, name, value)
+                    }
+                    ASTValue::Int(_) | ASTValue::Int64(_)
+                        if Type::from_name(ty).is_some_and(|ty| ty.is_unsigned()) =>
+                    {
+                        self.declare_value(ty, name, value)
+                    }
+                    ASTValue::Str(s) => {
+                        println!("Declare var: {} {} = \"{}\"", ty, name, s);
 
-                    variables.insert(name.clone(), (var_alloca, VarType::Bool));
-                }
-                ASTValue::VarRef(ref_name) => {
-                    // Handle variable assignment from another variable
-                    if let Some((src_ptr, src_type)) = variables.get(ref_name) {
-                        let var_alloca = match src_type {
-                            VarType::Int32 => {
-                                builder.build_alloca(i32_type, name).expect("alloca failed")
+                        let var_alloca = self.cg.builder.build_alloca(self.cg.i8_ptr, name).expect("alloca failed");
+                        let c_string = self.cg.const_str(s);
+                        self.cg.builder.build_store(var_alloca, c_string);
+
+                        self.declare(name.clone(), (var_alloca, VarType::Str));
+                    }
+                    ASTValue::Int(n) => {
+                        println!("Declare var: {} {} = {}", ty, name, n);
+
+                        // Determine the correct integer type based on the declared type
+                        let (var_alloca, var_type) = match ty.as_str() {
+                            "i32" => {
+                                let alloca =
+                                    self.cg.builder.build_alloca(self.cg.i32_type, name).expect("alloca failed");
+                                let int_val = self.cg.i32_type.const_int(*n as u64, false);
+                                self.cg.builder.build_store(alloca, int_val);
+                                (alloca, VarType::Int32)
                             }
-                            VarType::Int64 => {
-                                builder.build_alloca(i64_type, name).expect("alloca failed")
+                            "i64" => {
+                                let alloca =
+                                    self.cg.builder.build_alloca(self.cg.i64_type, name).expect("alloca failed");
+                                let int_val = self.cg.i64_type.const_int(*n as u64, false);
+                                self.cg.builder.build_store(alloca, int_val);
+                                (alloca, VarType::Int64)
                             }
-                            VarType::Float32 => {
-                                builder.build_alloca(f32_type, name).expect("alloca failed")
+                            _ => {
+                                // Default to i32 for backward compatibility
+                                let alloca =
+                                    self.cg.builder.build_alloca(self.cg.i32_type, name).expect("alloca failed");
+                                let int_val = self.cg.i32_type.const_int(*n as u64, false);
+                                self.cg.builder.build_store(alloca, int_val);
+                                (alloca, VarType::Int32)
+                            }
+                        };
+
+                        self.declare(name.clone(), (var_alloca, var_type));
+                    }
+                    ASTValue::Int64(n) => {
+                        println!("Declare var: {} {} = {}", ty, name, n);
+
+                        // Determine the correct integer type based on the declared type
+                        let (var_alloca, var_type) = match ty.as_str() {
+                            "i32" => {
+                                let alloca =
+                                    self.cg.builder.build_alloca(self.cg.i32_type, name).expect("alloca failed");
+                                let int_val = self.cg.i32_type.const_int(*n as u64, false);
+                                self.cg.builder.build_store(alloca, int_val);
+                                (alloca, VarType::Int32)
                             }
-                            VarType::Float64 => {
-                                builder.build_alloca(f64_type, name).expect("alloca failed")
+                            "i64" => {
+                                let alloca =
+                                    self.cg.builder.build_alloca(self.cg.i64_type, name).expect("alloca failed");
+                                let int_val = self.cg.i64_type.const_int(*n as u64, false);
+                                self.cg.builder.build_store(alloca, int_val);
+                                (alloca, VarType::Int64)
                             }
-                            VarType::Bool => builder
-                                .build_alloca(bool_type, name)
-                                .expect("alloca failed"),
-                            VarType::Str => {
-                                builder.build_alloca(i8_ptr, name).expect("alloca failed")
+                            _ => {
+                                // Default to i64 for Int64 literals
+                                let alloca =
+                                    self.cg.builder.build_alloca(self.cg.i64_type, name).expect("alloca failed");
+                                let int_val = self.cg.i64_type.const_int(*n as u64, false);
+                                self.cg.builder.build_store(alloca, int_val);
+                                (alloca, VarType::Int64)
                             }
                         };
 
-                        let src_val = match src_type {
-                            VarType::Int32 => builder
-                                .build_load(i32_type, *src_ptr, ref_name)
-                                .expect("load failed"),
-                            VarType::Int64 => builder
-                                .build_load(i64_type, *src_ptr, ref_name)
-                                .expect("load failed"),
-                            VarType::Float32 => builder
-                                .build_load(f32_type, *src_ptr, ref_name)
-                                .expect("load failed"),
-                            VarType::Float64 => builder
-                                .build_load(f64_type, *src_ptr, ref_name)
-                                .expect("load failed"),
-                            VarType::Bool => builder
-                                .build_load(bool_type, *src_ptr, ref_name)
-                                .expect("load failed"),
-                            VarType::Str => builder
-                                .build_load(i8_ptr, *src_ptr, ref_name)
-                                .expect("load failed"),
+                        self.declare(name.clone(), (var_alloca, var_type));
+                    }
+                    ASTValue::Float32(f) => {
+                        println!("Declare var: {} {} = {}", ty, name, f);
+
+                        // Determine the correct float type based on the declared type
+                        let (var_alloca, var_type) = match ty.as_str() {
+                            "f32" => {
+                                let alloca =
+                                    self.cg.builder.build_alloca(self.cg.f32_type, name).expect("alloca failed");
+                                let float_val = self.cg.f32_type.const_float(*f as f64);
+                                self.cg.builder.build_store(alloca, float_val);
+                                (alloca, VarType::Float32)
+                            }
+                            "f64" => {
+                                let alloca =
+                                    self.cg.builder.build_alloca(self.cg.f64_type, name).expect("alloca failed");
+                                let float_val = self.cg.f64_type.const_float(*f as f64);
+                                self.cg.builder.build_store(alloca, float_val);
+                                (alloca, VarType::Float64)
+                            }
+                            _ => {
+                                // Default to f32 for Float32 literals
+                                let alloca =
+                                    self.cg.builder.build_alloca(self.cg.f32_type, name).expect("alloca failed");
+                                let float_val = self.cg.f32_type.const_float(*f as f64);
+                                self.cg.builder.build_store(alloca, float_val);
+                                (alloca, VarType::Float32)
+                            }
                         };
 
-                        builder.build_store(var_alloca, src_val);
-                        variables.insert(name.clone(), (var_alloca, *src_type));
-                    } else {
-                        panic!("Unknown variable reference: {}", ref_name);
+                        self.declare(name.clone(), (var_alloca, var_type));
                     }
-                }
-                ASTValue::FuncCall {
-                    name: func_name,
-                    args,
-                } => {
-                    // Handle function call assignment
-                    if let Some(function) = _functions.get(func_name) {
-                        // Prepare arguments for function call
-                        let mut call_args = Vec::new();
-
-                        for arg in args {
-                            match arg {
-                                ASTValue::LessThan(lhs, rhs) => {
-                                    call_args.push(
-                                        compile_comparison(
-                                            builder,
-                                            variables,
-                                            _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::SLT,
-                                            i32_type,
-                                        )
-                                        .into(),
-                                    );
-                                }
-                                ASTValue::GreaterThan(lhs, rhs) => {
-                                    call_args.push(
-                                        compile_comparison(
-                                            builder,
-                                            variables,
-                                           _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::SGT,
-                                            i32_type,
-                                        )
-                                        .into(),
-                                    );
-                                }
-                                ASTValue::LessEqual(lhs, rhs) => {
-                                    call_args.push(
-                                        compile_comparison(
-                                            builder,
-                                            variables,
-                                            _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::SLE,
-                                            i32_type,
-                                        )
-                                        .into(),
-                                    );
-                                }
-                                ASTValue::GreaterEqual(lhs, rhs) => {
-                                    call_args.push(
-                                        compile_comparison(
-                                            builder,
-                                            variables,
-                                            _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::SGE,
-                                            i32_type,
-                                        )
-                                        .into(),
-                                    );
-                                }
-                                ASTValue::Equal(lhs, rhs) => {
-                                    call_args.push(
-                                        compile_comparison(
-                                            builder,
-                                            variables,
-                                            _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::EQ,
-                                            i32_type,
-                                        )
-                                        .into(),
-                                    );
-                                }
-                                ASTValue::EqualEqual(lhs, rhs) => {
-                                    call_args.push(
-                                        compile_comparison(
-                                            builder,
-                                            variables,
-                                            _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::EQ,
-                                            i32_type,
-                                        )
-                                        .into(),
-                                    );
-                                }
-                                ASTValue::NotEqual(lhs, rhs) => {
-                                    call_args.push(
-                                        compile_comparison(
-                                            builder,
-                                            variables,
-                                            _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::NE,
-                                            i32_type,
-                                        )
-                                        .into(),
-                                    );
-                                }
-
-                                ASTValue::Int(n) => {
-                                    call_args.push(i32_type.const_int(*n as u64, false).into());
-                                }
-                                ASTValue::Int64(n) => {
-                                    call_args.push(i64_type.const_int(*n as u64, false).into());
-                                }
-                                ASTValue::Float32(f) => {
-                                    call_args.push(f32_type.const_float(*f as f64).into());
-                                }
-                                ASTValue::Float64(f) => {
-                                    call_args.push(f64_type.const_float(*f).into());
-                                }
-                                ASTValue::Bool(b) => {
-                                    call_args.push(
-                                        bool_type.const_int(if *b { 1 } else { 0 }, false).into(),
-                                    );
-                                }
-                                ASTValue::Str(s) => {
-                                    let c_string = builder
-                                        .build_global_string_ptr(s, "arg_str")
-                                        .expect("global str failed");
-                                    call_args.push(c_string.as_pointer_value().into());
-                                }
-                                ASTValue::VarRef(var_name) => {
-                                    if let Some((var_ptr, var_type)) = variables.get(var_name) {
-                                        let val = match var_type {
-                                            VarType::Int32 => builder
-                                                .build_load(i32_type, *var_ptr, var_name)
-                                                .expect("load failed"),
-                                            VarType::Int64 => builder
-                                                .build_load(i64_type, *var_ptr, var_name)
-                                                .expect("load failed"),
-                                            VarType::Float32 => builder
-                                                .build_load(f32_type, *var_ptr, var_name)
-                                                .expect("load failed"),
-                                            VarType::Float64 => builder
-                                                .build_load(f64_type, *var_ptr, var_name)
-                                                .expect("load failed"),
-                                            VarType::Bool => builder
-                                                .build_load(bool_type, *var_ptr, var_name)
-                                                .expect("load failed"),
-                                            VarType::Str => builder
-                                                .build_load(i8_ptr, *var_ptr, var_name)
-                                                .expect("load failed"),
-                                        };
-                                        call_args.push(val.into());
-                                    } else {
-                                        panic!("Unknown variable in function call: {}", var_name);
-                                    }
-                                }
-                                ASTValue::FuncCall { .. } => {
-                                    // Nested function calls - you'd need to implement this recursively
-                                    panic!("Nested function calls not yet implemented");
-                                }
+                    ASTValue::Float64(f) => {
+                        println!("Declare var: {} {} = {}", ty, name, f);
+
+                        // Determine the correct float type based on the declared type
+                        let (var_alloca, var_type) = match ty.as_str() {
+                            "f32" => {
+                                let alloca =
+                                    self.cg.builder.build_alloca(self.cg.f32_type, name).expect("alloca failed");
+                                let float_val = self.cg.f32_type.const_float(*f);
+                                self.cg.builder.build_store(alloca, float_val);
+                                (alloca, VarType::Float32)
                             }
-                        }
+                            "f64" => {
+                                let alloca =
+                                    self.cg.builder.build_alloca(self.cg.f64_type, name).expect("alloca failed");
+                                let float_val = self.cg.f64_type.const_float(*f);
+                                self.cg.builder.build_store(alloca, float_val);
+                                (alloca, VarType::Float64)
+                            }
+                            _ => {
+                                // Default to f64 for Float64 literals
+                                let alloca =
+                                    self.cg.builder.build_alloca(self.cg.f64_type, name).expect("alloca failed");
+                                let float_val = self.cg.f64_type.const_float(*f);
+                                self.cg.builder.build_store(alloca, float_val);
+                                (alloca, VarType::Float64)
+                            }
+                        };
 
-                        // Make the function call
-                        let call_result = builder
-                            .build_call(*function, &call_args, "func_call")
-                            .expect("function call failed");
-
-                        // Store the result in a new variable
-                        // For now, assuming functions return i32 (you might need to track return types)
-                        let var_alloca =
-                            builder.build_alloca(i32_type, name).expect("alloca failed");
-                        if let Some(result_value) = call_result.try_as_basic_value().left() {
-                            builder.build_store(var_alloca, result_value);
-                            variables.insert(name.clone(), (var_alloca, VarType::Int32));
-                        } else {
-                            // Function returned void, store a default value
-                            let default_val = i32_type.const_int(0, false);
-                            builder.build_store(var_alloca, default_val);
-                            variables.insert(name.clone(), (var_alloca, VarType::Int32));
-                        }
-                    } else {
-                        panic!("Unknown function: {}", func_name);
+                        self.declare(name.clone(), (var_alloca, var_type));
                     }
-                }
-            },
-
-            AST::If {
-                condition,
-                then_body,
-                elif_branches,
-                else_body,
-            } => {
-                // Compile the main condition
-                let i1_cond = compile_condition(builder, context, variables,_functions, &condition)
-                    .expect("compile_condition failed");
-
-                let then_bb = context.append_basic_block(*current_function, "then");
-                let merge_bb = context.append_basic_block(*current_function, "merge");
-
-                // Start with the else/elif chain
-                let mut current_else_bb = context.append_basic_block(*current_function, "else");
-                let mut else_block_exists = else_body.is_some() || !elif_branches.is_empty();
-
-                builder.build_conditional_branch(i1_cond, then_bb, current_else_bb);
-
-                // THEN block
-                builder.position_at_end(then_bb);
-
-                compile_statements(
-                    then_body,
-                    builder,
-                    context,
-                    puts_fn,
-                    current_function, // <-- insert this
-                    variables,
-                    _functions,
-                );
-
-                builder.build_unconditional_branch(merge_bb);
-
-                // ELIF blocks
-
-                // ELIF blocks
-                for (elif_cond, elif_body) in elif_branches {
-                    builder.position_at_end(current_else_bb);
-
-                    let elif_bb = context.append_basic_block(*current_function, "elif");
-                    let next_else_bb = context.append_basic_block(*current_function, "else_next");
-
-                    let i1_elif = compile_condition(builder, context, variables, _functions,elif_cond)
-                        .expect("compile_condition failed");
-                    builder.build_conditional_branch(i1_elif, elif_bb, next_else_bb);
-
-                    // ELIF block
-                    builder.position_at_end(elif_bb);
-                    compile_statements(
-                        elif_body, // ✅ use elif_body here
-                        builder,
-                        context,
-                        puts_fn,
-                        current_function,
-                        variables,
-                        _functions,
-                    );
-
-                    builder.build_unconditional_branch(merge_bb);
-
-                    current_else_bb = next_else_bb; // Move to next else
-                }
-
-                // ELSE block
-                builder.position_at_end(current_else_bb);
-                if let Some(else_body) = else_body {
-                    compile_statements(
-                        else_body, // ✅ use else_body here
-                        builder,
-                        context,
-                        puts_fn,
-                        current_function,
-                        variables,
-                        _functions,
-                    );
-                }
-                builder.build_unconditional_branch(merge_bb);
+                    ASTValue::Bool(b) => {
+                        println!("Declare var: {} {} = {}", ty, name, b);
 
-                // Merge block
-                builder.position_at_end(merge_bb);
-            }
+                        let var_alloca = self.cg.builder
+                            .build_alloca(self.cg.bool_type, name)
+                            .expect("alloca failed");
+                        let bool_val = self.cg.bool_type.const_int(if *b { 1 } else { 0 }, false);
+                        self.cg.builder.build_store(var_alloca, bool_val);
 
-            AST::Return(value) => {
-                match value {
-                    ASTValue::LessThan(lhs, rhs) => {
-                        let cmp_val = compile_comparison(
-                            builder,
-                            variables,
-                            _functions,
-                            lhs,
-                            rhs,
-                            IntPredicate::SLT,
-                            i32_type,
-                        );
-                        builder.build_return(Some(&cmp_val));
-                    }
-                    ASTValue::GreaterThan(lhs, rhs) => {
-                        let cmp_val = compile_comparison(
-                            builder,
-                            variables,
-                            _functions,
-                            lhs,
-                            rhs,
-                            IntPredicate::SGT,
-                            i32_type,
-                        );
-                        builder.build_return(Some(&cmp_val));
-                    }
-                    ASTValue::LessEqual(lhs, rhs) => {
-                        let cmp_val = compile_comparison(
-                            builder,
-                            variables,
-                            _functions,
-                            lhs,
-                            rhs,
-                            IntPredicate::SLE,
-                            i32_type,
-                        );
-                        builder.build_return(Some(&cmp_val));
-                    }
-                    ASTValue::GreaterEqual(lhs, rhs) => {
-                        let cmp_val = compile_comparison(
-                            builder,
-                            variables,
-                            _functions,
-                            lhs,
-                            rhs,
-                            IntPredicate::SGE,
-                            i32_type,
-                        );
-                        builder.build_return(Some(&cmp_val));
-                    }
-                    ASTValue::Equal(lhs, rhs) => {
-                        let cmp_val = compile_comparison(
-                            builder,
-                            variables,
-                            _functions,
-                            lhs,
-                            rhs,
-                            IntPredicate::EQ,
-                            i32_type,
-                        );
-                        builder.build_return(Some(&cmp_val));
-                    }
-                    ASTValue::EqualEqual(lhs, rhs) => {
-                        let cmp_val = compile_comparison(
-                            builder,
-                            variables,
-                            _functions,
-                            lhs,
-                            rhs,
-                            IntPredicate::EQ,
-                            i32_type,
-                        );
-                        builder.build_return(Some(&cmp_val));
-                    }
-                    ASTValue::NotEqual(lhs, rhs) => {
-                        let cmp_val = compile_comparison(
-                            builder,
-                            variables,
-                            _functions,
-                            lhs,
-                            rhs,
-                            IntPredicate::NE,
-                            i32_type,
-                        );
-                        builder.build_return(Some(&cmp_val));
-                    }
-                    ASTValue::Int(n) => {
-                        let ret_val = i32_type.const_int(*n as u64, false);
-                        builder.build_return(Some(&ret_val));
-                    }
-                    ASTValue::Int64(n) => {
-                        let ret_val = i64_type.const_int(*n as u64, false);
-                        builder.build_return(Some(&ret_val));
-                    }
-                    ASTValue::Float32(f) => {
-                        let ret_val = f32_type.const_float(*f as f64);
-                        builder.build_return(Some(&ret_val));
-                    }
-                    ASTValue::Float64(f) => {
-                        let ret_val = f64_type.const_float(*f);
-                        builder.build_return(Some(&ret_val));
+                        self.declare(name.clone(), (var_alloca, VarType::Bool));
                     }
-                    ASTValue::Bool(b) => {
-                        let ret_val = bool_type.const_int(if *b { 1 } else { 0 }, false);
-                        builder.build_return(Some(&ret_val));
+                    ASTValue::Null => panic!("null has no value to compile"),
+                    ASTValue::Char(c) => {
+                        let var_alloca = self.cg.builder
+                            .build_alloca(self.cg.i8_type, name)
+                            .expect("alloca failed");
+                        let char_val = self.cg.i8_type.const_int(*c as u64, false);
+                        self.cg.builder.build_store(var_alloca, char_val);
+
+                        self.declare(name.clone(), (var_alloca, VarType::Char));
                     }
-                    ASTValue::Str(_) => {
-                        // For string returns, you'd need to handle this based on your ABI
-                        // This is a simplified version
-                        let ret_val = i32_type.const_int(0, false);
-                        builder.build_return(Some(&ret_val));
-                    }
-                    ASTValue::VarRef(name) => {
-                        if let Some((var_ptr, var_type)) = variables.get(name) {
-                            match var_type {
-                                VarType::Int32 => {
-                                    let val = builder
-                                        .build_load(i32_type, *var_ptr, name)
-                                        .expect("load failed");
-                                    builder.build_return(Some(&val.into_int_value()));
+                    ASTValue::VarRef(ref_name) => {
+                        // Handle variable assignment from another variable
+                        if let Some((src_ptr, src_type)) = self.lookup(ref_name) {
+                            let var_alloca = match src_type {
+                                VarType::Int32 | VarType::UInt32 => {
+                                    self.cg.builder.build_alloca(self.cg.i32_type, name).expect("alloca failed")
                                 }
-                                VarType::Int64 => {
-                                    let val = builder
-                                        .build_load(i64_type, *var_ptr, name)
-                                        .expect("load failed");
-                                    builder.build_return(Some(&val.into_int_value()));
+                                VarType::Int64 | VarType::UInt64 => {
+                                    self.cg.builder.build_alloca(self.cg.i64_type, name).expect("alloca failed")
                                 }
                                 VarType::Float32 => {
-                                    let val = builder
-                                        .build_load(f32_type, *var_ptr, name)
-                                        .expect("load failed");
-                                    builder.build_return(Some(&val.into_float_value()));
+                                    self.cg.builder.build_alloca(self.cg.f32_type, name).expect("alloca failed")
                                 }
                                 VarType::Float64 => {
-                                    let val = builder
-                                        .build_load(f64_type, *var_ptr, name)
-                                        .expect("load failed");
-                                    builder.build_return(Some(&val.into_float_value()));
-                                }
-                                VarType::Bool => {
-                                    let val = builder
-                                        .build_load(bool_type, *var_ptr, name)
-                                        .expect("load failed");
-                                    builder.build_return(Some(&val.into_int_value()));
+                                    self.cg.builder.build_alloca(self.cg.f64_type, name).expect("alloca failed")
                                 }
+                                VarType::Bool => self.cg.builder
+                                    .build_alloca(self.cg.bool_type, name)
+                                    .expect("alloca failed"),
+                                VarType::Char | VarType::UInt8 => self.cg.builder
+                                    .build_alloca(self.cg.i8_type, name)
+                                    .expect("alloca failed"),
                                 VarType::Str => {
-                                    // Again, simplified - string return handling depends on your ABI
-                                    let ret_val = i32_type.const_int(0, false);
-                                    builder.build_return(Some(&ret_val));
+                                    self.cg.builder.build_alloca(self.cg.i8_ptr, name).expect("alloca failed")
                                 }
-                            }
+                            };
+
+                            let src_val = match src_type {
+                                VarType::Int32 | VarType::UInt32 => self.cg.builder
+                                    .build_load(self.cg.i32_type, *src_ptr, ref_name)
+                                    .expect("load failed"),
+                                VarType::Int64 | VarType::UInt64 => self.cg.builder
+                                    .build_load(self.cg.i64_type, *src_ptr, ref_name)
+                                    .expect("load failed"),
+                                VarType::Float32 => self.cg.builder
+                                    .build_load(self.cg.f32_type, *src_ptr, ref_name)
+                                    .expect("load failed"),
+                                VarType::Float64 => self.cg.builder
+                                    .build_load(self.cg.f64_type, *src_ptr, ref_name)
+                                    .expect("load failed"),
+                                VarType::Bool => self.cg.builder
+                                    .build_load(self.cg.bool_type, *src_ptr, ref_name)
+                                    .expect("load failed"),
+                                VarType::Char | VarType::UInt8 => self.cg.builder
+                                    .build_load(self.cg.i8_type, *src_ptr, ref_name)
+                                    .expect("load failed"),
+                                VarType::Str => self.cg.builder
+                                    .build_load(self.cg.i8_ptr, *src_ptr, ref_name)
+                                    .expect("load failed"),
+                            };
+
+                            self.cg.builder.build_store(var_alloca, src_val);
+                            self.declare(name.clone(), (var_alloca, *src_type));
                         } else {
-                            panic!("Unknown variable in return: {}", name);
+                            panic!("Unknown variable reference: {}", ref_name);
                         }
                     }
                     ASTValue::FuncCall {
                         name: func_name,
                         args,
                     } => {
-                        // Handle function call in return statement
-                        if let Some(function) = _functions.get(func_name) {
+                        // Handle function call assignment
+                        if let Some(function) = self.functions.get(func_name) {
+                            // Prepare arguments for function call
                             let mut call_args = Vec::new();
 
                             for arg in args {
                                 match arg {
-                                    ASTValue::EqualEqual(lhs, rhs) => {
-                                        let cmp_val = compile_comparison(
-                                            builder,
-                                            variables,
-                                            _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::EQ,
-                                            i32_type,
-                                        );
-                                        call_args.push(cmp_val.into());
-                                    }
                                     ASTValue::LessThan(lhs, rhs) => {
-                                        let cmp_val = compile_comparison(
-                                            builder,
-                                            variables,
-                                            _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::SLT,
-                                            i32_type,
+                                        call_args.push(
+                                            self.compile_comparison(
+                                                lhs,
+                                                rhs,
+                                                IntPredicate::SLT,
+                                            )
+                                            .into(),
                                         );
-                                        call_args.push(cmp_val.into());
                                     }
                                     ASTValue::GreaterThan(lhs, rhs) => {
-                                        let cmp_val = compile_comparison(
-                                            builder,
-                                            variables,
-                                            _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::SGT,
-                                            i32_type,
+                                        call_args.push(
+                                            self.compile_comparison(
+                                                lhs,
+                                                rhs,
+                                                IntPredicate::SGT,
+                                            )
+                                            .into(),
                                         );
-                                        call_args.push(cmp_val.into());
                                     }
                                     ASTValue::LessEqual(lhs, rhs) => {
-                                        let cmp_val = compile_comparison(
-                                            builder,
-                                            variables,
-                                            _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::SLE,
-                                            i32_type,
+                                        call_args.push(
+                                            self.compile_comparison(
+                                                lhs,
+                                                rhs,
+                                                IntPredicate::SLE,
+                                            )
+                                            .into(),
                                         );
-                                        call_args.push(cmp_val.into());
                                     }
                                     ASTValue::GreaterEqual(lhs, rhs) => {
-                                        let cmp_val = compile_comparison(
-                                            builder,
-                                            variables,
-                                            _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::SGE,
-                                            i32_type,
+                                        call_args.push(
+                                            self.compile_comparison(
+                                                lhs,
+                                                rhs,
+                                                IntPredicate::SGE,
+                                            )
+                                            .into(),
                                         );
-                                        call_args.push(cmp_val.into());
                                     }
                                     ASTValue::Equal(lhs, rhs) => {
-                                        let cmp_val = compile_comparison(
-                                            builder,
-                                            variables,
-                                            _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::EQ,
-                                            i32_type,
+                                        call_args.push(
+                                            self.compile_comparison(
+                                                lhs,
+                                                rhs,
+                                                IntPredicate::EQ,
+                                            )
+                                            .into(),
+                                        );
+                                    }
+                                    ASTValue::EqualEqual(lhs, rhs) => {
+                                        call_args.push(
+                                            self.compile_comparison(
+                                                lhs,
+                                                rhs,
+                                                IntPredicate::EQ,
+                                            )
+                                            .into(),
                                         );
-                                        call_args.push(cmp_val.into());
                                     }
                                     ASTValue::NotEqual(lhs, rhs) => {
-                                        let cmp_val = compile_comparison(
-                                            builder,
-                                            variables,
-                                            _functions,
-                                            lhs,
-                                            rhs,
-                                            IntPredicate::NE,
-                                            i32_type,
+                                        call_args.push(
+                                            self.compile_comparison(
+                                                lhs,
+                                                rhs,
+                                                IntPredicate::NE,
+                                            )
+                                            .into(),
                                         );
-                                        call_args.push(cmp_val.into());
                                     }
+
                                     ASTValue::Int(n) => {
-                                        call_args.push(i32_type.const_int(*n as u64, false).into());
+                                        call_args.push(self.cg.i32_type.const_int(*n as u64, false).into());
                                     }
                                     ASTValue::Int64(n) => {
-                                        call_args.push(i64_type.const_int(*n as u64, false).into());
+                                        call_args.push(self.cg.i64_type.const_int(*n as u64, false).into());
                                     }
                                     ASTValue::Float32(f) => {
-                                        call_args.push(f32_type.const_float(*f as f64).into());
+                                        call_args.push(self.cg.f32_type.const_float(*f as f64).into());
                                     }
                                     ASTValue::Float64(f) => {
-                                        call_args.push(f64_type.const_float(*f).into());
+                                        call_args.push(self.cg.f64_type.const_float(*f).into());
                                     }
                                     ASTValue::Bool(b) => {
                                         call_args.push(
-                                            bool_type
-                                                .const_int(if *b { 1 } else { 0 }, false)
-                                                .into(),
+                                            self.cg.bool_type.const_int(if *b { 1 } else { 0 }, false).into(),
                                         );
                                     }
+                                    ASTValue::Null => panic!("null has no value to compile"),
+                                    ASTValue::Char(c) => {
+                                        call_args.push(self.cg.i8_type.const_int(*c as u64, false).into());
+                                    }
                                     ASTValue::Str(s) => {
-                                        let c_string = builder
-                                            .build_global_string_ptr(s, "ret_arg_str")
-                                            .expect("global str failed");
-                                        call_args.push(c_string.as_pointer_value().into());
+                                        let c_string = self.cg.const_str(s);
+                                        call_args.push(c_string.into());
                                     }
                                     ASTValue::VarRef(var_name) => {
-                                        if let Some((var_ptr, var_type)) = variables.get(var_name) {
+                                        if let Some((var_ptr, var_type)) = self.lookup(var_name) {
                                             let val = match var_type {
-                                                VarType::Int32 => builder
-                                                    .build_load(i32_type, *var_ptr, var_name)
+                                                VarType::Int32 | VarType::UInt32 => self.cg.builder
+                                                    .build_load(self.cg.i32_type, *var_ptr, var_name)
+                                                    .expect("load failed"),
+                                                VarType::Int64 | VarType::UInt64 => self.cg.builder
+                                                    .build_load(self.cg.i64_type, *var_ptr, var_name)
                                                     .expect("load failed"),
-                                                VarType::Int64 => builder
-                                                    .build_load(i64_type, *var_ptr, var_name)
+                                                VarType::Float32 => self.cg.builder
+                                                    .build_load(self.cg.f32_type, *var_ptr, var_name)
                                                     .expect("load failed"),
-                                                VarType::Float32 => builder
-                                                    .build_load(f32_type, *var_ptr, var_name)
+                                                VarType::Float64 => self.cg.builder
+                                                    .build_load(self.cg.f64_type, *var_ptr, var_name)
                                                     .expect("load failed"),
-                                                VarType::Float64 => builder
-                                                    .build_load(f64_type, *var_ptr, var_name)
+                                                VarType::Bool => self.cg.builder
+                                                    .build_load(self.cg.bool_type, *var_ptr, var_name)
                                                     .expect("load failed"),
-                                                VarType::Bool => builder
-                                                    .build_load(bool_type, *var_ptr, var_name)
+                                                VarType::Char | VarType::UInt8 => self.cg.builder
+                                                    .build_load(self.cg.i8_type, *var_ptr, var_name)
                                                     .expect("load failed"),
-                                                VarType::Str => builder
-                                                    .build_load(i8_ptr, *var_ptr, var_name)
+                                                VarType::Str => self.cg.builder
+                                                    .build_load(self.cg.i8_ptr, *var_ptr, var_name)
                                                     .expect("load failed"),
                                             };
                                             call_args.push(val.into());
                                         } else {
-                                            panic!(
-                                                "Unknown variable in function call: {}",
-                                                var_name
-                                            );
+                                            panic!("Unknown variable in function call: {}", var_name);
                                         }
                                     }
-                                    ASTValue::FuncCall { .. } => {
-                                        panic!(
-                                            "Nested function calls in return not yet implemented"
-                                        );
+                                    ASTValue::FuncCall { .. }
+                                    | ASTValue::UInt8(_)
+                                    | ASTValue::UInt32(_)
+                                    | ASTValue::UInt64(_)
+                                    | ASTValue::Add(..)
+                                    | ASTValue::Sub(..)
+                                    | ASTValue::Mul(..)
+                                    | ASTValue::Div(..)
+                                    | ASTValue::Rem(..)
+                                    | ASTValue::Neg(..)
+                                    | ASTValue::And(..)
+                                    | ASTValue::Or(..)
+                                    | ASTValue::Not(..) => {
+                                        let param = function
+                                            .get_nth_param(call_args.len() as u32)
+                                            .expect("too many arguments");
+                                        call_args.push(self.compile_arithmetic_as(arg, param.get_type()).into());
                                     }
                                 }
                             }
 
-                            let call_result = builder
-                                .build_call(*function, &call_args, "ret_func_call")
+                            // Make the function call
+                            let call_result = self.cg.builder
+                                .build_call(*function, &call_args, "func_call")
                                 .expect("function call failed");
-                            if let Some(result_value) = call_result.try_as_basic_value().left() {
-                                // The into_*_value() methods don't return Result, they panic on wrong type
-                                // So we need to handle this differently
-                                builder.build_return(Some(&result_value.into_int_value()));
+
+                            // Store the result in a new variable of the type the
+                            // function returns, so i64s and bools survive intact
+                            if let Some(mut result_value) = call_result.try_as_basic_value().left() {
+                                let mut var_type = self.cg.var_type(result_value.get_type());
+                                if self.is_unsigned(value) {
+                                    var_type = var_type.unsigned();
+                                }
+                                if let Some(declared) = Type::from_name(ty) {
+                                    result_value = self.convert(result_value, self.cg.type_of(&declared));
+                                    var_type = VarType::of(declared);
+                                }
+                                let var_alloca = self.cg.builder
+                                    .build_alloca(result_value.get_type(), name)
+                                    .expect("alloca failed");
+                                self.cg.builder.build_store(var_alloca, result_value);
+                                self.declare(name.clone(), (var_alloca, var_type));
                             } else {
-                                let ret_val = i32_type.const_int(0, false);
-                                builder.build_return(Some(&ret_val));
+                                let var_alloca =
+                                    self.cg.builder.build_alloca(self.cg.i32_type, name).expect("alloca failed");
+                                // Function returned void, store a default value
+                                let default_val = self.cg.i32_type.const_int(0, false);
+                                self.cg.builder.build_store(var_alloca, default_val);
+                                self.declare(name.clone(), (var_alloca, VarType::Int32));
                             }
                         } else {
-                            panic!("Unknown function in return: {}", func_name);
+                            panic!("Unknown function: {}", func_name);
                         }
                     }
-                }
-                return; // Exit early since we've returned
-            }
+                },
 
-            AST::NewLine => {
-                // Skip newlines
-            }
+                AST::If {
+                    condition,
+                    then_body,
+                    elif_branches,
+                    else_body,
+                } => {
+                    // Compile the main condition
+                    let i1_cond = self.compile_condition(&condition)
+                        .expect("compile_condition failed");
 
-            _ => {
-                println!("Unhandled statement: {:?}", stmt);
-            }
-        }
-    }
-}
+                    let then_bb = self.append_block("then");
+                    let merge_bb = self.append_block("merge");
 
-fn compile_value<'ctx>(
-    builder: &inkwell::builder::Builder<'ctx>,
-    variables: &HashMap<String, (PointerValue<'ctx>, VarType)>,
-    functions: &HashMap<String, FunctionValue<'ctx>>,
-    val: &ASTValue,
-) -> IntValue<'ctx> {
-    let context = builder.get_insert_block().unwrap().get_context();
-    
-    match val {
-        ASTValue::Int(n) => context.i32_type().const_int(*n as u64, false),
-        
-        ASTValue::Int64(n) => {
-            context.i32_type().const_int(*n as u64, false)
-        }
-        
-        ASTValue::Bool(b) => {
-            context.i32_type().const_int(if *b { 1 } else { 0 }, false)
-        }
+                    // Start with the else/elif chain
+                    let mut current_else_bb = self.append_block("else");
+                    let mut else_block_exists = else_body.is_some() || !elif_branches.is_empty();
 
-        // Handle comparison operations
-        ASTValue::LessThan(lhs, rhs) => {
-            let lhs_val = compile_value(builder, variables, functions, lhs);
-            let rhs_val = compile_value(builder, variables, functions, rhs);
-            builder
-                .build_int_compare(IntPredicate::SLT, lhs_val, rhs_val, "lt_cmp")
-                .expect("Failed to build less than comparison")
-        }
+                    self.cg.builder.build_conditional_branch(i1_cond, then_bb, current_else_bb);
 
-        ASTValue::GreaterThan(lhs, rhs) => {
-            let lhs_val = compile_value(builder, variables, functions, lhs);
-            let rhs_val = compile_value(builder, variables, functions, rhs);
-            builder
-                .build_int_compare(IntPredicate::SGT, lhs_val, rhs_val, "gt_cmp")
-                .expect("Failed to build greater than comparison")
-        }
+                    // THEN block
+                    self.position_at_end(then_bb);
 
-        ASTValue::LessEqual(lhs, rhs) => {
-            let lhs_val = compile_value(builder, variables, functions, lhs);
-            let rhs_val = compile_value(builder, variables, functions, rhs);
-            builder
-                .build_int_compare(IntPredicate::SLE, lhs_val, rhs_val, "le_cmp")
-                .expect("Failed to build less equal comparison")
-        }
+                    self.compile_block(then_body);
 
-        ASTValue::GreaterEqual(lhs, rhs) => {
-            let lhs_val = compile_value(builder, variables, functions, lhs);
-            let rhs_val = compile_value(builder, variables, functions, rhs);
-            builder
-                .build_int_compare(IntPredicate::SGE, lhs_val, rhs_val, "ge_cmp")
-                .expect("Failed to build greater equal comparison")
-        }
+                    // A body ending in `return` already has its terminator
+                    if !self.is_terminated() {
+                        self.cg.builder.build_unconditional_branch(merge_bb);
+                    }
 
-        ASTValue::Equal(lhs, rhs) | ASTValue::EqualEqual(lhs, rhs) => {
-            let lhs_val = compile_value(builder, variables, functions, lhs);
-            let rhs_val = compile_value(builder, variables, functions, rhs);
-            builder
-                .build_int_compare(IntPredicate::EQ, lhs_val, rhs_val, "eq_cmp")
-                .expect("Failed to build equal comparison")
-        }
+                    // ELIF blocks
+                    for (elif_cond, elif_body) in elif_branches {
+                        self.position_at_end(current_else_bb);
 
-        ASTValue::NotEqual(lhs, rhs) => {
-            let lhs_val = compile_value(builder, variables, functions, lhs);
-            let rhs_val = compile_value(builder, variables, functions, rhs);
-            builder
-                .build_int_compare(IntPredicate::NE, lhs_val, rhs_val, "ne_cmp")
-                .expect("Failed to build not equal comparison")
-        }
+                        let elif_bb = self.append_block("elif");
+                        let next_else_bb = self.append_block("else_next");
 
-        // Handle function calls
-        ASTValue::FuncCall { name, args } => {
-            if let Some(function) = functions.get(name) {
-                let mut call_args = Vec::new();
-                
-                for arg in args {
-                    let arg_val = compile_value(builder, variables, functions, arg);
-                    call_args.push(arg_val.into());
+                        let i1_elif = self.compile_condition(elif_cond)
+                            .expect("compile_condition failed");
+                        self.cg.builder.build_conditional_branch(i1_elif, elif_bb, next_else_bb);
+
+                        // ELIF block
+                        self.position_at_end(elif_bb);
+                        self.compile_block(elif_body);
+
+                        if !self.is_terminated() {
+                            self.cg.builder.build_unconditional_branch(merge_bb);
+                        }
+
+                        current_else_bb = next_else_bb; // Move to next else
+                    }
+
+                    // ELSE block
+                    self.position_at_end(current_else_bb);
+                    if let Some(else_body) = else_body {
+                        self.compile_block(else_body);
+                    }
+                    if !self.is_terminated() {
+                        self.cg.builder.build_unconditional_branch(merge_bb);
+                    }
+
+                    // Merge block
+                    self.position_at_end(merge_bb);
                 }
-                
-                let call_result = builder
-                    .build_call(*function, &call_args, "func_call_result")
-                    .expect("function call failed");
-                
-                if let Some(result_value) = call_result.try_as_basic_value().left() {
-                    result_value.into_int_value()
-                } else {
-                    // Function returned void, return 0
-                    context.i32_type().const_int(0, false)
+
+                AST::While { condition, body } => {
+                    let cond_bb = self.append_block("while_cond");
+                    let body_bb = self.append_block("while_body");
+                    let done_bb = self.append_block("while_done");
+                    self.cg.builder.build_unconditional_branch(cond_bb);
+
+                    self.position_at_end(cond_bb);
+                    let i1_cond = self.compile_condition(condition)
+                        .expect("compile_condition failed");
+                    self.cg.builder.build_conditional_branch(i1_cond, body_bb, done_bb);
+
+                    // Locals declared in the body get fresh allocas on every
+                    // pass, so the stack is rewound before going round again
+                    self.position_at_end(body_bb);
+                    let sp = self.save_stack();
+
+                    let targets = LoopTargets {
+                        continue_block: cond_bb,
+                        break_block: done_bb,
+                    };
+                    self.in_loop(targets, |this| this.compile_block(body));
+
+                    if !self.is_terminated() {
+                        self.restore_stack(sp);
+                        self.cg.builder.build_unconditional_branch(cond_bb);
+                    }
+
+                    self.position_at_end(done_bb);
                 }
-            } else {
-                panic!("Unknown function in compile_value: {}", name);
-            }
-        }
-        
-        ASTValue::VarRef(name) => {
-            let (ptr, var_type) = variables.get(name).expect("Unknown variable");
-            match var_type {
-                VarType::Int32 => builder
-                    .build_load(context.i32_type(), *ptr, name)
-                    .expect("Failed to load i32")
-                    .into_int_value(),
-                    
-                VarType::Int64 => {
-                    let i64_val = builder
-                        .build_load(context.i64_type(), *ptr, name)
-                        .expect("Failed to load i64")
+
+                AST::For {
+                    variable,
+                    start,
+                    end,
+                    body,
+                } => {
+                    // The counter has the wider of the bounds' types, and is
+                    // unsigned if either is
+                    let unsigned = self.is_unsigned(start) || self.is_unsigned(end);
+                    let first = self.compile_arithmetic(start).into_int_value();
+                    let last = self.compile_arithmetic(end).into_int_value();
+                    let ty = if first.get_type().get_bit_width() >= last.get_type().get_bit_width() {
+                        first.get_type()
+                    } else {
+                        last.get_type()
+                    };
+                    let first = self.cast(first.into(), ty.into(), unsigned).into_int_value();
+                    let last = self.cast(last.into(), ty.into(), unsigned).into_int_value();
+                    let counter = self.cg.builder
+                        .build_alloca(ty, variable)
+                        .expect("alloca failed");
+                    self.cg.builder.build_store(counter, first).expect("store failed");
+
+                    let cond_bb = self.append_block("for_cond");
+                    let body_bb = self.append_block("for_body");
+                    let step_bb = self.append_block("for_step");
+                    let done_bb = self.append_block("for_done");
+                    self.cg.builder.build_unconditional_branch(cond_bb);
+
+                    self.position_at_end(cond_bb);
+                    let current = self.cg.builder
+                        .build_load(ty, counter, "for_counter")
+                        .expect("build_load failed")
                         .into_int_value();
-                    builder
-                        .build_int_truncate(i64_val, context.i32_type(), "i64_to_i32")
-                        .expect("Failed to truncate i64 to i32")
-                }
-                
-                VarType::Float32 => {
-                    let f32_val = builder
-                        .build_load(context.f32_type(), *ptr, name)
-                        .expect("Failed to load f32")
-                        .into_float_value();
-                    builder
-                        .build_float_to_signed_int(f32_val, context.i32_type(), "f32_to_i32")
-                        .expect("Failed to convert f32 to i32")
-                }
-                
-                VarType::Float64 => {
-                    let f64_val = builder
-                        .build_load(context.f64_type(), *ptr, name)
-                        .expect("Failed to load f64")
-                        .into_float_value();
-                    builder
-                        .build_float_to_signed_int(f64_val, context.i32_type(), "f64_to_i32")
-                        .expect("Failed to convert f64 to i32")
-                }
-                
-                VarType::Bool => {
-                    let bool_val = builder
-                        .build_load(context.bool_type(), *ptr, name)
-                        .expect("Failed to load bool")
+                    let predicate = if unsigned { IntPredicate::ULT } else { IntPredicate::SLT };
+                    let more = self.cg.builder
+                        .build_int_compare(predicate, current, last, "for_more")
+                        .expect("build_int_compare failed");
+                    self.cg.builder.build_conditional_branch(more, body_bb, done_bb);
+
+                    // As for while, each pass gets its locals' allocas back
+                    self.position_at_end(body_bb);
+                    let sp = self.save_stack();
+                    self.scopes.push(Locals::new());
+                    let mut var_type = self.cg.var_type(ty.into());
+                    if unsigned {
+                        var_type = var_type.unsigned();
+                    }
+                    self.declare(variable.clone(), (counter, var_type));
+                    let targets = LoopTargets {
+                        continue_block: step_bb,
+                        break_block: done_bb,
+                    };
+                    self.in_loop(targets, |this| this.compile_block(body));
+                    self.scopes.pop();
+                    if !self.is_terminated() {
+                        self.restore_stack(sp);
+                        self.cg.builder.build_unconditional_branch(step_bb);
+                    }
+
+                    self.position_at_end(step_bb);
+                    let current = self.cg.builder
+                        .build_load(ty, counter, "for_counter")
+                        .expect("build_load failed")
                         .into_int_value();
-                    builder
-                        .build_int_z_extend(bool_val, context.i32_type(), "bool_to_i32")
-                        .expect("Failed to extend bool to i32")
+                    let next = self.cg.builder
+                        .build_int_add(current, ty.const_int(1, false), "for_next")
+                        .expect("build_int_add failed");
+                    self.cg.builder.build_store(counter, next).expect("store failed");
+                    self.cg.builder.build_unconditional_branch(cond_bb);
+
+                    self.position_at_end(done_bb);
+                }
+
+                AST::Return(value) => {
+                    // Whatever the value is, it's returned as the function's
+                    // return type: a literal takes the type it's returned as,
+                    // and a narrower variable or call result is widened
+                    let ret_type = self.function
+                        .get_type()
+                        .get_return_type()
+                        .expect("functions always return a value");
+                    let result = match value {
+                        // Self-recursive call in return position: mark it
+                        // `tail` so LLVM can turn the recursion into a loop
+                        ASTValue::FuncCall { name, args }
+                            if prelude::lookup(name).is_none()
+                                && self.method(name).is_none()
+                                && self.functions.get(name) == Some(&self.function) =>
+                        {
+                            let call_args: Vec<BasicMetadataValueEnum> = args
+                                .iter()
+                                .zip(self.function.get_params())
+                                .map(|(arg, param)| self.compile_arithmetic_as(arg, param.get_type()).into())
+                                .collect();
+                            let call = self.cg.builder
+                                .build_call(self.function, &call_args, "ret_func_call")
+                                .expect("function call failed");
+                            call.set_tail_call(true);
+                            call.try_as_basic_value()
+                                .left()
+                                .expect("functions always return a value")
+                        }
+                        _ => self.compile_arithmetic(value),
+                    };
+                    let result = self.convert(result, ret_type);
+                    self.cg.builder.build_return(Some(&result));
+                    return; // Exit early since we've returned
                 }
-                
-                VarType::Str => {
-                    context.i32_type().const_int(0, false)
+
+                _ => {
+                    println!("Unhandled statement: {:?}", stmt);
                 }
             }
         }
-        
-        ASTValue::Float32(f) => {
-            context.i32_type().const_int(*f as i64 as u64, false)
-        }
-        
-        ASTValue::Float64(f) => {
-            context.i32_type().const_int(*f as i64 as u64, false)
-        }
-        
-        ASTValue::Str(_) => {
-            context.i32_type().const_int(0, false)
-        }
-        
-        _ => {
-            panic!("compile_value: unsupported ASTValue type for integer conversion: {:?}", val);
-        }
     }
-}
 
+    // `let x = value` for a value compile_arithmetic can compile, taking its
+    // type unless the declaration names one
+    fn declare_value(&mut self, ty: &str, name: &str, value: &ASTValue) {
+        println!("Declare var: {} {} = {:?}", ty, name, value);
 
-// Helper function to build comparison variables
-fn build_comparison_var<'ctx>(
-    builder: &inkwell::builder::Builder<'ctx>,
-    variables: &mut HashMap<String, (PointerValue<'ctx>, VarType)>,
-    functions: &HashMap<String, FunctionValue<'ctx>>, // Add this parameter
-    ty: &str,
-    name: &str,
-    lhs: &ASTValue,
-    rhs: &ASTValue,
-    predicate: IntPredicate,
-    i32_type: IntType<'ctx>,
-) {
-    println!("Declare var: {} {} = {:?}", ty, name, predicate);
+        let mut result = self.compile_arithmetic(value);
+        let mut var_type = self.cg.var_type(result.get_type());
+        if self.is_unsigned(value) {
+            var_type = var_type.unsigned();
+        }
+        if let Some(declared) = Type::from_name(ty) {
+            result = self.convert(result, self.cg.type_of(&declared));
+            var_type = VarType::of(declared);
+        }
+        let alloca = self.cg.builder
+            .build_alloca(result.get_type(), name)
+            .expect("alloca failed");
+        self.cg.builder.build_store(alloca, result).expect("store failed");
+        self.declare(name.to_string(), (alloca, var_type));
+    }
 
-    let alloca = builder.build_alloca(i32_type, name).expect("alloca failed");
+    fn compile_basic_value(&self, val: &ASTValue) -> BasicValueEnum<'ctx> {
+        match val {
+            ASTValue::Int(n) => self.cg.i32_type.const_int(*n as u64, false).into(),
+            ASTValue::Int64(n) => self.cg.i64_type.const_int(*n as u64, false).into(),
+            ASTValue::VarRef(name) => {
+                let (ptr, var_type) = self.lookup(name).expect("Unknown variable");
+                match var_type {
+                    VarType::Int32 | VarType::UInt32 => self.cg.builder
+                        .build_load(self.cg.i32_type, *ptr, name)
+                        .expect("FAIL")
+                        .into(),
+                    VarType::Int64 | VarType::UInt64 => self.cg.builder
+                        .build_load(self.cg.i64_type, *ptr, name)
+                        .expect("FAIL")
+                        .into(),
+                    VarType::Str => self.cg.builder.build_load(self.cg.i8_ptr, *ptr, name).expect("FAIL").into(),
+                    _ => unimplemented!(),
+                }
+            }
+            ASTValue::Str(s) => {
+                self.cg.const_str(s).into()
+            }
+            // Add other ASTValue variants if needed
+            _ => todo!("compile_basic_value: unsupported value"),
+        }
+    }
 
-    // Pass functions to compile_value
-    let lhs_val = compile_value(builder, variables, functions, lhs);
-    let rhs_val = compile_value(builder, variables, functions, rhs);
+    // Arithmetic over literals, variables and calls (or just one of those),
+    // in the type the type checker gave it
+    pub fn compile_arithmetic(&self, val: &ASTValue) -> BasicValueEnum<'ctx> {
+        let (lhs_ast, rhs_ast) = match val {
+            ASTValue::Add(lhs, rhs)
+            | ASTValue::Sub(lhs, rhs)
+            | ASTValue::Mul(lhs, rhs)
+            | ASTValue::Div(lhs, rhs)
+            | ASTValue::Rem(lhs, rhs) => (lhs, rhs),
+            ASTValue::Neg(operand) => {
+                return match self.compile_arithmetic(operand) {
+                    BasicValueEnum::IntValue(v) => {
+                        self.cg.builder.build_int_neg(v, "neg").expect("build_int_neg failed").into()
+                    }
+                    BasicValueEnum::FloatValue(v) => {
+                        self.cg.builder.build_float_neg(v, "fneg").expect("build_float_neg failed").into()
+                    }
+                    other => panic!("negating a non-number: {:?}", other),
+                };
+            }
+            other => return self.compile_operand(other),
+        };
+        let lhs = self.compile_arithmetic(lhs_ast);
+        let rhs = self.compile_arithmetic(rhs_ast);
+
+        // An integer with a float is promoted to the float's type, so `/`
+        // is an integer division only when both sides are integers.
+        // Otherwise only a literal can differ in type from the other side,
+        // and it takes that side's type; the right one wins if both are
+        // literals, as in the type checker
+        let is_literal = |v: &ASTValue| {
+            matches!(v, ASTValue::Int(_) | ASTValue::Int64(_) | ASTValue::Float32(_) | ASTValue::Float64(_))
+        };
+        let (lhs, rhs) = if lhs.is_float_value() && rhs.is_int_value() {
+            (lhs, self.cast(rhs, lhs.get_type(), self.is_unsigned(rhs_ast)))
+        } else if lhs.is_int_value() && rhs.is_float_value() {
+            (self.cast(lhs, rhs.get_type(), self.is_unsigned(lhs_ast)), rhs)
+        } else if is_literal(rhs_ast) || !is_literal(lhs_ast) {
+            (lhs, self.convert(rhs, lhs.get_type()))
+        } else {
+            (self.convert(lhs, rhs.get_type()), rhs)
+        };
+
+        let builder = &self.cg.builder;
+        match (lhs, rhs) {
+            (BasicValueEnum::IntValue(l), BasicValueEnum::IntValue(r)) => {
+                if matches!(val, ASTValue::Div(..) | ASTValue::Rem(..)) {
+                    self.emit_division_check(r);
+                }
+                let unsigned = self.is_unsigned(val);
+                match val {
+                    ASTValue::Add(..) => builder.build_int_add(l, r, "add"),
+                    ASTValue::Sub(..) => builder.build_int_sub(l, r, "sub"),
+                    ASTValue::Mul(..) => builder.build_int_mul(l, r, "mul"),
+                    ASTValue::Div(..) if unsigned => builder.build_int_unsigned_div(l, r, "udiv"),
+                    ASTValue::Div(..) => builder.build_int_signed_div(l, r, "div"),
+                    _ if unsigned => builder.build_int_unsigned_rem(l, r, "urem"),
+                    _ => builder.build_int_signed_rem(l, r, "rem"),
+                }
+                .expect("integer arithmetic failed")
+                .into()
+            }
+            (BasicValueEnum::FloatValue(l), BasicValueEnum::FloatValue(r)) => match val {
+                ASTValue::Add(..) => builder.build_float_add(l, r, "fadd"),
+                ASTValue::Sub(..) => builder.build_float_sub(l, r, "fsub"),
+                ASTValue::Mul(..) => builder.build_float_mul(l, r, "fmul"),
+                ASTValue::Div(..) => builder.build_float_div(l, r, "fdiv"),
+                _ => builder.build_float_rem(l, r, "frem"),
+            }
+            .expect("float arithmetic failed")
+            .into(),
+            _ => panic!("arithmetic on mismatched operands: {:?}", val),
+        }
+    }
 
-    let cmp_val = builder
-        .build_int_compare(predicate, lhs_val, rhs_val, &format!("{}_cmp", name))
-        .expect("build_int_compare failed");
+    pub fn compile_arithmetic_as(&self, val: &ASTValue, ty: BasicTypeEnum<'ctx>) -> BasicValueEnum<'ctx> {
+        let value = self.compile_arithmetic(val);
+        self.cast(value, ty, self.is_unsigned(val))
+    }
 
-    builder.build_store(alloca, cmp_val);
-    variables.insert(name.to_string(), (alloca, VarType::Int32));
-}
-fn compile_basic_value<'ctx>(
-    builder: &inkwell::builder::Builder<'ctx>,
-    variables: &HashMap<String, (PointerValue<'ctx>, VarType)>,
-    val: &ASTValue,
-    i32_type: IntType<'ctx>,
-    i64_type: IntType<'ctx>,
-    i8_ptr: PointerType<'ctx>,
-) -> BasicValueEnum<'ctx> {
-    match val {
-        ASTValue::Int(n) => i32_type.const_int(*n as u64, false).into(),
-        ASTValue::Int64(n) => i64_type.const_int(*n as u64, false).into(),
-        ASTValue::VarRef(name) => {
-            let (ptr, var_type) = variables.get(name).expect("Unknown variable");
-            match var_type {
-                VarType::Int32 => builder
-                    .build_load(i32_type, *ptr, name)
-                    .expect("FAIL")
-                    .into(),
-                VarType::Int64 => builder
-                    .build_load(i64_type, *ptr, name)
-                    .expect("FAIL")
-                    .into(),
-                VarType::Str => builder.build_load(i8_ptr, *ptr, name).expect("FAIL").into(),
-                _ => unimplemented!(),
+    fn compile_operand(&self, val: &ASTValue) -> BasicValueEnum<'ctx> {
+        match val {
+            ASTValue::Float32(f) => self.cg.f32_type.const_float(*f as f64).into(),
+            ASTValue::Float64(f) => self.cg.f64_type.const_float(*f).into(),
+            ASTValue::Bool(b) => self.cg.bool_type.const_int(*b as u64, false).into(),
+            ASTValue::Char(c) => self.cg.i8_type.const_int(*c as u64, false).into(),
+            ASTValue::UInt8(n) => self.cg.i8_type.const_int(*n as u64, false).into(),
+            ASTValue::UInt32(n) => self.cg.i32_type.const_int(*n as u64, false).into(),
+            ASTValue::UInt64(n) => self.cg.i64_type.const_int(*n, false).into(),
+            ASTValue::LessThan(..)
+            | ASTValue::GreaterThan(..)
+            | ASTValue::Equal(..)
+            | ASTValue::EqualEqual(..)
+            | ASTValue::NotEqual(..)
+            | ASTValue::LessEqual(..)
+            | ASTValue::GreaterEqual(..)
+            | ASTValue::And(..)
+            | ASTValue::Or(..)
+            | ASTValue::Not(..) => self
+                .compile_condition(val)
+                .expect("compile_condition failed")
+                .into(),
+            ASTValue::VarRef(name) => {
+                let (ptr, var_type) = *self
+                    .lookup(name)
+                    .unwrap_or_else(|| panic!("Unknown variable {}", name));
+                self.cg.builder
+                    .build_load(self.cg.basic_type(var_type), ptr, name)
+                    .expect("load failed")
             }
+            ASTValue::FuncCall { name, args } => {
+                if let Some(builtin) = prelude::lookup(name) {
+                    let result = (builtin.lower)(self, args);
+                    if builtin.noreturn {
+                        self.cg.builder.build_unreachable().expect("build_unreachable failed");
+                    }
+                    return result.unwrap_or_else(|| self.cg.i32_type.const_zero().into());
+                }
+                // A method takes the variable it's called on first, as `self`
+                let (function, receiver) = match self.method(name) {
+                    Some((function, receiver)) => (function, Some(ASTValue::VarRef(receiver.to_string()))),
+                    None => {
+                        let function = self.functions.get(name);
+                        (*function.unwrap_or_else(|| panic!("Unknown function {}", name)), None)
+                    }
+                };
+                let call_args: Vec<BasicMetadataValueEnum> = receiver
+                    .iter()
+                    .chain(args)
+                    .zip(function.get_params())
+                    .map(|(arg, param)| self.compile_arithmetic_as(arg, param.get_type()).into())
+                    .collect();
+                self.cg.builder
+                    .build_call(function, &call_args, "call")
+                    .expect("function call failed")
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap_or_else(|| self.cg.i32_type.const_zero().into())
+            }
+            other => self.compile_basic_value(other),
         }
-        ASTValue::Str(s) => {
-            let c_string = builder.build_global_string_ptr(s, &format!("{}_str", s));
-            c_string.expect("Fail").as_pointer_value().into()
-        }
-        // Add other ASTValue variants if needed
-        _ => todo!("compile_basic_value: unsupported value"),
     }
-}
 
-fn compile_comparison<'ctx>(
-    builder: &inkwell::builder::Builder<'ctx>,
-    variables: &HashMap<String, (PointerValue<'ctx>, VarType)>,
-   functions: &HashMap<String, FunctionValue<'ctx>>,
-    lhs: &ASTValue,
-    rhs: &ASTValue,
-    predicate: IntPredicate,
-    i32_type: IntType<'ctx>,
-) -> IntValue<'ctx> {
-    let lhs_val = compile_value(builder, variables, functions, lhs);
-    let rhs_val = compile_value(builder, variables, functions, rhs);
-
-    builder
-        .build_int_compare(predicate, lhs_val, rhs_val, "cmp")
-        .expect("build_int_compare failed")
-}
+    // `value` as type `ty`: integers are sign-extended or truncated, floats
+    // widened or rounded, and either converted to the other if need be
+    pub fn convert(&self, value: BasicValueEnum<'ctx>, ty: BasicTypeEnum<'ctx>) -> BasicValueEnum<'ctx> {
+        self.cast(value, ty, false)
+    }
 
-fn compile_if<'ctx>(
-    builder: &inkwell::builder::Builder<'ctx>,
-    context: &'ctx inkwell::context::Context,
-    function: inkwell::values::FunctionValue<'ctx>,
-    puts_fn: &inkwell::values::FunctionValue<'ctx>,
-    variables: &mut HashMap<String, (inkwell::values::PointerValue<'ctx>, VarType)>,
-    _functions: &HashMap<String, inkwell::values::FunctionValue<'ctx>>,
-    condition: &ASTValue,
-    then_body: &[AST],
-    elif_branches: &[(ASTValue, Vec<AST>)],
-    else_body: &Option<Vec<AST>>,
-) {
-    // Compile the condition into an i1 (boolean)
-    let i1_cond = compile_condition(builder, context, variables, _functions,condition)
-        .expect("Failed to compile condition");
-
-    let then_bb = context.append_basic_block(function, "then");
-    let merge_bb = context.append_basic_block(function, "merge");
-
-    // Branch to then or else (or merge if no else)
-    match else_body {
-        Some(else_stmts) => {
-            let else_bb = context.append_basic_block(function, "else");
-            builder
-                .build_conditional_branch(i1_cond, then_bb, else_bb)
-                .expect("Failed to build conditional branch");
-
-            // Then block
-            builder.position_at_end(then_bb);
-            compile_statements(
-                then_body, builder, context, &function, puts_fn, variables, _functions,
-            );
-            builder
-                .build_unconditional_branch(merge_bb)
-                .expect("Failed to branch to merge");
-
-            // Else block
-            builder.position_at_end(else_bb);
-            compile_statements(
-                else_stmts, // Use else_stmts instead of else_body
-                builder, context, &function, // Use &function instead of current_function
-                puts_fn, variables, _functions,
-            );
-            builder
-                .build_unconditional_branch(merge_bb)
-                .expect("Failed to branch to merge");
+    // convert, except that an `unsigned` integer is zero-extended and read
+    // as unsigned when it becomes a float
+    fn cast(&self, value: BasicValueEnum<'ctx>, ty: BasicTypeEnum<'ctx>, unsigned: bool) -> BasicValueEnum<'ctx> {
+        if value.get_type() == ty {
+            return value;
         }
-        None => {
-            builder
-                .build_conditional_branch(i1_cond, then_bb, merge_bb)
-                .expect("Failed to build conditional branch");
-
-            builder.position_at_end(then_bb);
-            compile_statements(
-                then_body, builder, context,
-                &function, // Use &function instead of current_function
-                puts_fn, variables, _functions,
-            );
-            builder
-                .build_unconditional_branch(merge_bb)
-                .expect("Failed to branch to merge");
+        let builder = &self.cg.builder;
+        match (value, ty) {
+            (BasicValueEnum::IntValue(v), BasicTypeEnum::IntType(t)) => {
+                builder.build_int_cast_sign_flag(v, t, !unsigned, "int_cast").expect("int cast failed").into()
+            }
+            (BasicValueEnum::FloatValue(v), BasicTypeEnum::FloatType(t)) => {
+                builder.build_float_cast(v, t, "float_cast").expect("float cast failed").into()
+            }
+            (BasicValueEnum::IntValue(v), BasicTypeEnum::FloatType(t)) if unsigned => builder
+                .build_unsigned_int_to_float(v, t, "uint_to_float")
+                .expect("int to float failed")
+                .into(),
+            (BasicValueEnum::IntValue(v), BasicTypeEnum::FloatType(t)) => builder
+                .build_signed_int_to_float(v, t, "int_to_float")
+                .expect("int to float failed")
+                .into(),
+            (BasicValueEnum::FloatValue(v), BasicTypeEnum::IntType(t)) => builder
+                .build_float_to_signed_int(v, t, "float_to_int")
+                .expect("float to int failed")
+                .into(),
+            _ => value,
         }
     }
 
-    // Handle elif branches
-    for (elif_cond, elif_body) in elif_branches {
-        let elif_bb = context.append_basic_block(function, "elif_then");
-        let next_bb = context.append_basic_block(function, "elif_next");
-
-        let elif_i1 = compile_condition(builder, context, variables,_functions, elif_cond)
-            .expect("Failed to compile elif condition");
-
-        builder
-            .build_conditional_branch(elif_i1, elif_bb, next_bb)
-            .expect("Failed to build elif conditional branch");
+    // Integer division by zero is undefined behavior in LLVM, so it exits
+    // with a message instead, the way a stack overflow does
+    fn emit_division_check(&self, divisor: IntValue<'ctx>) {
+        if divisor.get_zero_extended_constant().is_some_and(|n| n != 0) {
+            return;
+        }
+        let is_zero = self.cg.builder
+            .build_int_compare(IntPredicate::EQ, divisor, divisor.get_type().const_zero(), "is_zero")
+            .expect("build_int_compare failed");
+        let zero_bb = self.append_block("division_by_zero");
+        let ok_bb = self.append_block("divide");
+        self.cg.builder
+            .build_conditional_branch(is_zero, zero_bb, ok_bb)
+            .expect("build_cond_br failed");
+
+        self.position_at_end(zero_bb);
+        let message = "division by zero\n";
+        self.cg.builder
+            .build_call(
+                self.cg.write_fn,
+                &[
+                    self.cg.i32_type.const_int(2, false).into(),
+                    self.cg.const_str(message).into(),
+                    self.cg.i64_type.const_int(message.len() as u64, false).into(),
+                ],
+                "report_division_by_zero",
+            )
+            .expect("build_call failed");
+        self.cg.builder
+            .build_call(self.cg.exit_fn, &[self.cg.i32_type.const_int(1, false).into()], "")
+            .expect("build_call failed");
+        self.cg.builder.build_unreachable().expect("build_unreachable failed");
+
+        self.position_at_end(ok_bb);
+    }
 
-        builder.position_at_end(elif_bb);
-        compile_statements(
-            elif_body, builder, context,
-            &function, // Use &function instead of current_function
-            puts_fn, variables, _functions,
-        );
-        builder
-            .build_unconditional_branch(merge_bb)
-            .expect("Failed to branch to merge");
+    fn compile_comparison(
+        &self,
+        lhs: &ASTValue,
+        rhs: &ASTValue,
+        predicate: IntPredicate,
+    ) -> IntValue<'ctx> {
+        let (lhs_unsigned, rhs_unsigned) = (self.is_unsigned(lhs), self.is_unsigned(rhs));
+        let lhs = self.compile_arithmetic(lhs);
+        let rhs = self.compile_arithmetic(rhs);
+
+        // Both sides are compared in the wider of their types; a float on
+        // either side makes it a float comparison. The type checker only
+        // lets an unsigned integer meet a signed one that's a literal, which
+        // takes the unsigned type, so one unsigned side makes it unsigned.
+        // Bools only meet bools, and order as 0 and 1, so they're unsigned too.
+        let is_bool = |value: BasicValueEnum<'ctx>| {
+            matches!(value, BasicValueEnum::IntValue(v) if v.get_type().get_bit_width() == 1)
+        };
+        let (lhs_unsigned, rhs_unsigned) = (lhs_unsigned || is_bool(lhs), rhs_unsigned || is_bool(rhs));
+        let ty: BasicTypeEnum<'ctx> = match (lhs, rhs) {
+            (BasicValueEnum::IntValue(l), BasicValueEnum::IntValue(r)) => {
+                if l.get_type().get_bit_width() >= r.get_type().get_bit_width() {
+                    l.get_type().into()
+                } else {
+                    r.get_type().into()
+                }
+            }
+            _ if lhs.get_type() == self.cg.f64_type.into() || rhs.get_type() == self.cg.f64_type.into() => {
+                self.cg.f64_type.into()
+            }
+            _ => self.cg.f32_type.into(),
+        };
+        match (self.cast(lhs, ty, lhs_unsigned), self.cast(rhs, ty, rhs_unsigned)) {
+            (BasicValueEnum::IntValue(l), BasicValueEnum::IntValue(r)) => {
+                let predicate = match predicate {
+                    IntPredicate::SLT if lhs_unsigned || rhs_unsigned => IntPredicate::ULT,
+                    IntPredicate::SGT if lhs_unsigned || rhs_unsigned => IntPredicate::UGT,
+                    IntPredicate::SLE if lhs_unsigned || rhs_unsigned => IntPredicate::ULE,
+                    IntPredicate::SGE if lhs_unsigned || rhs_unsigned => IntPredicate::UGE,
+                    other => other,
+                };
+                self.cg.builder
+                    .build_int_compare(predicate, l, r, "cmp")
+                    .expect("build_int_compare failed")
+            }
+            (BasicValueEnum::FloatValue(l), BasicValueEnum::FloatValue(r)) => {
+                // As in C, `<`, `>`, `<=`, `>=` and `==` are ordered: all
+                // false when either side is NaN, so NaN isn't even equal to
+                // itself. `!=` is unordered and holds instead, which makes
+                // `x != x` the test for NaN. consteval folds the same way.
+                let predicate = match predicate {
+                    IntPredicate::SLT => FloatPredicate::OLT,
+                    IntPredicate::SGT => FloatPredicate::OGT,
+                    IntPredicate::SLE => FloatPredicate::OLE,
+                    IntPredicate::SGE => FloatPredicate::OGE,
+                    IntPredicate::NE => FloatPredicate::UNE,
+                    _ => FloatPredicate::OEQ,
+                };
+                self.cg.builder
+                    .build_float_compare(predicate, l, r, "fcmp")
+                    .expect("build_float_compare failed")
+            }
+            (l, r) => panic!("cannot compare {:?} with {:?}", l, r),
+        }
+    }
 
-        builder.position_at_end(next_bb);
+    fn compile_condition(&self, cond: &ASTValue) -> Result<IntValue<'ctx>, String> {
+        match cond {
+            ASTValue::LessThan(lhs, rhs) => Ok(self.compile_comparison(
+                lhs,
+                rhs,
+                IntPredicate::SLT,
+            )),
+            ASTValue::GreaterThan(lhs, rhs) => Ok(self.compile_comparison(
+                lhs,
+                rhs,
+                IntPredicate::SGT,
+            )),
+            ASTValue::LessEqual(lhs, rhs) => Ok(self.compile_comparison(
+                lhs,
+                rhs,
+                IntPredicate::SLE,
+            )),
+            ASTValue::GreaterEqual(lhs, rhs) => Ok(self.compile_comparison(
+                lhs,
+                rhs,
+                IntPredicate::SGE,
+            )),
+            ASTValue::Equal(lhs, rhs) | ASTValue::EqualEqual(lhs, rhs) => Ok(self.compile_comparison(
+                lhs,
+                rhs,
+                IntPredicate::EQ,
+            )),
+            ASTValue::NotEqual(lhs, rhs) => Ok(self.compile_comparison(
+                lhs,
+                rhs,
+                IntPredicate::NE,
+            )),
+            ASTValue::Bool(b) => Ok(self.cg.bool_type.const_int(*b as u64, false)),
+            ASTValue::And(lhs, rhs) => self.compile_logical(lhs, rhs, true),
+            ASTValue::Or(lhs, rhs) => self.compile_logical(lhs, rhs, false),
+            ASTValue::Not(operand) => {
+                let value = self.compile_condition(operand)?;
+                Ok(self.cg.builder.build_not(value, "not").expect("build_not failed"))
+            }
+            // A bool variable or call as an operand of `&&`, `||` or `!`
+            ASTValue::VarRef(_) | ASTValue::FuncCall { .. } => Ok(self
+                .compile_arithmetic_as(cond, self.cg.bool_type.into())
+                .into_int_value()),
+            _ => Err("Unsupported condition in compile_condition".to_string()),
+        }
     }
 
-    builder.position_at_end(merge_bb);
-}
-fn compile_condition<'ctx>(
-    builder: &inkwell::builder::Builder<'ctx>,
-    context: &'ctx inkwell::context::Context,
-    variables: &HashMap<String, (PointerValue<'ctx>, VarType)>,
-_functions: &HashMap<String, FunctionValue<'ctx>>,
-    cond: &ASTValue,
-) -> Result<IntValue<'ctx>, String> {
-    match cond {
-        ASTValue::LessThan(lhs, rhs) => Ok(compile_comparison(
-            builder,
-            variables,
-            _functions,
-            lhs,
-            rhs,
-            IntPredicate::SLT,
-            context.i32_type(),
-        )),
-        ASTValue::GreaterThan(lhs, rhs) => Ok(compile_comparison(
-            builder,
-            variables,
-            _functions,
-            lhs,
-            rhs,
-            IntPredicate::SGT,
-            context.i32_type(),
-        )),
-        ASTValue::LessEqual(lhs, rhs) => Ok(compile_comparison(
-            builder,
-            variables,
-            _functions,
-            lhs,
-            rhs,
-            IntPredicate::SLE,
-            context.i32_type(),
-        )),
-        ASTValue::GreaterEqual(lhs, rhs) => Ok(compile_comparison(
-            builder,
-            variables,
-            _functions,
-            lhs,
-            rhs,
-            IntPredicate::SGE,
-            context.i32_type(),
-        )),
-        ASTValue::Equal(lhs, rhs) | ASTValue::EqualEqual(lhs, rhs) => Ok(compile_comparison(
-            builder,
-            variables,
-            _functions,
-            lhs,
-            rhs,
-            IntPredicate::EQ,
-            context.i32_type(),
-        )),
-        ASTValue::NotEqual(lhs, rhs) => Ok(compile_comparison(
-            builder,
-            variables,
-            _functions,
-            lhs,
-            rhs,
-            IntPredicate::NE,
-            context.i32_type(),
-        )),
-        ASTValue::Bool(b) => Ok(context.bool_type().const_int(*b as u64, false)),
-        _ => Err("Unsupported condition in compile_condition".to_string()),
+    // `lhs && rhs` if `and`, else `lhs || rhs`. The right side gets its own
+    // block and only runs when the left side doesn't already decide it.
+    fn compile_logical(&self, lhs: &ASTValue, rhs: &ASTValue, and: bool) -> Result<IntValue<'ctx>, String> {
+        let lhs_val = self.compile_condition(lhs)?;
+        let lhs_end = self.cg.builder.get_insert_block().expect("builder is not positioned");
+        let rhs_bb = self.append_block(if and { "and_rhs" } else { "or_rhs" });
+        let done_bb = self.append_block(if and { "and_done" } else { "or_done" });
+        let (then_bb, else_bb) = if and { (rhs_bb, done_bb) } else { (done_bb, rhs_bb) };
+        self.cg.builder
+            .build_conditional_branch(lhs_val, then_bb, else_bb)
+            .expect("build_cond_br failed");
+
+        self.position_at_end(rhs_bb);
+        let rhs_val = self.compile_condition(rhs)?;
+        let rhs_end = self.cg.builder.get_insert_block().expect("builder is not positioned");
+        self.cg.builder.build_unconditional_branch(done_bb).expect("build_br failed");
+
+        // Skipping the right side means the result is the left side's value
+        self.position_at_end(done_bb);
+        let result = self.cg.builder
+            .build_phi(self.cg.bool_type, if and { "and" } else { "or" })
+            .expect("build_phi failed");
+        result.add_incoming(&[
+            (&self.cg.bool_type.const_int(!and as u64, false), lhs_end),
+            (&rhs_val, rhs_end),
+        ]);
+        Ok(result.as_basic_value().into_int_value())
     }
 }
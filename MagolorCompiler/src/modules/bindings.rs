@@ -0,0 +1,85 @@
+use crate::modules::interface::ModuleInterface;
+use crate::modules::types::Type;
+
+// Rust can't use these even as raw identifiers
+const NOT_RAW: [&str; 4] = ["self", "Self", "super", "crate"];
+
+const RUST_KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "box", "break", "const", "continue", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+    "pub", "ref", "return", "static", "struct", "trait", "true", "try", "type", "unsafe", "use",
+    "where", "while", "yield",
+];
+
+// An `extern "C"` block declaring every function `interface` exports, for
+// `--emit rust-bindings`. Functions come out in name order, like the .magi
+// file, so the bindings only change when the interface does.
+pub fn rust(interface: &ModuleInterface) -> String {
+    let mut out = format!(
+        "// Bindings for the Magolor module `{module}`, generated by magolor; do not edit.\n\
+         // Link the module's object or static library (`--emit staticlib` and\n\
+         // `cargo:rustc-link-lib=static={module}` from a build script). Build it\n\
+         // with --no-main so its init() runs when the program starts.\n\n",
+        module = interface.module
+    );
+
+    let uses_c_char = |ty: Type| matches!(ty, Type::Str | Type::Char);
+    let needs_c_char = interface
+        .functions
+        .values()
+        .any(|sig| uses_c_char(sig.ret) || sig.params.iter().any(|(_, ty)| uses_c_char(*ty)));
+    if needs_c_char {
+        // Strings are NUL-terminated and owned by the Magolor side
+        out.push_str("use std::ffi::c_char;\n\n");
+    }
+
+    out.push_str("unsafe extern \"C\" {\n");
+    for (name, sig) in &interface.functions {
+        let params: Vec<String> = sig
+            .params
+            .iter()
+            .map(|(param, ty)| format!("{}: {}", identifier(param).0, rust_type(*ty)))
+            .collect();
+        // void functions return a 0 that nobody needs to see
+        let ret = match sig.ret {
+            Type::Void => String::new(),
+            ty => format!(" -> {}", rust_type(ty)),
+        };
+
+        let (ident, renamed) = identifier(name);
+        if renamed {
+            out.push_str(&format!("    #[link_name = \"{}\"]\n", name));
+        }
+        out.push_str(&format!("    pub fn {}({}){};\n", ident, params.join(", "), ret));
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn rust_type(ty: Type) -> &'static str {
+    match ty {
+        Type::I32 => "i32",
+        Type::I64 => "i64",
+        Type::U8 => "u8",
+        Type::U32 => "u32",
+        Type::U64 => "u64",
+        Type::F32 => "f32",
+        Type::F64 => "f64",
+        Type::Bool => "bool",
+        Type::Char => "c_char",
+        Type::Str => "*const c_char",
+        Type::Void => "()",
+    }
+}
+
+// `name` as a Rust identifier, and whether it had to be renamed rather than
+// just escaped
+fn identifier(name: &str) -> (String, bool) {
+    if NOT_RAW.contains(&name) {
+        (format!("{}_", name), true)
+    } else if RUST_KEYWORDS.contains(&name) {
+        (format!("r#{}", name), false)
+    } else {
+        (name.to_string(), false)
+    }
+}
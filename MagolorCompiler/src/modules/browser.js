@@ -0,0 +1,40 @@
+// Runs a Magolor program built for wasm32-unknown-unknown in a browser page.
+//
+//   import { run } from "./hello.js";
+//   run("hello.wasm");
+//
+// Output is split into lines and handed to `print` (console.log by default).
+export async function run(url, print = console.log) {
+  const decoder = new TextDecoder();
+  let memory;
+  let pending = "";
+
+  const imports = {
+    env: {
+      magolor_print(ptr, len) {
+        pending += decoder.decode(new Uint8Array(memory.buffer, ptr, len));
+        const lines = pending.split("\n");
+        pending = lines.pop();
+        lines.forEach((line) => print(line));
+      },
+      // time.now(): whole seconds since the Unix epoch
+      magolor_time() {
+        return BigInt(Math.floor(Date.now() / 1000));
+      },
+      // random.next(): 64 random bits
+      magolor_random() {
+        return crypto.getRandomValues(new BigInt64Array(1))[0];
+      },
+    },
+  };
+
+  const { instance } = await WebAssembly.instantiateStreaming(fetch(url), imports);
+  memory = instance.exports.memory;
+  try {
+    // Runs each module's init(), which _start would do outside a browser
+    instance.exports.__wasm_call_ctors?.();
+    return instance.exports.main();
+  } finally {
+    if (pending) print(pending);
+  }
+}
@@ -0,0 +1,28 @@
+use crate::modules::codegen::Target;
+use crate::modules::parser::{AST, ASTValue};
+use crate::modules::visitor::{AstFolder, fold_value_children};
+
+// `compiler.version()` and `compiler.target()` are known before the program
+// runs, so they're replaced by string literals ahead of type checking and
+// the rest of the pipeline never sees them
+pub fn fold_compiler_info(ast: Vec<AST>, target: Target) -> Vec<AST> {
+    CompilerInfo { target }.fold_program(ast)
+}
+
+struct CompilerInfo {
+    target: Target,
+}
+
+impl AstFolder for CompilerInfo {
+    fn fold_value(&mut self, value: ASTValue) -> ASTValue {
+        match value {
+            ASTValue::FuncCall { name, args } if args.is_empty() => match name.as_str() {
+                "compiler.version" => ASTValue::Str(env!("CARGO_PKG_VERSION").to_string()),
+                "compiler.target" => ASTValue::Str(self.target.resolved_triple().to_string()),
+                // Anything else is reported as an unknown function
+                _ => ASTValue::FuncCall { name, args },
+            },
+            other => fold_value_children(self, other),
+        }
+    }
+}
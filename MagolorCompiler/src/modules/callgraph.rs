@@ -0,0 +1,64 @@
+use crate::modules::parser::AST;
+use crate::modules::treeshake;
+use std::collections::{BTreeSet, HashMap};
+
+// The program's calls as a Graphviz digraph, for `--emit callgraph`.
+// Functions tree shaking will drop are drawn dashed, imported ones as
+// ellipses labelled with their module, and #[inline] hints are shown on
+// the function they're attached to. Nodes follow source order and edges are
+// sorted, so the output only changes when the program does.
+pub fn to_dot(ast: &[AST], roots: &[&str], imported: &HashMap<String, String>) -> String {
+    let reachable = treeshake::reachable(ast, roots);
+
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    let mut external = BTreeSet::new();
+    for node in ast {
+        let AST::FuncDef {
+            name,
+            body,
+            attributes,
+            ..
+        } = node
+        else {
+            continue;
+        };
+
+        let mut label = name.clone();
+        for attr in attributes.iter().filter(|attr| attr.name == "inline") {
+            label.push_str(&match attr.args.first() {
+                Some(arg) => format!("\\n#[inline({})]", arg),
+                None => "\\n#[inline]".to_string(),
+            });
+        }
+        let unused = reachable.as_ref().is_some_and(|set| !set.contains(name));
+        let style = if unused { ", style=dashed, color=gray" } else { "" };
+        nodes.push(format!("    \"{}\" [label=\"{}\"{}];", name, label, style));
+
+        let callees: BTreeSet<String> = treeshake::callees(body).into_iter().collect();
+        for callee in callees {
+            if let Some(module) = imported.get(&callee) {
+                external.insert((callee.clone(), module.clone()));
+            }
+            edges.push(format!("    \"{}\" -> \"{}\";", name, callee));
+        }
+    }
+
+    let mut dot = String::from("digraph callgraph {\n    node [shape=box];\n");
+    for node in nodes {
+        dot.push_str(&node);
+        dot.push('\n');
+    }
+    for (name, module) in external {
+        dot.push_str(&format!(
+            "    \"{}\" [shape=ellipse, label=\"{}\\n(from {})\"];\n",
+            name, name, module
+        ));
+    }
+    for edge in edges {
+        dot.push_str(&edge);
+        dot.push('\n');
+    }
+    dot.push_str("}\n");
+    dot
+}
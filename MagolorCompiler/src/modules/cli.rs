@@ -0,0 +1,549 @@
+use crate::modules::IR;
+use crate::modules::compiler::Stage;
+use crate::modules::tokenizer::KeywordCase;
+use crate::modules::codegen::{
+    CodegenOptions, HOST_TRIPLE, Sanitizer, StaticLink, Target, Toolchain,
+};
+use clap::error::ErrorKind;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::fmt;
+use std::path::Path;
+
+// Everything the driver needs to know about one compilation
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    pub input: String,
+    pub output: Option<String>,
+    pub compile_db: Option<String>,
+    // Also write the JS loader for browser builds
+    pub emit_js_glue: bool,
+    // Also write the call graph as <output>.dot
+    pub emit_call_graph: bool,
+    // Also write Rust declarations of the exported functions as <output>.rs
+    pub emit_rust_bindings: bool,
+    // Stages to print the program after, for debugging the compiler
+    pub dump_after: Vec<Stage>,
+    // Reject programs that reach outside their own source
+    pub sandbox: bool,
+    // Don't make the prelude's modules available without `use`
+    pub no_prelude: bool,
+    pub keyword_case: KeywordCase,
+    pub codegen: CodegenOptions,
+    // Raw argv, kept so the compilation database can replay the invocation
+    pub arguments: Vec<String>,
+}
+
+// Arguments that parse but can't be used together, or point at nothing.
+// The driver exits with a usage status for these rather than the one for
+// programs that fail to compile.
+#[derive(Debug)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+// What `--print` reports, for build systems interrogating the compiler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrintRequest {
+    // Every name --target accepts
+    TargetList,
+    // Triple of the --target (the host's by default)
+    TargetTriple,
+    // Installation prefix of the compiler
+    Sysroot,
+    // Directories `use` searches for module interfaces
+    SearchPaths,
+    // The command that links programs for the --target
+    Linker,
+}
+
+impl PrintRequest {
+    pub const NAMES: [&str; 5] = ["target-list", "target-triple", "sysroot", "search-paths", "linker"];
+
+    pub fn from_name(name: &str) -> Option<PrintRequest> {
+        match name {
+            "target-list" => Some(PrintRequest::TargetList),
+            "target-triple" => Some(PrintRequest::TargetTriple),
+            "sysroot" => Some(PrintRequest::Sysroot),
+            "search-paths" => Some(PrintRequest::SearchPaths),
+            "linker" => Some(PrintRequest::Linker),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CliCommand {
+    // One entry per input file, compiled in order
+    Compile(Vec<CompileOptions>),
+    // Answer --print queries instead of compiling; the input, if any, is
+    // what search paths are reported for
+    Print {
+        requests: Vec<PrintRequest>,
+        input: Option<String>,
+        codegen: CodegenOptions,
+    },
+    // List the definition and every reference of a symbol
+    Refs { input: String, symbol: String },
+    // Rename the symbol at line:col, rewriting the file in place
+    Rename {
+        input: String,
+        line: usize,
+        col: usize,
+        new_name: String,
+    },
+}
+
+fn build_cli() -> Command {
+    Command::new("magolor")
+        .about("Compiler for the Magolor language")
+        .version(env!("CARGO_PKG_VERSION"))
+        .long_version(long_version())
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .arg(
+            Arg::new("input")
+                .value_name("SOURCE")
+                .help("Source files to compile; @FILE reads more arguments from FILE")
+                .num_args(1..)
+                .required_unless_present("print"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Write the generated LLVM IR to FILE instead of stderr"),
+        )
+        .arg(
+            Arg::new("print")
+                .long("print")
+                .value_name("INFO")
+                .help("Print compiler information instead of compiling")
+                .value_delimiter(',')
+                .value_parser(PrintRequest::NAMES)
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("compile-db")
+                .long("compile-db")
+                .value_name("FILE")
+                .help("Record this compilation in a compile_commands.json style database"),
+        )
+        .arg(
+            Arg::new("target")
+                .long("target")
+                .value_name("TARGET")
+                .help("Generate code for TARGET (native, wasm32-wasi, wasm32-unknown-unknown)")
+                .value_parser(parse_target),
+        )
+        .arg(
+            Arg::new("emit")
+                .long("emit")
+                .value_name("KIND")
+                .help("Outputs to write besides the IR: js-glue writes a browser loader next to it, callgraph a Graphviz .dot file, staticlib builds a static library (implies --no-main), rust-bindings an extern \"C\" Rust module for it")
+                .value_delimiter(',')
+                .value_parser(["llvm-ir", "js-glue", "callgraph", "staticlib", "rust-bindings"])
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("dump-after")
+                .long("dump-after")
+                .value_name("STAGE")
+                .help("Print the program to stderr after the given pipeline stages")
+                .value_delimiter(',')
+                .value_parser(Stage::NAMES)
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("opt-level")
+                .short('O')
+                .value_name("LEVEL")
+                .help("Optimization level clang builds the program at")
+                .value_parser(["0", "1", "2", "3", "s", "z"]),
+        )
+        .arg(
+            Arg::new("unroll-loops")
+                .long("unroll-loops")
+                .help("Unroll loops (needs -O1 or higher)")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("no-unroll-loops"),
+        )
+        .arg(
+            Arg::new("no-unroll-loops")
+                .long("no-unroll-loops")
+                .help("Never unroll loops, even at -O2 and above")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("sanitize")
+                .long("sanitize")
+                .value_name("LIST")
+                .help("Instrument for the given sanitizers (address, undefined)")
+                .value_delimiter(',')
+                .value_parser(parse_sanitizer)
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("profile-generate")
+                .long("profile-generate")
+                .value_name("DIR")
+                .help("Instrument the program to write execution profiles (to DIR if given)")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("")
+                .conflicts_with("profile-use"),
+        )
+        .arg(
+            Arg::new("profile-use")
+                .long("profile-use")
+                .value_name("FILE")
+                .help("Optimize using a merged .profdata file from instrumented runs"),
+        )
+        .arg(
+            Arg::new("coverage")
+                .long("coverage")
+                .help("Instrument the program to record line coverage for llvm-cov gcov")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("codesign")
+                .long("codesign")
+                .value_name("IDENTITY")
+                .help("Codesign the program after linking (macOS; ad hoc unless IDENTITY is given)")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("-"),
+        )
+        .arg(
+            Arg::new("static")
+                .long("static")
+                .help("Link a fully static program, against musl when it's installed")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("sandbox")
+                .long("sandbox")
+                .help("Compile untrusted code: only built-in modules can be imported, and not process")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-prelude")
+                .long("no-prelude")
+                .help("Don't import console, assert, strings, math, process, time and random implicitly; `use` the ones you need")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("keyword-case")
+                .long("keyword-case")
+                .value_name("MODE")
+                .help("How keywords may be capitalized: lowercase (the default) or insensitive, which also accepts `Fn`, `RETURN` and the like")
+                .value_parser(parse_keyword_case),
+        )
+        .arg(
+            Arg::new("no-main")
+                .long("no-main")
+                .help("Compile a library for C or Rust to link against its `pub` functions; main is not allowed")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["static", "codesign"]),
+        )
+        .subcommand(
+            Command::new("refs")
+                .about("Show where a function or variable is defined and used")
+                .arg(
+                    Arg::new("symbol")
+                        .value_name("SYMBOL")
+                        .help("Function name, or function::variable for a local")
+                        .required(true),
+                )
+                .arg(Arg::new("input").value_name("SOURCE").required(true)),
+        )
+        .subcommand(
+            Command::new("rename")
+                .about("Rename a function or variable and every reference to it")
+                .arg(Arg::new("input").value_name("SOURCE").required(true))
+                .arg(
+                    Arg::new("position")
+                        .value_name("LINE:COL")
+                        .help("Position of any occurrence of the symbol")
+                        .value_parser(parse_position)
+                        .required(true),
+                )
+                .arg(Arg::new("new-name").value_name("NEWNAME").required(true)),
+        )
+}
+
+// What --version prints after the program name; -V keeps to the number
+fn long_version() -> &'static str {
+    let text = format!(
+        "{} ({})\nhost: {}\nLLVM version: {}",
+        env!("CARGO_PKG_VERSION"),
+        env!("MAGOLOR_GIT_HASH"),
+        HOST_TRIPLE,
+        IR::llvm_version()
+    );
+    // clap only takes static strings here; this runs once per invocation
+    Box::leak(text.into_boxed_str())
+}
+
+fn parse_position(text: &str) -> Result<(usize, usize), String> {
+    let (line, col) = text
+        .split_once(':')
+        .ok_or_else(|| format!("expected LINE:COL, got '{}'", text))?;
+    let line = line.parse().map_err(|_| format!("invalid line number '{}'", line))?;
+    let col = col.parse().map_err(|_| format!("invalid column number '{}'", col))?;
+    Ok((line, col))
+}
+
+fn parse_sanitizer(text: &str) -> Result<Sanitizer, String> {
+    Sanitizer::from_name(text)
+        .ok_or_else(|| format!("unknown sanitizer '{}' (expected address or undefined)", text))
+}
+
+fn parse_keyword_case(text: &str) -> Result<KeywordCase, String> {
+    KeywordCase::from_name(text)
+        .ok_or_else(|| format!("unknown keyword case '{}' (expected lowercase or insensitive)", text))
+}
+
+fn parse_target(text: &str) -> Result<Target, String> {
+    Target::from_name(text).ok_or_else(|| {
+        let names: Vec<&str> = Target::ALL.iter().map(Target::name).collect();
+        let (last, rest) = names.split_last().expect("there are targets");
+        format!("unknown target '{}' (expected {} or {})", text, rest.join(", "), last)
+    })
+}
+
+fn emits(matches: &ArgMatches, kind: &str) -> bool {
+    matches
+        .get_many::<String>("emit")
+        .into_iter()
+        .flatten()
+        .any(|k| k == kind)
+}
+
+fn string_arg(matches: &ArgMatches, name: &str) -> Option<String> {
+    matches.get_one::<String>(name).cloned()
+}
+
+// Arguments nest through at most this many response files, which stops a
+// file that (indirectly) names itself
+const MAX_RESPONSE_FILE_DEPTH: usize = 16;
+
+// Replaces every `@FILE` argument with the arguments listed in FILE, so
+// generated build scripts aren't bound by the OS command line length
+fn expand_response_files(args: Vec<String>, depth: usize) -> Result<Vec<String>, String> {
+    let mut expanded = Vec::new();
+    for arg in args {
+        let Some(path) = arg.strip_prefix('@').filter(|path| !path.is_empty()) else {
+            expanded.push(arg);
+            continue;
+        };
+        if depth == MAX_RESPONSE_FILE_DEPTH {
+            return Err(format!("response files nested too deeply at '{}'", path));
+        }
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read response file '{}': {}", path, e))?;
+        expanded.extend(expand_response_files(split_response_file(&text), depth + 1)?);
+    }
+    Ok(expanded)
+}
+
+// Splits a response file the way GCC does: arguments are separated by
+// whitespace (including newlines), quotes group, and a backslash escapes
+// the next character except inside single quotes
+fn split_response_file(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', Some('\'')) => current.get_or_insert_default().push(c),
+            ('\\', _) => {
+                let arg = current.get_or_insert_default();
+                if let Some(escaped) = chars.next() {
+                    arg.push(escaped);
+                }
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (c, Some(_)) => current.get_or_insert_default().push(c),
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                current.get_or_insert_default();
+            }
+            (c, None) if c.is_whitespace() => args.extend(current.take()),
+            (c, None) => current.get_or_insert_default().push(c),
+        }
+    }
+    args.extend(current);
+    args
+}
+
+pub fn parse_args(args: Vec<String>) -> CliCommand {
+    let mut cli = build_cli();
+    let args = match expand_response_files(args, 0) {
+        Ok(args) => args,
+        Err(e) => cli.error(ErrorKind::Io, e).exit(),
+    };
+    let matches = cli.try_get_matches_from_mut(&args).unwrap_or_else(|e| e.exit());
+
+    match matches.subcommand() {
+        Some(("refs", sub)) => CliCommand::Refs {
+            input: string_arg(sub, "input").unwrap(),
+            symbol: string_arg(sub, "symbol").unwrap(),
+        },
+        Some(("rename", sub)) => {
+            let (line, col) = *sub.get_one::<(usize, usize)>("position").unwrap();
+            CliCommand::Rename {
+                input: string_arg(sub, "input").unwrap(),
+                line,
+                col,
+                new_name: string_arg(sub, "new-name").unwrap(),
+            }
+        }
+        _ if matches.contains_id("print") => CliCommand::Print {
+            requests: matches
+                .get_many::<String>("print")
+                .into_iter()
+                .flatten()
+                .filter_map(|name| PrintRequest::from_name(name))
+                .collect(),
+            input: string_arg(&matches, "input"),
+            codegen: codegen_options(&matches),
+        },
+        _ => {
+            let inputs: Vec<String> = matches
+                .get_many::<String>("input")
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect();
+            let output = string_arg(&matches, "output");
+            if inputs.len() > 1 && output.is_some() {
+                cli.error(
+                    ErrorKind::ArgumentConflict,
+                    "--output can't be used with more than one input",
+                )
+                .exit();
+            }
+
+            let options = CompileOptions {
+                input: String::new(),
+                output,
+                compile_db: string_arg(&matches, "compile-db"),
+                emit_js_glue: emits(&matches, "js-glue"),
+                emit_call_graph: emits(&matches, "callgraph"),
+                emit_rust_bindings: emits(&matches, "rust-bindings"),
+                dump_after: matches
+                    .get_many::<String>("dump-after")
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|name| Stage::from_name(name))
+                    .collect(),
+                sandbox: matches.get_flag("sandbox"),
+                no_prelude: matches.get_flag("no-prelude"),
+                keyword_case: matches.get_one::<KeywordCase>("keyword-case").copied().unwrap_or_default(),
+                codegen: codegen_options(&matches),
+                arguments: Vec::new(),
+            };
+            let batch = inputs
+                .iter()
+                .map(|input| CompileOptions {
+                    input: input.clone(),
+                    // Each file's database entry replays just that file
+                    arguments: args
+                        .iter()
+                        .filter(|arg| *arg == input || !inputs.contains(arg))
+                        .cloned()
+                        .collect(),
+                    ..options.clone()
+                })
+                .collect();
+            CliCommand::Compile(batch)
+        }
+    }
+}
+
+// Checks clap can't express: combinations that depend on the target and
+// the detected toolchain
+pub fn check_options(options: &CompileOptions) -> Result<(), UsageError> {
+    let codegen = &options.codegen;
+    let usage = |message: String| Err(UsageError(message));
+
+    // The sanitizer runtimes are only built for native targets
+    if codegen.target != Target::Native && !codegen.sanitizers.is_empty() {
+        return usage(format!(
+            "sanitizers are not supported for target {}",
+            codegen.target.name()
+        ));
+    }
+
+    if codegen.codesign.is_some()
+        && (codegen.target != Target::Native || codegen.toolchain != Toolchain::Apple)
+    {
+        return usage("--codesign is only supported for native macOS builds".to_string());
+    }
+
+    if codegen.static_link.is_some() && codegen.target == Target::Native {
+        // Apple ships no static libSystem and MSVC's static CRT needs more
+        // than a flag; the sanitizer runtimes only come as shared libraries
+        if matches!(codegen.toolchain, Toolchain::Apple | Toolchain::Msvc) {
+            return usage("--static is not supported with this toolchain".to_string());
+        }
+        if !codegen.sanitizers.is_empty() {
+            return usage("--static cannot be combined with --sanitize".to_string());
+        }
+    }
+
+    if codegen.staticlib && (codegen.static_link.is_some() || codegen.codesign.is_some()) {
+        return usage("--static and --codesign apply to programs, not --emit staticlib".to_string());
+    }
+
+    if options.emit_js_glue && codegen.target != Target::Wasm32Unknown {
+        return usage("--emit js-glue requires --target wasm32-unknown-unknown".to_string());
+    }
+
+    if let Some(profile) = &codegen.profile_use {
+        if !Path::new(profile).is_file() {
+            return usage(format!("profile '{}' does not exist", profile));
+        }
+    }
+
+    Ok(())
+}
+
+fn codegen_options(matches: &ArgMatches) -> CodegenOptions {
+    let mut sanitizers = Vec::new();
+    for sanitizer in matches.get_many::<Sanitizer>("sanitize").into_iter().flatten() {
+        if !sanitizers.contains(sanitizer) {
+            sanitizers.push(*sanitizer);
+        }
+    }
+
+    CodegenOptions {
+        target: matches.get_one::<Target>("target").copied().unwrap_or_default(),
+        toolchain: Toolchain::detect(),
+        sanitizers,
+        profile_generate: string_arg(matches, "profile-generate"),
+        profile_use: string_arg(matches, "profile-use"),
+        coverage: matches.get_flag("coverage"),
+        codesign: string_arg(matches, "codesign"),
+        static_link: matches.get_flag("static").then(StaticLink::detect),
+        opt_level: string_arg(matches, "opt-level"),
+        unroll_loops: if matches.get_flag("unroll-loops") {
+            Some(true)
+        } else if matches.get_flag("no-unroll-loops") {
+            Some(false)
+        } else {
+            None
+        },
+        no_main: matches.get_flag("no-main") || emits(matches, "staticlib"),
+        staticlib: emits(matches, "staticlib"),
+    }
+}
@@ -0,0 +1,351 @@
+use std::path::{Path, PathBuf};
+
+// Knobs that change what the backend emits, as opposed to what it accepts.
+// The driver fills these in from the command line; library users set them
+// through Compiler::set_codegen_options.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sanitizer {
+    Address,
+    Undefined,
+}
+
+impl Sanitizer {
+    pub fn from_name(name: &str) -> Option<Sanitizer> {
+        match name {
+            "address" => Some(Sanitizer::Address),
+            "undefined" => Some(Sanitizer::Undefined),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Undefined => "undefined",
+        }
+    }
+}
+
+// Loader for wasm32-unknown-unknown builds: supplies env.magolor_print and
+// calls main. Written out by `--emit js-glue`.
+pub const JS_GLUE: &str = include_str!("browser.js");
+
+// The machine this compiler was built for, which native builds run on
+pub const HOST_TRIPLE: &str = env!("MAGOLOR_HOST");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    // Whatever clang builds for by default, with libc as the runtime
+    #[default]
+    Native,
+    // WebAssembly with WASI; the runtime talks to WASI imports directly
+    Wasm32Wasi,
+    // Bare WebAssembly for a browser page, printing through a JS import
+    Wasm32Unknown,
+}
+
+impl Target {
+    pub const ALL: [Target; 3] = [Target::Native, Target::Wasm32Wasi, Target::Wasm32Unknown];
+
+    pub fn from_name(name: &str) -> Option<Target> {
+        match name {
+            "native" => Some(Target::Native),
+            "wasm32-wasi" | "wasm32-wasip1" => Some(Target::Wasm32Wasi),
+            "wasm32-unknown-unknown" => Some(Target::Wasm32Unknown),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Target::Native => "native",
+            Target::Wasm32Wasi => "wasm32-wasi",
+            Target::Wasm32Unknown => "wasm32-unknown-unknown",
+        }
+    }
+
+    // How deep the stack may get before the stack check stops the program
+    // with "stack overflow", leaving headroom for libc and the reporting
+    // code below what the main thread gets: usually 8 MiB natively, and the
+    // 64 KiB wasm-ld reserves unless told otherwise
+    pub fn stack_limit(&self) -> u64 {
+        match self {
+            Target::Native => 7 * 1024 * 1024,
+            Target::Wasm32Wasi | Target::Wasm32Unknown => 48 * 1024,
+        }
+    }
+
+    // Triple stamped on the module; None leaves it to clang's default
+    pub fn triple(&self) -> Option<&'static str> {
+        match self {
+            Target::Native => None,
+            Target::Wasm32Wasi => Some("wasm32-unknown-wasi"),
+            Target::Wasm32Unknown => Some("wasm32-unknown-unknown"),
+        }
+    }
+
+    // Triple the program ends up running on, for reporting
+    pub fn resolved_triple(&self) -> &'static str {
+        self.triple().unwrap_or(HOST_TRIPLE)
+    }
+}
+
+// Which C toolchain clang hands the final link to for native builds. The
+// Windows ones disagree on target triple, linker flag syntax and file
+// extensions; Apple's needs the triple so objects come out as Mach-O for
+// the right architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Toolchain {
+    #[default]
+    Unix,
+    Apple,
+    Msvc,
+    MinGw,
+}
+
+impl Toolchain {
+    // A Visual Studio developer prompt sets VCINSTALLDIR and MSYS2 shells set
+    // MSYSTEM. With neither, assume MSVC, which is clang's own default there.
+    pub fn detect() -> Toolchain {
+        if cfg!(target_os = "macos") {
+            Toolchain::Apple
+        } else if !cfg!(windows) {
+            Toolchain::Unix
+        } else if std::env::var_os("VCINSTALLDIR").is_some() {
+            Toolchain::Msvc
+        } else if std::env::var_os("MSYSTEM").is_some() {
+            Toolchain::MinGw
+        } else {
+            Toolchain::Msvc
+        }
+    }
+
+    pub fn object_extension(&self) -> &'static str {
+        match self {
+            Toolchain::Msvc => "obj",
+            Toolchain::Unix | Toolchain::Apple | Toolchain::MinGw => "o",
+        }
+    }
+
+    pub fn static_library_name(&self, stem: &str) -> String {
+        match self {
+            Toolchain::Msvc => format!("{}.lib", stem),
+            Toolchain::Unix | Toolchain::Apple | Toolchain::MinGw => format!("lib{}.a", stem),
+        }
+    }
+
+    pub fn executable_extension(&self) -> &'static str {
+        match self {
+            Toolchain::Unix | Toolchain::Apple => "",
+            Toolchain::Msvc | Toolchain::MinGw => "exe",
+        }
+    }
+
+    // Windows reserves only 1 MiB for the main thread; ask for the 8 MiB
+    // the stack-overflow check assumes, in each linker's own syntax
+    pub fn clang_args(&self) -> Vec<String> {
+        let args: &[&str] = match self {
+            Toolchain::Unix => &[],
+            // The oldest releases that run on each architecture
+            Toolchain::Apple if cfg!(target_arch = "aarch64") => &["--target=arm64-apple-macosx11.0"],
+            Toolchain::Apple => &["--target=x86_64-apple-macosx10.13"],
+            Toolchain::Msvc => &["--target=x86_64-pc-windows-msvc", "-Wl,/STACK:8388608"],
+            Toolchain::MinGw => &["--target=x86_64-w64-mingw32", "-Wl,--stack,8388608"],
+        };
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+}
+
+// How --static gets a program with no shared library dependencies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StaticLink {
+    // musl is built for static linking, so prefer it when it's installed
+    Musl { sysroot: String },
+    // Otherwise the platform's own libc as an archive; glibc manages this
+    // for everything we call, though it warns about some functions
+    SystemLibc,
+}
+
+impl StaticLink {
+    // Where distributions put musl's headers and libc.a (musl-dev on Debian
+    // and Ubuntu, the musl package on Arch and Fedora)
+    const MUSL_SYSROOTS: [&str; 3] = ["/usr/lib/musl", "/usr/local/musl", "/usr/musl"];
+
+    pub fn detect() -> StaticLink {
+        if cfg!(target_os = "linux") {
+            for sysroot in StaticLink::MUSL_SYSROOTS {
+                if Path::new(sysroot).join("lib/libc.a").is_file() {
+                    return StaticLink::Musl {
+                        sysroot: sysroot.to_string(),
+                    };
+                }
+            }
+        }
+        StaticLink::SystemLibc
+    }
+
+    pub fn clang_args(&self) -> Vec<String> {
+        match self {
+            StaticLink::Musl { sysroot } => vec![
+                format!("--target={}-linux-musl", std::env::consts::ARCH),
+                format!("--sysroot={}", sysroot),
+                "-static".to_string(),
+            ],
+            StaticLink::SystemLibc => vec!["-static".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CodegenOptions {
+    pub target: Target,
+    // Only consulted for Target::Native
+    pub toolchain: Toolchain,
+    pub sanitizers: Vec<Sanitizer>,
+    // Directory for the raw profiles an instrumented build writes; Some("")
+    // leaves it to the runtime's default (the working directory)
+    pub profile_generate: Option<String>,
+    // Merged .profdata from earlier instrumented runs
+    pub profile_use: Option<String>,
+    // gcov-style counters, reported with `llvm-cov gcov`
+    pub coverage: bool,
+    // Identity to codesign the linked program with; "-" signs ad hoc
+    pub codesign: Option<String>,
+    pub static_link: Option<StaticLink>,
+    // clang's -O level: 0-3, s or z. None leaves clang's default (-O0).
+    pub opt_level: Option<String>,
+    // Force the loop unroller on or off; None leaves it to the -O level
+    pub unroll_loops: Option<bool>,
+    // Build a library for C or Rust code to link against rather than a
+    // program: defining main is an error and module initializers run when
+    // the library is loaded
+    pub no_main: bool,
+    // Archive the library's object into a static library for C and C++
+    // builds to link; implies no_main
+    pub staticlib: bool,
+}
+
+impl CodegenOptions {
+    // What clang should build from a module's IR: modules without a main
+    // become objects for the programs that import them, anything else the
+    // program itself
+    pub fn artifact_path(&self, source: &str, has_main: bool) -> PathBuf {
+        let extension = match (self.target, has_main) {
+            (Target::Native, false) => self.toolchain.object_extension(),
+            (Target::Native, true) => self.toolchain.executable_extension(),
+            (_, false) => "o",
+            (_, true) => "wasm",
+        };
+        Path::new(source).with_extension(extension)
+    }
+
+    // The commands that finish the build from `ir_file`, for the driver to
+    // show: clang, then codesign if asked for
+    pub fn build_commands(&self, ir_file: &str, source: &str, has_main: bool) -> Vec<String> {
+        let artifact = self.artifact_path(source, has_main);
+
+        let mut clang = self.linker_command();
+        if !has_main {
+            clang.push("-c".to_string());
+        }
+        clang.extend([ir_file.to_string(), "-o".to_string(), artifact.display().to_string()]);
+
+        let mut commands = vec![clang.join(" ")];
+        if self.staticlib {
+            commands.push(self.archive_command(&artifact, &self.static_library_path(source)));
+        }
+        // Objects are signed as part of the program they're linked into
+        if let (Some(identity), true) = (&self.codesign, has_main) {
+            commands.push(format!("codesign --force --sign {} {}", identity, artifact.display()));
+        }
+        commands
+    }
+
+    pub fn static_library_path(&self, source: &str) -> PathBuf {
+        let path = Path::new(source);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self.target {
+            Target::Native => self.toolchain.static_library_name(&stem),
+            _ => format!("lib{}.a", stem),
+        };
+        path.with_file_name(name)
+    }
+
+    fn archive_command(&self, object: &Path, archive: &Path) -> String {
+        match (self.target, self.toolchain) {
+            (Target::Native, Toolchain::Msvc) => {
+                format!("lib /nologo /OUT:{} {}", archive.display(), object.display())
+            }
+            (Target::Native, _) => format!("ar rcs {} {}", archive.display(), object.display()),
+            // GNU ar can't build the symbol index for wasm objects
+            _ => format!("llvm-ar rcs {} {}", archive.display(), object.display()),
+        }
+    }
+
+    // clang does the linking (and any optimizing) for every target
+    pub fn linker_command(&self) -> Vec<String> {
+        let mut command = vec!["clang".to_string()];
+        command.extend(self.clang_args());
+        command
+    }
+
+    pub fn sanitizes(&self, sanitizer: Sanitizer) -> bool {
+        self.sanitizers.contains(&sanitizer)
+    }
+
+    // We only produce IR, so the instrumentation and optimization passes and
+    // their runtimes come in when clang builds the final executable. These
+    // are the flags it needs. IR-level PGO works on any module, so profiles
+    // behave the same as for C sources.
+    pub fn clang_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        match self.target {
+            Target::Native => args.extend(self.toolchain.clang_args()),
+            target => args.push(format!("--target={}", target.name())),
+        }
+        // wasm modules are self-contained already
+        if let (Target::Native, Some(static_link)) = (self.target, &self.static_link) {
+            args.extend(static_link.clang_args());
+        }
+        // clang runs the new pass manager's default pipeline for the level,
+        // which is where LoopUnrollPass (and the rest of the loop passes) live
+        if let Some(level) = &self.opt_level {
+            args.push(format!("-O{}", level));
+        }
+        match self.unroll_loops {
+            Some(true) => args.push("-funroll-loops".to_string()),
+            Some(false) => args.push("-fno-unroll-loops".to_string()),
+            None => {}
+        }
+        // No libc and no _start: the page runs the module initializers and
+        // then calls main itself
+        if self.target == Target::Wasm32Unknown {
+            args.extend(
+                [
+                    "-nostdlib",
+                    "-Wl,--no-entry",
+                    "-Wl,--export=main",
+                    "-Wl,--export=__wasm_call_ctors",
+                ]
+                .map(String::from),
+            );
+        }
+        if !self.sanitizers.is_empty() {
+            let names: Vec<&str> = self.sanitizers.iter().map(Sanitizer::name).collect();
+            args.push(format!("-fsanitize={}", names.join(",")));
+        }
+        match self.profile_generate.as_deref() {
+            Some("") => args.push("-fprofile-generate".to_string()),
+            Some(dir) => args.push(format!("-fprofile-generate={}", dir)),
+            None => {}
+        }
+        if let Some(profile) = &self.profile_use {
+            args.push(format!("-fprofile-use={}", profile));
+        }
+        if self.coverage {
+            args.push("--coverage".to_string());
+        }
+        args
+    }
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// One entry in the database, laid out like clang's compile_commands.json so
+// existing tooling can read it without a Magolor-specific parser
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompileCommand {
+    pub directory: String,
+    pub file: String,
+    pub arguments: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub output: Option<String>,
+}
+
+impl CompileCommand {
+    pub fn new(file: &str, arguments: &[String], output: Option<&str>) -> Self {
+        let directory = env::current_dir()
+            .map(|d| d.display().to_string())
+            .unwrap_or_else(|_| ".".to_string());
+
+        CompileCommand {
+            directory,
+            file: file.to_string(),
+            arguments: arguments.to_vec(),
+            output: output.map(|o| o.to_string()),
+        }
+    }
+}
+
+// Adds `entry` to the database at `db_path`, replacing any earlier record for
+// the same input/output pair so repeated builds don't grow the file forever
+pub fn record(db_path: &str, entry: CompileCommand) -> Result<(), String> {
+    let mut entries: Vec<CompileCommand> = if Path::new(db_path).exists() {
+        let text = fs::read_to_string(db_path)
+            .map_err(|e| format!("Failed to read compilation database {}: {}", db_path, e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("Malformed compilation database {}: {}", db_path, e))?
+    } else {
+        Vec::new()
+    };
+
+    entries.retain(|e| {
+        !(e.directory == entry.directory && e.file == entry.file && e.output == entry.output)
+    });
+    entries.push(entry);
+
+    let text = serde_json::to_string_pretty(&entries)
+        .map_err(|e| format!("Failed to serialize compilation database: {}", e))?;
+    fs::write(db_path, text)
+        .map_err(|e| format!("Failed to write compilation database {}: {}", db_path, e))
+}
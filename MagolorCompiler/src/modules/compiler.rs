@@ -0,0 +1,561 @@
+use crate::modules::codegen::CodegenOptions;
+use crate::modules::diagnostics::{Diagnostics, Span};
+use crate::modules::index::SymbolIndex;
+use crate::modules::interface::{INIT_FN, ModuleInterface};
+use crate::modules::parser::AST;
+use crate::modules::passes::AstPass;
+use crate::modules::source::{SourceFile, SourceMap};
+use crate::modules::stdlib::{self, StdModule};
+use crate::modules::tokenizer::{KeywordCase, Token};
+use crate::modules::typeck::TypeInfo;
+use crate::modules::types::Type;
+use crate::modules::IR::DebugSource;
+use crate::modules::{IR, builtins, callgraph, consteval, parser, prelude, query, tokenizer, treeshake, typeck, validate};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+// What we remember about the most recently compiled file so editor queries
+// can be answered without redoing the work
+struct Analysis {
+    file: String,
+    tokens: Vec<(Token, Span)>,
+    types: TypeInfo,
+    index: SymbolIndex,
+    imports: Vec<ModuleInterface>,
+}
+
+// Where `use` looks for the interfaces of modules imported by `file`
+pub fn import_search_path(file: &str) -> PathBuf {
+    match Path::new(file).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+// Points in the pipeline where --dump-after can show the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    // The tree as parsed
+    Parse,
+    // Signatures and local types, once type checking has run
+    Typecheck,
+    // The tree with #[const] calls replaced by their results
+    ConstFold,
+    // The tree after any registered AstPass
+    Passes,
+    // The tree with unreachable functions dropped, as codegen sees it
+    Treeshake,
+    // The generated LLVM IR
+    Codegen,
+}
+
+impl Stage {
+    pub const NAMES: [&str; 6] = ["parse", "typecheck", "constfold", "passes", "treeshake", "codegen"];
+
+    pub fn from_name(name: &str) -> Option<Stage> {
+        match name {
+            "parse" => Some(Stage::Parse),
+            "typecheck" => Some(Stage::Typecheck),
+            "constfold" => Some(Stage::ConstFold),
+            "passes" => Some(Stage::Passes),
+            "treeshake" => Some(Stage::Treeshake),
+            "codegen" => Some(Stage::Codegen),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Parse => "parse",
+            Stage::Typecheck => "typecheck",
+            Stage::ConstFold => "constfold",
+            Stage::Passes => "passes",
+            Stage::Treeshake => "treeshake",
+            Stage::Codegen => "codegen",
+        }
+    }
+}
+
+// Library entry point. Downstream tools build one of these, register any
+// extra passes, then hand it source text. Problems are collected into
+// `diagnostics` rather than aborting on the first one.
+pub struct Compiler {
+    passes: Vec<Box<dyn AstPass>>,
+    codegen: CodegenOptions,
+    dump_after: Vec<Stage>,
+    emit_call_graph: bool,
+    call_graph: Option<String>,
+    sandbox: bool,
+    prelude: bool,
+    keyword_case: KeywordCase,
+    // The standard library modules the last analyzed file `use`s
+    std_modules: Vec<StdModule>,
+    diagnostics: Diagnostics,
+    sources: SourceMap,
+    file: String,
+    // Where `file` starts in `sources`
+    base: usize,
+    analysis: Option<Analysis>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            passes: Vec::new(),
+            codegen: CodegenOptions::default(),
+            dump_after: Vec::new(),
+            emit_call_graph: false,
+            call_graph: None,
+            sandbox: false,
+            prelude: true,
+            keyword_case: KeywordCase::default(),
+            std_modules: Vec::new(),
+            diagnostics: Diagnostics::new(),
+            sources: SourceMap::new(),
+            file: String::new(),
+            base: 0,
+            analysis: None,
+        }
+    }
+
+    // Passes run in registration order
+    pub fn add_pass(&mut self, pass: Box<dyn AstPass>) -> &mut Self {
+        self.passes.push(pass);
+        self
+    }
+
+    pub fn set_codegen_options(&mut self, options: CodegenOptions) -> &mut Self {
+        self.codegen = options;
+        self
+    }
+
+    // Print the program to stderr as it stands after each of `stages`. This
+    // happens as the pipeline runs, so the dumps before a crash still show.
+    pub fn set_dump_after(&mut self, stages: Vec<Stage>) -> &mut Self {
+        self.dump_after = stages;
+        self
+    }
+
+    // Have compile_ast record the program's call graph (as DOT) for
+    // take_call_graph
+    pub fn set_emit_call_graph(&mut self, emit: bool) -> &mut Self {
+        self.emit_call_graph = emit;
+        self
+    }
+
+    // For compiling untrusted code, e.g. behind a playground: anything that
+    // would reach outside the source text is an error. That's `use` of a
+    // user module, which reads its .magi from disk, and the process
+    // builtins (prelude::SANDBOXED); the language has no file or
+    // environment builtins yet.
+    pub fn set_sandbox(&mut self, sandbox: bool) -> &mut Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    // Whether every program sees the prelude's modules (console, assert,
+    // strings, math, process) without `use`-ing them. On by default.
+    pub fn set_prelude(&mut self, prelude: bool) -> &mut Self {
+        self.prelude = prelude;
+        self
+    }
+
+    // Whether `Fn` and `RETURN` are keywords too, or just identifiers. Strictly
+    // lowercase by default.
+    pub fn set_keyword_case(&mut self, case: KeywordCase) -> &mut Self {
+        self.keyword_case = case;
+        self
+    }
+
+    // The call graph of the last compile_ast, if one was asked for and the
+    // compile got far enough to build it
+    pub fn take_call_graph(&mut self) -> Option<String> {
+        self.call_graph.take()
+    }
+
+    fn dump(&self, stage: Stage, render: impl FnOnce() -> String) {
+        if self.dump_after.contains(&stage) {
+            eprintln!("=== after {} ===\n{}", stage.name(), render());
+        }
+    }
+
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    pub fn take_diagnostics(&mut self) -> Diagnostics {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    pub fn parse(&mut self, file: &str, source: &str) -> Vec<AST> {
+        self.parse_source(SourceFile::from_string(file, source.to_string()))
+    }
+
+    // Like parse, but takes a file the caller already loaded (possibly
+    // memory-mapped) instead of copying its text
+    pub fn parse_source(&mut self, source: SourceFile) -> Vec<AST> {
+        self.file = source.path().to_string();
+        self.base = self.sources.add(source);
+        self.analysis = None;
+
+        // Each item is parsed as soon as its tokens are lexed, so the whole
+        // file's tokens are never held at once
+        let base = self.base;
+        let (source, _) = self.sources.lookup(base).expect("current file is in the source map");
+        let mut lex_errors = Vec::new();
+        let lexed = tokenizer::lex(source.text(), self.keyword_case).filter_map(|lexed| match lexed {
+            Ok((tok, span)) => Some((tok, span.shifted(base))),
+            // Parsing goes on without it, so later errors still show up
+            Err(e) => {
+                lex_errors.push(e);
+                None
+            }
+        });
+        let mut ast = Vec::new();
+        for item in parser::Items::new(lexed) {
+            match item {
+                Ok(nodes) => ast.extend(nodes),
+                Err((e, span)) => self.diagnostics.error(e, Some(span)),
+            }
+        }
+        for e in lex_errors {
+            self.diagnostics.error(e.message(), Some(e.span.shifted(base)));
+        }
+        self.dump(Stage::Parse, || format!("{:#?}", ast));
+        ast
+    }
+
+    // Every file this compiler has loaded; spans in diagnostics and the
+    // symbol index are offsets into it
+    pub fn source_map(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    fn current_source(&self) -> &SourceFile {
+        self.sources
+            .lookup(self.base)
+            .map(|(file, _)| file)
+            .expect("current file is in the source map")
+    }
+
+    pub fn run_passes(&mut self, mut ast: Vec<AST>) -> Result<Vec<AST>, String> {
+        for pass in self.passes.iter_mut() {
+            ast = pass
+                .run(ast)
+                .map_err(|e| format!("pass '{}' failed: {}", pass.name(), e))?;
+        }
+        Ok(ast)
+    }
+
+    // Type checks the tree and builds the tables editor queries run against
+    pub fn analyze(&mut self, ast: &[AST]) {
+        let imports = self.load_imports(ast);
+        let builtins = self.builtin_modules(ast);
+        let tokens: Vec<(Token, Span)> = tokenizer::tokenize_with_spans(self.current_source().text(), self.keyword_case)
+            .into_iter()
+            .map(|(tok, span)| (tok, span.shifted(self.base)))
+            .collect();
+        let types = typeck::check(ast, &imports, &builtins, self.sandbox, &tokens, &mut self.diagnostics);
+        let index = SymbolIndex::build(&tokens);
+        self.analysis = Some(Analysis {
+            file: self.file.clone(),
+            tokens,
+            types,
+            index,
+            imports,
+        });
+    }
+
+    // Reads the interface of every module the file `use`s, from
+    // `<module>.magi` in the same directory as the file, or by compiling it
+    // if it's part of the standard library
+    fn load_imports(&mut self, ast: &[AST]) -> Vec<ModuleInterface> {
+        let mut imports = Vec::new();
+        self.std_modules.clear();
+        for node in ast {
+            let AST::Import(module) = node else {
+                continue;
+            };
+            if prelude::builtin_module(module).is_some() {
+                continue;
+            }
+            if let Some(loaded) = stdlib::load(module) {
+                match loaded {
+                    Ok(std_module) => {
+                        imports.push(std_module.interface.clone());
+                        self.std_modules.push(std_module);
+                    }
+                    Err(e) => self.diagnostics.error(e, None),
+                }
+                continue;
+            }
+            if self.sandbox {
+                self.diagnostics.error(
+                    format!("cannot import '{}': only built-in modules are available in the sandbox", module),
+                    None,
+                );
+                continue;
+            }
+            let path = import_search_path(&self.file).join(format!("{}.magi", module));
+            match ModuleInterface::read(&path) {
+                Ok(interface) => imports.push(interface),
+                Err(e) => self.diagnostics.error(
+                    format!("cannot import '{}' (compile {}.mag first): {}", module, module, e),
+                    None,
+                ),
+            }
+        }
+        imports
+    }
+
+    // The built-in modules `ast` can use: the whole prelude unless it's
+    // turned off, plus whichever it names in `use` lines, less the ones the
+    // sandbox withholds
+    fn builtin_modules(&self, ast: &[AST]) -> Vec<&'static str> {
+        let mut modules = Vec::new();
+        if self.prelude {
+            modules = prelude::MODULES.to_vec();
+        } else {
+            let used = ast.iter().filter_map(|node| match node {
+                AST::Import(module) => prelude::builtin_module(module),
+                _ => None,
+            });
+            for module in used {
+                if !modules.contains(&module) {
+                    modules.push(module);
+                }
+            }
+        }
+        if self.sandbox {
+            modules.retain(|module| !prelude::SANDBOXED.contains(module));
+        }
+        modules
+    }
+
+    // Signatures of the functions defined in the last analyzed file, for
+    // writing its .magi
+    pub fn interface(&self) -> Option<ModuleInterface> {
+        let analysis = self.analysis.as_ref()?;
+        let module = Path::new(&analysis.file).file_stem()?.to_string_lossy();
+        Some(ModuleInterface::from_types(&module, &analysis.types, &analysis.imports))
+    }
+
+    // Returns the generated LLVM IR as text, or None if anything reported so
+    // far (including parse errors) was an error
+    pub fn compile_ast(&mut self, ast: Vec<AST>) -> Option<String> {
+        for node in &ast {
+            if !matches!(node, AST::FuncDef { .. } | AST::Import(_)) {
+                self.diagnostics
+                    .warning(format!("top-level statement is ignored: {:?}", node), None);
+            }
+        }
+
+        if self.diagnostics.has_errors() {
+            return None;
+        }
+
+        let ast = builtins::fold_compiler_info(ast, self.codegen.target);
+        self.analyze(&ast);
+        if self.codegen.no_main {
+            let defines_main = self
+                .analysis
+                .as_ref()
+                .is_some_and(|analysis| analysis.types.functions.contains_key("main"));
+            if defines_main {
+                self.diagnostics
+                    .error("'main' is defined, but --no-main compiles a library", None);
+            }
+        }
+        self.dump(Stage::Typecheck, || {
+            self.analysis
+                .as_ref()
+                .map(|analysis| describe_types(&analysis.types))
+                .unwrap_or_default()
+        });
+
+        if self.diagnostics.has_errors() {
+            return None;
+        }
+
+        // Folding drops the calls to #[const] functions, so what counts as
+        // used is decided on the program as written
+        let written = {
+            let entry_points = entry_points(&ast);
+            let roots: Vec<&str> = entry_points.iter().map(String::as_str).collect();
+            treeshake::reachable(&ast, &roots)
+        };
+        let ast = consteval::fold_const_calls(ast, &mut self.diagnostics);
+        self.dump(Stage::ConstFold, || format!("{:#?}", ast));
+
+        if self.diagnostics.has_errors() {
+            return None;
+        }
+
+        validate::validate_ast(&ast);
+
+        let ast = match self.run_passes(ast) {
+            Ok(ast) => ast,
+            Err(e) => {
+                self.diagnostics.error(e, None);
+                return None;
+            }
+        };
+
+        // Catch custom passes that left the tree in a shape codegen can't handle
+        if !self.passes.is_empty() {
+            validate::validate_ast(&ast);
+        }
+        self.dump(Stage::Passes, || format!("{:#?}", ast));
+
+        let entry_points = entry_points(&ast);
+        let roots: Vec<&str> = entry_points.iter().map(String::as_str).collect();
+
+        // Drawn before tree shaking so the graph can show what it removes
+        if self.emit_call_graph {
+            let imported = self.analysis.as_ref().map(|a| &a.types.imported);
+            self.call_graph = Some(callgraph::to_dot(&ast, &roots, imported.unwrap_or(&HashMap::new())));
+        }
+
+        let ast = treeshake::shake(ast, &roots, written.as_ref(), &mut self.diagnostics);
+        let ast = self.link_std(ast);
+        self.dump(Stage::Treeshake, || format!("{:#?}", ast));
+
+        let debug = self.codegen.coverage.then(|| self.debug_source());
+        let imports = self.analysis.as_ref().map(|a| a.imports.as_slice()).unwrap_or(&[]);
+        let module = Path::new(&self.file)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let ir = IR::compile(ast, &module, &self.codegen, debug.as_ref(), imports);
+        self.dump(Stage::Codegen, || ir.clone());
+        Some(ir)
+    }
+
+    // Appends the standard library functions the program calls, along with
+    // whatever those call in turn. They are private to this module as if
+    // they had been written in it, so every module that uses one has its
+    // own copy.
+    fn link_std(&self, mut ast: Vec<AST>) -> Vec<AST> {
+        let called: HashSet<String> = ast
+            .iter()
+            .flat_map(|node| match node {
+                AST::FuncDef { body, .. } => treeshake::callees(body),
+                _ => HashSet::new(),
+            })
+            .collect();
+        let roots: Vec<&str> = called.iter().map(String::as_str).collect();
+
+        for module in &self.std_modules {
+            let Some(reachable) = treeshake::reachable(&module.ast, &roots) else {
+                continue;
+            };
+            let linked = module
+                .ast
+                .iter()
+                .filter(|node| matches!(node, AST::FuncDef { name, .. } if reachable.contains(name)));
+            for node in linked {
+                let mut function = node.clone();
+                if let AST::FuncDef { public, .. } = &mut function {
+                    *public = false;
+                }
+                ast.push(function);
+            }
+        }
+        ast
+    }
+
+    // Line of every function definition, for the debug info coverage needs
+    fn debug_source(&self) -> DebugSource {
+        let mut function_lines = HashMap::new();
+        if let Some(analysis) = &self.analysis {
+            for pair in analysis.tokens.windows(2) {
+                if let [(Token::Func, _), (Token::Ident(name), span)] = pair {
+                    let (line, _) = self.current_source().line_col(span.start - self.base);
+                    function_lines.insert(name.to_string(), line as u32);
+                }
+            }
+        }
+
+        let path = Path::new(&self.file);
+        let directory = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy().into_owned(),
+            _ => ".".to_string(),
+        };
+        DebugSource {
+            file: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| self.file.clone()),
+            directory,
+            function_lines,
+        }
+    }
+
+    // One-shot entry point: everything reported comes back in one collection
+    pub fn compile(&mut self, file: &str, source: &str) -> (Option<String>, Diagnostics) {
+        let ast = self.parse(file, source);
+        let ir = self.compile_ast(ast);
+        (ir, self.take_diagnostics())
+    }
+
+    // Type of the expression or name at a byte offset within `file`, for
+    // hover and inlay hints. Only answers for the file type checking last
+    // ran on.
+    pub fn type_at(&self, file: &str, offset: usize) -> Option<Type> {
+        let analysis = self.analysis.as_ref()?;
+        if analysis.file != file {
+            return None;
+        }
+        query::type_at(&analysis.tokens, &analysis.types, self.base + offset)
+    }
+
+    // Definitions/uses of every function and local in the last analyzed
+    // file. Spans are source map offsets; see source_map().
+    pub fn symbol_index(&self, file: &str) -> Option<&SymbolIndex> {
+        let analysis = self.analysis.as_ref()?;
+        (analysis.file == file).then_some(&analysis.index)
+    }
+}
+
+// The program runs main and init (init before main), and other modules can
+// call the `pub` functions; nothing else is reachable from outside
+fn entry_points(ast: &[AST]) -> Vec<String> {
+    ast.iter()
+        .filter_map(|node| match node {
+            AST::FuncDef { name, public, .. } if *public || name == "main" || name == INIT_FN => {
+                Some(name.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// Signatures then locals, sorted so dumps of the same program compare equal
+fn describe_types(types: &TypeInfo) -> String {
+    let mut names: Vec<&String> = types.functions.keys().collect();
+    names.sort();
+
+    let mut text = String::new();
+    for name in names {
+        let sig = &types.functions[name];
+        let params: Vec<String> = sig.params.iter().map(|(p, ty)| format!("{}: {}", p, ty)).collect();
+        text.push_str(&format!("fn {}({}) -> {}", name, params.join(", "), sig.ret));
+        if let Some(module) = types.imported.get(name) {
+            text.push_str(&format!("  // from {}", module));
+        }
+        text.push('\n');
+
+        let mut locals: Vec<(&String, &Type)> = types.locals.get(name).into_iter().flatten().collect();
+        locals.sort_by_key(|(local, _)| *local);
+        for (local, ty) in locals {
+            text.push_str(&format!("    let {}: {}\n", local, ty));
+        }
+    }
+    text
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,445 @@
+use crate::modules::diagnostics::Diagnostics;
+use crate::modules::parser::{AST, ASTValue};
+use crate::modules::types::Type;
+use crate::modules::visitor::{AstFolder, fold_value_children};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// Calls into the same #[const] functions nest at most this deep, so a
+// function that recurses forever is an error instead of a hung compiler
+const MAX_DEPTH: usize = 64;
+
+// Likewise a while loop gives up after this many passes through its body
+const MAX_ITERATIONS: usize = 100_000;
+
+struct ConstFn {
+    params: Vec<(String, Type)>,
+    ret: Type,
+    body: Vec<AST>,
+}
+
+// Replaces every call to a `#[const]` function whose arguments are all
+// literals with the literal it returns, e.g. to turn a lookup table written
+// as if/elif chains into constants. The functions themselves stay in the
+// program for calls that can't be folded. Runs after type checking, so
+// arguments already have the right types.
+pub fn fold_const_calls(ast: Vec<AST>, diagnostics: &mut Diagnostics) -> Vec<AST> {
+    let mut functions = HashMap::new();
+    for node in &ast {
+        let AST::FuncDef {
+            name,
+            params,
+            return_type,
+            body,
+            attributes,
+            ..
+        } = node
+        else {
+            continue;
+        };
+        if !attributes.iter().any(|attr| attr.name == "const") {
+            continue;
+        }
+        let ret = return_type.as_deref().and_then(Type::from_name).unwrap_or(Type::Void);
+        let params = params
+            .iter()
+            .filter_map(|(param, ty)| Some((param.clone(), Type::from_name(ty)?)))
+            .collect();
+        functions.insert(
+            name.clone(),
+            ConstFn {
+                params,
+                ret,
+                body: body.clone(),
+            },
+        );
+    }
+
+    if functions.is_empty() {
+        return ast;
+    }
+    ConstFolder {
+        functions: &functions,
+        diagnostics,
+    }
+    .fold_program(ast)
+}
+
+struct ConstFolder<'a> {
+    functions: &'a HashMap<String, ConstFn>,
+    diagnostics: &'a mut Diagnostics,
+}
+
+impl AstFolder for ConstFolder<'_> {
+    fn fold_value(&mut self, value: ASTValue) -> ASTValue {
+        let value = fold_value_children(self, value);
+        let ASTValue::FuncCall { name, args } = &value else {
+            return value;
+        };
+        if !self.functions.contains_key(name) || !args.iter().all(is_literal) {
+            return value;
+        }
+        match call(self.functions, name, args, 0) {
+            Ok(Some(result)) => result,
+            // Void: there's no value to put in its place
+            Ok(None) => value,
+            Err(e) => {
+                self.diagnostics.error(
+                    format!("cannot evaluate '{}' at compile time: {}", name, e),
+                    None,
+                );
+                value
+            }
+        }
+    }
+}
+
+fn is_literal(value: &ASTValue) -> bool {
+    matches!(
+        value,
+        ASTValue::Str(_)
+            | ASTValue::Int(_)
+            | ASTValue::Int64(_)
+            | ASTValue::UInt8(_)
+            | ASTValue::UInt32(_)
+            | ASTValue::UInt64(_)
+            | ASTValue::Float32(_)
+            | ASTValue::Float64(_)
+            | ASTValue::Bool(_)
+            | ASTValue::Char(_)
+    )
+}
+
+fn call(
+    functions: &HashMap<String, ConstFn>,
+    name: &str,
+    args: &[ASTValue],
+    depth: usize,
+) -> Result<Option<ASTValue>, String> {
+    if depth == MAX_DEPTH {
+        return Err(format!("calls nest more than {} deep", MAX_DEPTH));
+    }
+    let Some(function) = functions.get(name) else {
+        return Err(format!("'{}' is not #[const]", name));
+    };
+
+    let mut frame = Frame {
+        functions,
+        depth,
+        vars: HashMap::new(),
+    };
+    for ((param, ty), arg) in function.params.iter().zip(args) {
+        frame.vars.insert(param.clone(), convert(arg.clone(), *ty)?);
+    }
+
+    match frame.run(&function.body)? {
+        Some(result) if function.ret != Type::Void => convert(result, function.ret).map(Some),
+        _ => Ok(None),
+    }
+}
+
+// One activation of a #[const] function: its variables, all holding literals
+struct Frame<'a> {
+    functions: &'a HashMap<String, ConstFn>,
+    depth: usize,
+    vars: HashMap<String, ASTValue>,
+}
+
+impl Frame<'_> {
+    // Some(value) once a return statement runs
+    fn run(&mut self, body: &[AST]) -> Result<Option<ASTValue>, String> {
+        for stmt in body {
+            match stmt {
+                AST::VarDecl(ty, name, value, _) => {
+                    let value = self.eval(value)?;
+                    let value = match Type::from_name(ty) {
+                        Some(ty) => convert(value, ty)?,
+                        None => value,
+                    };
+                    self.vars.insert(name.clone(), value);
+                }
+                AST::Return(value) => return self.eval(value).map(Some),
+                AST::If {
+                    condition,
+                    then_body,
+                    elif_branches,
+                    else_body,
+                } => {
+                    let mut taken = None;
+                    if self.eval_condition(condition)? {
+                        taken = Some(then_body);
+                    } else {
+                        for (elif_cond, elif_body) in elif_branches {
+                            if self.eval_condition(elif_cond)? {
+                                taken = Some(elif_body);
+                                break;
+                            }
+                        }
+                    }
+                    if let Some(body) = taken.or(else_body.as_ref()) {
+                        if let Some(result) = self.run(body)? {
+                            return Ok(Some(result));
+                        }
+                    }
+                }
+                AST::While { condition, body } => {
+                    let mut passes = 0;
+                    while self.eval_condition(condition)? {
+                        if passes == MAX_ITERATIONS {
+                            return Err(format!("loop runs more than {} times", MAX_ITERATIONS));
+                        }
+                        passes += 1;
+                        if let Some(result) = self.run(body)? {
+                            return Ok(Some(result));
+                        }
+                    }
+                }
+                AST::For {
+                    variable,
+                    start,
+                    end,
+                    body,
+                } => {
+                    let (start, end) = (self.eval(start)?, self.eval(end)?);
+                    let (Some(first), Some(last)) = (as_int(&start), as_int(&end)) else {
+                        return Err(format!("range {:?}..{:?} isn't of integers", start, end));
+                    };
+                    // An unsuffixed start takes the end's type
+                    let bound = if matches!(start, ASTValue::Int(_)) { &end } else { &start };
+                    let ty = integer_type(bound).unwrap_or(Type::I32);
+                    let shadowed = self.vars.get(variable).cloned();
+                    for (passes, i) in (first..last).enumerate() {
+                        if passes == MAX_ITERATIONS {
+                            return Err(format!("loop runs more than {} times", MAX_ITERATIONS));
+                        }
+                        self.vars.insert(variable.clone(), wrap(i, ty));
+                        if let Some(result) = self.run(body)? {
+                            return Ok(Some(result));
+                        }
+                    }
+                    match shadowed {
+                        Some(value) => self.vars.insert(variable.clone(), value),
+                        None => self.vars.remove(variable),
+                    };
+                }
+                AST::Call { object, method, .. } => {
+                    return Err(format!("'{}.{}' can't run at compile time", object, method));
+                }
+                AST::VarRef(_) | AST::Literal(_) | AST::Import(_) | AST::FuncDef { .. } => {}
+            }
+        }
+        Ok(None)
+    }
+
+    fn eval_condition(&mut self, cond: &ASTValue) -> Result<bool, String> {
+        match self.eval(cond)? {
+            ASTValue::Bool(b) => Ok(b),
+            other => Err(format!("condition evaluated to {:?}, not a bool", other)),
+        }
+    }
+
+    fn eval(&mut self, value: &ASTValue) -> Result<ASTValue, String> {
+        match value {
+            ASTValue::VarRef(name) => self
+                .vars
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("unknown variable '{}'", name)),
+            ASTValue::FuncCall { name, args } => {
+                let args = args.iter().map(|arg| self.eval(arg)).collect::<Result<Vec<_>, _>>()?;
+                call(self.functions, name, &args, self.depth + 1)?
+                    .ok_or_else(|| format!("'{}' returned no value", name))
+            }
+            // None is a comparison with NaN, where only `!=` holds
+            ASTValue::LessThan(lhs, rhs) => {
+                self.compare(lhs, rhs, |o| o == Some(Ordering::Less))
+            }
+            ASTValue::GreaterThan(lhs, rhs) => {
+                self.compare(lhs, rhs, |o| o == Some(Ordering::Greater))
+            }
+            ASTValue::LessEqual(lhs, rhs) => {
+                self.compare(lhs, rhs, |o| matches!(o, Some(Ordering::Less | Ordering::Equal)))
+            }
+            ASTValue::GreaterEqual(lhs, rhs) => {
+                self.compare(lhs, rhs, |o| matches!(o, Some(Ordering::Greater | Ordering::Equal)))
+            }
+            ASTValue::Equal(lhs, rhs) | ASTValue::EqualEqual(lhs, rhs) => {
+                self.compare(lhs, rhs, |o| o == Some(Ordering::Equal))
+            }
+            ASTValue::NotEqual(lhs, rhs) => {
+                self.compare(lhs, rhs, |o| o != Some(Ordering::Equal))
+            }
+            ASTValue::Add(lhs, rhs)
+            | ASTValue::Sub(lhs, rhs)
+            | ASTValue::Mul(lhs, rhs)
+            | ASTValue::Div(lhs, rhs)
+            | ASTValue::Rem(lhs, rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                arithmetic(value, &lhs, &rhs)
+            }
+            // The right side is only evaluated if it matters, as at run time
+            ASTValue::And(lhs, rhs) => {
+                Ok(ASTValue::Bool(self.eval_condition(lhs)? && self.eval_condition(rhs)?))
+            }
+            ASTValue::Or(lhs, rhs) => {
+                Ok(ASTValue::Bool(self.eval_condition(lhs)? || self.eval_condition(rhs)?))
+            }
+            ASTValue::Not(operand) => Ok(ASTValue::Bool(!self.eval_condition(operand)?)),
+            ASTValue::Neg(operand) => match self.eval(operand)? {
+                ASTValue::Int(n) => Ok(ASTValue::Int(n.wrapping_neg())),
+                ASTValue::Int64(n) => Ok(ASTValue::Int64(n.wrapping_neg())),
+                ASTValue::Float32(f) => Ok(ASTValue::Float32(-f)),
+                ASTValue::Float64(f) => Ok(ASTValue::Float64(-f)),
+                other => Err(format!("cannot negate {:?}", other)),
+            },
+            literal => Ok(literal.clone()),
+        }
+    }
+
+    fn compare(
+        &mut self,
+        lhs: &ASTValue,
+        rhs: &ASTValue,
+        holds: impl Fn(Option<Ordering>) -> bool,
+    ) -> Result<ASTValue, String> {
+        let lhs = self.eval(lhs)?;
+        let rhs = self.eval(rhs)?;
+        let ordering = match (&lhs, &rhs) {
+            (ASTValue::Str(a), ASTValue::Str(b)) => Some(a.cmp(b)),
+            (ASTValue::Bool(a), ASTValue::Bool(b)) => Some(a.cmp(b)),
+            (ASTValue::Char(a), ASTValue::Char(b)) => Some(a.cmp(b)),
+            _ => match (as_int(&lhs), as_int(&rhs)) {
+                (Some(a), Some(b)) => Some(a.cmp(&b)),
+                _ => match (as_float(&lhs), as_float(&rhs)) {
+                    (Some(a), Some(b)) => a.partial_cmp(&b),
+                    _ => return Err(format!("cannot compare {:?} with {:?}", lhs, rhs)),
+                },
+            },
+        };
+        Ok(ASTValue::Bool(holds(ordering)))
+    }
+}
+
+// `op` applied to two literals. Integers wrap around the way the generated
+// code's do; a literal mixed with a wider operand takes its type.
+fn arithmetic(op: &ASTValue, lhs: &ASTValue, rhs: &ASTValue) -> Result<ASTValue, String> {
+    match (lhs, rhs) {
+        (ASTValue::Int(a), ASTValue::Int(b)) => {
+            integer_op(op, *a as i128, *b as i128).map(|n| ASTValue::Int(n as i32))
+        }
+        // Rounding the f64 result gives the same f32 as doing it in f32
+        (ASTValue::Float32(a), ASTValue::Float32(b)) => {
+            Ok(ASTValue::Float32(float_op(op, *a as f64, *b as f64) as f32))
+        }
+        // An integer with a float is promoted to the float's type first
+        (_, ASTValue::Float32(b)) if as_int(lhs).is_some() => {
+            let a = as_int(lhs).unwrap_or_default() as f32;
+            Ok(ASTValue::Float32(float_op(op, a as f64, *b as f64) as f32))
+        }
+        (ASTValue::Float32(a), _) if as_int(rhs).is_some() => {
+            let b = as_int(rhs).unwrap_or_default() as f32;
+            Ok(ASTValue::Float32(float_op(op, *a as f64, b as f64) as f32))
+        }
+        _ => match (as_int(lhs), as_int(rhs)) {
+            // Unsigned arithmetic wraps at its type's width
+            (Some(a), Some(b)) => {
+                let ty = [lhs, rhs]
+                    .iter()
+                    .filter_map(|value| integer_type(value))
+                    .find(Type::is_unsigned)
+                    .unwrap_or(Type::I64);
+                integer_op(op, a, b).map(|n| wrap(n, ty))
+            }
+            _ => match (as_float(lhs), as_float(rhs)) {
+                (Some(a), Some(b)) => Ok(ASTValue::Float64(float_op(op, a, b))),
+                _ => Err(format!("cannot do arithmetic on {:?} and {:?}", lhs, rhs)),
+            },
+        },
+    }
+}
+
+fn integer_op(op: &ASTValue, a: i128, b: i128) -> Result<i128, String> {
+    if b == 0 && matches!(op, ASTValue::Div(..) | ASTValue::Rem(..)) {
+        return Err("division by zero".to_string());
+    }
+    Ok(match op {
+        ASTValue::Add(..) => a.wrapping_add(b),
+        ASTValue::Sub(..) => a.wrapping_sub(b),
+        ASTValue::Mul(..) => a.wrapping_mul(b),
+        ASTValue::Div(..) => a.wrapping_div(b),
+        ASTValue::Rem(..) => a.wrapping_rem(b),
+        _ => unreachable!("not an arithmetic value: {:?}", op),
+    })
+}
+
+fn float_op(op: &ASTValue, a: f64, b: f64) -> f64 {
+    match op {
+        ASTValue::Add(..) => a + b,
+        ASTValue::Sub(..) => a - b,
+        ASTValue::Mul(..) => a * b,
+        ASTValue::Div(..) => a / b,
+        ASTValue::Rem(..) => a % b,
+        _ => unreachable!("not an arithmetic value: {:?}", op),
+    }
+}
+
+// Wide enough for every integer type, signed or not, so comparisons of
+// any two come out right
+fn as_int(value: &ASTValue) -> Option<i128> {
+    match value {
+        ASTValue::Int(n) => Some(*n as i128),
+        ASTValue::Int64(n) => Some(*n as i128),
+        ASTValue::UInt8(n) => Some(*n as i128),
+        ASTValue::UInt32(n) => Some(*n as i128),
+        ASTValue::UInt64(n) => Some(*n as i128),
+        _ => None,
+    }
+}
+
+fn integer_type(value: &ASTValue) -> Option<Type> {
+    match value {
+        ASTValue::Int(_) => Some(Type::I32),
+        ASTValue::Int64(_) => Some(Type::I64),
+        ASTValue::UInt8(_) => Some(Type::U8),
+        ASTValue::UInt32(_) => Some(Type::U32),
+        ASTValue::UInt64(_) => Some(Type::U64),
+        _ => None,
+    }
+}
+
+// The literal of integer type `ty` holding `n`, wrapped around to fit
+fn wrap(n: i128, ty: Type) -> ASTValue {
+    match ty {
+        Type::I32 => ASTValue::Int(n as i32),
+        Type::U8 => ASTValue::UInt8(n as u8),
+        Type::U32 => ASTValue::UInt32(n as u32),
+        Type::U64 => ASTValue::UInt64(n as u64),
+        _ => ASTValue::Int64(n as i64),
+    }
+}
+
+// Integers too, promoted the way they are when mixed with an f64
+fn as_float(value: &ASTValue) -> Option<f64> {
+    match value {
+        ASTValue::Int(n) => Some(*n as f64),
+        ASTValue::Int64(n) => Some(*n as f64),
+        ASTValue::UInt8(n) => Some(*n as f64),
+        ASTValue::UInt32(n) => Some(*n as f64),
+        ASTValue::UInt64(n) => Some(*n as f64),
+        ASTValue::Float32(f) => Some(*f as f64),
+        ASTValue::Float64(f) => Some(*f),
+        _ => None,
+    }
+}
+
+// The literal of type `ty` holding `value`, the way codegen would convert it
+fn convert(value: ASTValue, ty: Type) -> Result<ASTValue, String> {
+    let converted = match ty {
+        Type::I32 | Type::I64 | Type::U8 | Type::U32 | Type::U64 => as_int(&value).map(|n| wrap(n, ty)),
+        Type::F32 => as_float(&value).map(|f| ASTValue::Float32(f as f32)),
+        Type::F64 => as_float(&value).map(ASTValue::Float64),
+        Type::Bool | Type::Char | Type::Str | Type::Void => Some(value.clone()),
+    };
+    converted.ok_or_else(|| format!("{:?} is not a {}", value, ty))
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+
+// Byte range into the source text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    // Moves a file-local span into a SourceMap's global offset space
+    pub fn shifted(self, base: usize) -> Span {
+        Span {
+            start: self.start + base,
+            end: self.end + base,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    // Not every stage tracks positions yet
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match self.span {
+            Some(span) => write!(f, "{} at {}..{}: {}", label, span.start, span.end, self.message),
+            None => write!(f, "{}: {}", label, self.message),
+        }
+    }
+}
+
+// Everything reported during one compilation. Stages keep going after an
+// error where they can, so callers (the CLI, IDE integrations) see all of
+// the problems at once instead of just the first.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Diagnostics { items: Vec::new() }
+    }
+
+    pub fn error(&mut self, message: impl Into<String>, span: Option<Span>) {
+        self.items.push(Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+        });
+    }
+
+    pub fn warning(&mut self, message: impl Into<String>, span: Option<Span>) {
+        self.items.push(Diagnostic {
+            severity: Severity::Warning,
+            message: message.into(),
+            span,
+        });
+    }
+
+    pub fn extend(&mut self, other: Diagnostics) {
+        self.items.extend(other.items);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.items.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.items.iter().filter(|d| d.severity == Severity::Error).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    // Ordered by position; diagnostics without a span go last, and the sort
+    // is stable so those keep the order they were reported in
+    pub fn sorted(&self) -> Vec<&Diagnostic> {
+        let mut items: Vec<&Diagnostic> = self.items.iter().collect();
+        items.sort_by_key(|d| (d.span.is_none(), d.span));
+        items
+    }
+}
@@ -0,0 +1,256 @@
+use crate::modules::diagnostics::Span;
+use crate::modules::parser::token_to_type_string;
+use crate::modules::tokenizer::Token;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    Function(String),
+    // `fn name` inside `impl ty`
+    Method { ty: String, name: String },
+    // `function` is `ty.method` for a method's parameters and locals
+    Local { function: String, name: String },
+}
+
+impl Symbol {
+    pub fn name(&self) -> &str {
+        match self {
+            Symbol::Function(name) => name,
+            Symbol::Method { name, .. } => name,
+            Symbol::Local { name, .. } => name,
+        }
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Symbol::Function(name) => write!(f, "{}", name),
+            Symbol::Method { ty, name } => write!(f, "{}.{}", ty, name),
+            Symbol::Local { function, name } => write!(f, "{}::{}", function, name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SymbolEntry {
+    pub definition: Option<Span>,
+    pub references: Vec<Span>,
+}
+
+// Definitions and uses of every function and local, resolved from the spanned
+// token stream. Scoping follows codegen: one flat scope per function holding
+// its parameters and every `let` in its body. Methods are indexed where
+// they're defined, but not where they're called: which type `x.len()`
+// calls it on takes types, and the tokens don't have them.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolIndex {
+    entries: HashMap<Symbol, SymbolEntry>,
+    // Every occurrence (definition or use) in source order, for offset lookups
+    occurrences: Vec<(Span, Symbol)>,
+}
+
+impl SymbolIndex {
+    pub fn build(tokens: &[(Token, Span)]) -> Self {
+        let mut index = SymbolIndex::default();
+
+        let owners = impl_owners(tokens);
+
+        // Functions can be called before they are defined, so find them all first
+        let mut functions = HashSet::new();
+        for (i, (tok, _)) in tokens.iter().enumerate() {
+            if *tok == Token::Func && !owners.contains_key(&i) {
+                if let Some((Token::Ident(name), _)) = tokens.get(i + 1) {
+                    functions.insert(name.clone());
+                }
+            }
+        }
+
+        let mut current: Option<String> = None;
+        let mut pending: Option<String> = None;
+        let mut in_params = false;
+        let mut locals: HashSet<String> = HashSet::new();
+        let mut depth = 0;
+        // Depth of the braces around a function body: 1 for a method
+        let mut outer = 0;
+
+        for (i, (tok, span)) in tokens.iter().enumerate() {
+            let prev = if i > 0 { Some(&tokens[i - 1].0) } else { None };
+            let next = tokens.get(i + 1).map(|(t, _)| t);
+
+            match tok {
+                Token::LParen if pending.is_some() && depth == outer => in_params = true,
+                Token::RParen if in_params => in_params = false,
+                Token::LBrace => {
+                    if depth == outer && pending.is_some() {
+                        current = pending.take();
+                        in_params = false;
+                    }
+                    depth += 1;
+                }
+                Token::RBrace => {
+                    depth -= 1;
+                    if depth == outer {
+                        current = None;
+                        locals.clear();
+                    }
+                    if depth == 0 {
+                        outer = 0;
+                    }
+                }
+                Token::Impl if depth == 0 => outer = 1,
+                Token::Ident(name) => {
+                    // `console.print`: neither the object nor the method is ours
+                    if prev == Some(&Token::Dot) || next == Some(&Token::Dot) {
+                        continue;
+                    }
+
+                    if prev == Some(&Token::Func) {
+                        match owners.get(&(i - 1)) {
+                            Some(ty) => {
+                                let symbol = Symbol::Method {
+                                    ty: ty.clone(),
+                                    name: name.to_string(),
+                                };
+                                index.add(symbol, *span, true);
+                                pending = Some(format!("{}.{}", ty, name));
+                            }
+                            None => {
+                                index.add(Symbol::Function(name.to_string()), *span, true);
+                                pending = Some(name.to_string());
+                            }
+                        }
+                        locals.clear();
+                        continue;
+                    }
+
+                    // `type: name` inside a parameter list
+                    if in_params && prev == Some(&Token::Colon) {
+                        if let Some(function) = &pending {
+                            let symbol = Symbol::Local {
+                                function: function.clone(),
+                                name: name.to_string(),
+                            };
+                            index.add(symbol, *span, true);
+                            locals.insert(name.to_string());
+                        }
+                        continue;
+                    }
+
+                    let Some(function) = &current else {
+                        continue;
+                    };
+
+                    // `let [mut] [type] name =`, `const [type] name =` or
+                    // `for name in`
+                    let is_decl = (next == Some(&Token::Eq)
+                        && tokens[i.saturating_sub(3)..i]
+                            .iter()
+                            .any(|(token, _)| matches!(token, Token::Let | Token::Const)))
+                        || prev == Some(&Token::For);
+                    if is_decl {
+                        let symbol = Symbol::Local {
+                            function: function.clone(),
+                            name: name.to_string(),
+                        };
+                        index.add(symbol, *span, true);
+                        locals.insert(name.to_string());
+                        continue;
+                    }
+
+                    // Locals shadow functions unless the name is being called
+                    if next != Some(&Token::LParen) && locals.contains(&**name) {
+                        let symbol = Symbol::Local {
+                            function: function.clone(),
+                            name: name.to_string(),
+                        };
+                        index.add(symbol, *span, false);
+                    } else if functions.contains(name) {
+                        index.add(Symbol::Function(name.to_string()), *span, false);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        index
+    }
+
+    fn add(&mut self, symbol: Symbol, span: Span, is_definition: bool) {
+        let entry = self.entries.entry(symbol.clone()).or_default();
+        if is_definition && entry.definition.is_none() {
+            entry.definition = Some(span);
+        } else {
+            entry.references.push(span);
+        }
+        self.occurrences.push((span, symbol));
+    }
+
+    pub fn get(&self, symbol: &Symbol) -> Option<&SymbolEntry> {
+        self.entries.get(symbol)
+    }
+
+    pub fn definition(&self, symbol: &Symbol) -> Option<Span> {
+        self.entries.get(symbol)?.definition
+    }
+
+    pub fn references(&self, symbol: &Symbol) -> &[Span] {
+        self.entries
+            .get(symbol)
+            .map(|e| e.references.as_slice())
+            .unwrap_or(&[])
+    }
+
+    // The symbol whose name covers `offset`, for go-to-definition
+    pub fn symbol_at(&self, offset: usize) -> Option<&Symbol> {
+        self.occurrences
+            .iter()
+            .find(|(span, _)| span.start <= offset && offset < span.end)
+            .map(|(_, symbol)| symbol)
+    }
+
+    // Looks a symbol up by user-facing name: `foo` matches the function, the
+    // methods and every local called foo, `i32.foo` only that method and
+    // `foo::x` only the local x inside foo
+    pub fn lookup(&self, query: &str) -> Vec<(&Symbol, &SymbolEntry)> {
+        let mut found: Vec<(&Symbol, &SymbolEntry)> = self
+            .entries
+            .iter()
+            .filter(|(symbol, _)| match query.split_once("::") {
+                Some((function, name)) => {
+                    matches!(symbol, Symbol::Local { function: f, name: n } if f == function && n == name)
+                }
+                None => symbol.name() == query || symbol.to_string() == query,
+            })
+            .collect();
+        found.sort_by_key(|(_, entry)| entry.definition);
+        found
+    }
+}
+
+// The type each `fn` token inside an `impl` block belongs to, by token index
+fn impl_owners(tokens: &[(Token, Span)]) -> HashMap<usize, String> {
+    let mut owners = HashMap::new();
+    let mut owner: Option<String> = None;
+    let mut depth = 0;
+    for (i, (tok, _)) in tokens.iter().enumerate() {
+        match tok {
+            Token::Impl if depth == 0 => owner = tokens.get(i + 1).and_then(|(ty, _)| token_to_type_string(ty)),
+            Token::LBrace => depth += 1,
+            Token::RBrace => {
+                depth -= 1;
+                if depth == 0 {
+                    owner = None;
+                }
+            }
+            Token::Func if depth == 1 => {
+                if let Some(ty) = &owner {
+                    owners.insert(i, ty.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    owners
+}
@@ -0,0 +1,92 @@
+use crate::modules::typeck::{FunctionSig, TypeInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Bumped whenever the layout changes so stale files are rejected instead of
+// misread
+const FORMAT_VERSION: u32 = 2;
+
+// Everything another module needs to type-check calls into this one, written
+// next to the source as `<module>.magi` after a successful compile. `use foo`
+// reads foo.magi instead of reparsing foo.mag.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleInterface {
+    pub version: u32,
+    pub module: String,
+    // Sorted so rebuilding an unchanged module leaves the file byte-identical
+    pub functions: BTreeMap<String, FunctionSig>,
+    // Whether the module's object defines init_symbol(module): set when it
+    // has an init() or imports a module that does
+    pub init: bool,
+}
+
+// A module's `init()` runs before main. It isn't exported itself, since
+// every module may have one; instead each module that needs initializing
+// exports this wrapper, which runs the module's imports' wrappers (in `use`
+// order) and then its init, once.
+pub const INIT_FN: &str = "init";
+
+pub fn init_symbol(module: &str) -> String {
+    format!("__magolor_init_{}", module)
+}
+
+// Whether a function's symbol is visible outside its module's object: main,
+// and anything `pub` except init
+pub fn is_exported(name: &str, public: bool) -> bool {
+    (public || name == "main") && name != INIT_FN
+}
+
+impl ModuleInterface {
+    // Only the `pub` functions defined in this module; main and init are run
+    // by the program rather than being part of its interface, even if `pub`
+    pub fn from_types(module: &str, types: &TypeInfo, imports: &[ModuleInterface]) -> Self {
+        let functions = types
+            .functions
+            .iter()
+            .filter(|(name, _)| {
+                types.public.contains(*name)
+                    && !matches!(name.as_str(), "main" | INIT_FN)
+                    && !types.imported.contains_key(*name)
+            })
+            .map(|(name, sig)| (name.clone(), sig.clone()))
+            .collect();
+
+        ModuleInterface {
+            version: FORMAT_VERSION,
+            module: module.to_string(),
+            functions,
+            init: types.functions.contains_key(INIT_FN) || imports.iter().any(|i| i.init),
+        }
+    }
+
+    // `dir/foo.mag` -> `dir/foo.magi`
+    pub fn path_for(source: &str) -> PathBuf {
+        Path::new(source).with_extension("magi")
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read interface {}: {}", path.display(), e))?;
+        let interface: ModuleInterface = serde_json::from_str(&text)
+            .map_err(|e| format!("Malformed interface {}: {}", path.display(), e))?;
+        if interface.version != FORMAT_VERSION {
+            return Err(format!(
+                "interface {} is format version {}, expected {}; recompile '{}'",
+                path.display(),
+                interface.version,
+                FORMAT_VERSION,
+                interface.module
+            ));
+        }
+        Ok(interface)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize interface: {}", e))?;
+        fs::write(path, text)
+            .map_err(|e| format!("Failed to write interface {}: {}", path.display(), e))
+    }
+}
@@ -1,13 +1,21 @@
-use crate::modules::tokenizer::Token;
+use crate::modules::diagnostics::Span;
+use crate::modules::tokenizer::{Token, TokenStream, spelling};
 
 #[derive(Debug, Clone)]
 pub enum ASTValue {
     Str(String),
     Int(i32),
     Int64(i64),
+    // Only ever written with a suffix, so always exactly that type
+    UInt8(u8),
+    UInt32(u32),
+    UInt64(u64),
     Float32(f32),
     Float64(f64),
     Bool(bool),
+    Char(u8),
+    // The absent value; nothing has a type it fits until optionals exist
+    Null,
     VarRef(String),
     FuncCall {
         name: String,
@@ -22,19 +30,52 @@ EqualEqual(Box<ASTValue>, Box<ASTValue>),
     NotEqual(Box<ASTValue>, Box<ASTValue>),
     LessEqual(Box<ASTValue>, Box<ASTValue>),
     GreaterEqual(Box<ASTValue>, Box<ASTValue>),
+
+    // Arithmetic on two numbers of the same type
+    Add(Box<ASTValue>, Box<ASTValue>),
+    Sub(Box<ASTValue>, Box<ASTValue>),
+    Mul(Box<ASTValue>, Box<ASTValue>),
+    Div(Box<ASTValue>, Box<ASTValue>),
+    Rem(Box<ASTValue>, Box<ASTValue>),
+    // Unary minus
+    Neg(Box<ASTValue>),
+
+    // Logic on bools; `&&` and `||` don't evaluate the right side if the
+    // left decides the result
+    And(Box<ASTValue>, Box<ASTValue>),
+    Or(Box<ASTValue>, Box<ASTValue>),
+    Not(Box<ASTValue>),
+}
+
+// `#[name]` or `#[name(arg, ...)]` attached to a function
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+// How a variable declaration binds its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutability {
+    // `let`
+    Immutable,
+    // `let mut`
+    Mutable,
+    // `const`: the value must be known at compile time
+    Const,
 }
 
 #[derive(Debug, Clone)]
 pub enum AST {
     Import(String),
-    VarDecl(String, String, ASTValue),
+    // type, name, value
+    VarDecl(String, String, ASTValue, Mutability),
     VarRef(String),
     Literal(ASTValue),
-    NewLine,
     Call {
         object: String,
         method: String,
-        args: Vec<AST>,
+        args: Vec<ASTValue>,
     },
     Return(ASTValue),
     FuncDef {
@@ -42,6 +83,10 @@ pub enum AST {
         params: Vec<(String, String)>,
         return_type: Option<String>,
         body: Vec<AST>,
+        attributes: Vec<Attribute>,
+        // `pub`: part of the module's interface. Anything else is only
+        // callable from inside the module.
+        public: bool,
     },
     If {
         condition: ASTValue,
@@ -49,825 +94,751 @@ pub enum AST {
         elif_branches: Vec<(ASTValue, Vec<AST>)>, // (condition, body) pairs
         else_body: Option<Vec<AST>>,
     },
+    // Runs the body for as long as the condition holds, checking it first
+    While {
+        condition: ASTValue,
+        body: Vec<AST>,
+    },
+    // `for variable in start..end`: the body runs with the variable set to
+    // start, start + 1, ... up to but not including end, which is evaluated
+    // once before the first pass
+    For {
+        variable: String,
+        start: ASTValue,
+        end: ASTValue,
+        body: Vec<AST>,
+    },
 }
 
 // Helper function to convert token to type string
-fn token_to_type_string(token: &Token) -> Option<String> {
+pub fn token_to_type_string(token: &Token) -> Option<String> {
     match token {
-        Token::Ident(name) => Some(name.clone()),
+        Token::Ident(name) => Some(name.to_string()),
         Token::I32Type => Some("i32".to_string()),
         Token::I64Type => Some("i64".to_string()),
+        Token::U8Type => Some("u8".to_string()),
+        Token::U32Type => Some("u32".to_string()),
+        Token::U64Type => Some("u64".to_string()),
         Token::F32Type => Some("f32".to_string()),
         Token::F64Type => Some("f64".to_string()),
         Token::StringType => Some("string".to_string()),
         Token::BoolType => Some("bool".to_string()),
+        Token::CharType => Some("char".to_string()),
         _ => None,
     }
 }
 
 // Helper function to parse let statements (used in both top-level and function body)
-fn parse_let_statement(tokens: &[Token], start_index: usize) -> Result<(AST, usize), String> {
-    let mut i = start_index + 1; // skip 'let'
-    
-    if i >= tokens.len() {
-        return Err("Unexpected end of tokens after 'let'".to_string());
-    }
-    
-    // Get first token after 'let' - could be type or variable name
-    let first_token = &tokens[i];
+// `let [mut] [type] name = value` or `const [type] name = value`
+fn parse_let_statement(tokens: &mut TokenStream) -> Result<AST, String> {
+    let (keyword, mutability) = match tokens.next() {
+        Some(Token::Const) => ("const", Mutability::Const),
+        _ if tokens.eat(&Token::Mut) => ("let mut", Mutability::Mutable),
+        _ => ("let", Mutability::Immutable),
+    };
+
+    // Get first token after the keyword - could be type or variable name
+    let first_token = tokens
+        .next()
+        .ok_or_else(|| format!("Unexpected end of tokens after '{}'", keyword))?;
     let first_string = token_to_type_string(first_token)
-        .ok_or_else(|| format!("Expected identifier or type after 'let', but found: {:?}", first_token))?;
-    i += 1;
-    
-    if i >= tokens.len() {
-        return Err(format!("Unexpected end of tokens after 'let {}'", first_string));
-    }
-    
-    match &tokens[i] {
-        Token::Ident(second_ident) => {
+        .ok_or_else(|| format!("Expected identifier or type after '{}', but found: {:?}", keyword, first_token))?;
+
+    match tokens.next() {
+        Some(Token::Ident(second_ident)) => {
             // Check for '=' after the second identifier
-            i += 1;
-            if i < tokens.len() && tokens[i] == Token::Eq {
+            if tokens.eat(&Token::Eq) {
                 // Format: let type name = value
-                i += 1; // skip '='
-                let mut k = i;
-                match parse_value(tokens, &mut k) {
-                    Ok(value) => {
-                        let ast_node = AST::VarDecl(first_string, second_ident.clone(), value);
-                        Ok((ast_node, k))
-                    }
+                match parse_expression(tokens) {
+                    Ok(value) => Ok(AST::VarDecl(first_string, second_ident.to_string(), value, mutability)),
                     Err(e) => Err(format!("Error parsing variable declaration value: {}", e)),
                 }
             } else {
                 Err(format!("Expected '=' after variable type '{}' and name '{}'", first_string, second_ident))
             }
         }
-        Token::Eq => {
+        Some(Token::Eq) => {
             // Format: let name = value (infer type)
-            i += 1; // skip '='
-            let mut k = i;
-            match parse_value(tokens, &mut k) {
+            match parse_expression(tokens) {
                 Ok(value) => {
                     // Infer type from value
                     let ty = match &value {
                         ASTValue::Int(_) => "i32".to_string(),
                         ASTValue::Int64(_) => "i64".to_string(),
+                        ASTValue::UInt8(_) => "u8".to_string(),
+                        ASTValue::UInt32(_) => "u32".to_string(),
+                        ASTValue::UInt64(_) => "u64".to_string(),
                         ASTValue::Float32(_) => "f32".to_string(),
                         ASTValue::Float64(_) => "f64".to_string(),
                         ASTValue::Str(_) => "string".to_string(),
                         ASTValue::Bool(_) => "bool".to_string(),
+                        ASTValue::Char(_) => "char".to_string(),
                         ASTValue::VarRef(_) => "auto".to_string(),
                         ASTValue::FuncCall { .. } => "auto".to_string(),
                         _ => "auto".to_string(),
                     };
-                    let ast_node = AST::VarDecl(ty, first_string, value);
-                    Ok((ast_node, k))
+                    Ok(AST::VarDecl(ty, first_string, value, mutability))
                 }
                 Err(e) => Err(format!("Error parsing variable declaration value: {}", e)),
             }
         }
-        _ => {
-            Err(format!("Expected identifier or '=' after 'let {}', but found: {:?}", first_string, tokens[i]))
+        Some(other) => {
+            Err(format!("Expected identifier or '=' after '{} {}', but found: {:?}", keyword, first_string, other))
         }
+        None => Err(format!("Unexpected end of tokens after '{} {}'", keyword, first_string)),
     }
 }
 
-// Helper function to parse comparison expressions
-fn parse_condition(tokens: &[Token], index: &mut usize) -> Result<ASTValue, String> {
-    // Check for opening parenthesis
-    let has_paren = if *index < tokens.len() && tokens[*index] == Token::LParen {
-        *index += 1; // skip '('
-        true
-    } else {
-        false
-    };
-    
-    // Parse left side
-    let left = parse_value(tokens, index)?;
-    
-    // Check for comparison operator
-    if *index >= tokens.len() {
-        if has_paren {
-            return Err("Expected closing ')' after condition".to_string());
-        }
-        return Ok(left);
-    }
-    
-    let condition = match &tokens[*index] {
-        Token::Less => {
-            *index += 1;
-            let right = parse_value(tokens, index)?;
-            ASTValue::LessThan(Box::new(left), Box::new(right))
-        }
-        Token::Greater => {
-            *index += 1;
-            let right = parse_value(tokens, index)?;
-            ASTValue::GreaterThan(Box::new(left), Box::new(right))
-        }
-        Token::Eq => {
-            *index += 1;
-            let right = parse_value(tokens, index)?;
-            ASTValue::Equal(Box::new(left), Box::new(right))
-        }
-        Token::EqEq => {
-            *index += 1;
-            let right = parse_value(tokens, index)?;
-            ASTValue::EqualEqual(Box::new(left),Box::new(right))
-        }
-        Token::NotEq => {
-            *index += 1;
-            let right = parse_value(tokens, index)?;
-            ASTValue::NotEqual(Box::new(left), Box::new(right))
-        }
-        Token::LessEq => {
-            *index += 1;
-            let right = parse_value(tokens, index)?;
-            ASTValue::LessEqual(Box::new(left), Box::new(right))
-        }
-        Token::GreaterEq => {
-            *index += 1;
-            let right = parse_value(tokens, index)?;
-            ASTValue::GreaterEqual(Box::new(left), Box::new(right))
-        }
-        _ => left, // No comparison operator, just return the value
-    };
-    
-    // Check for closing parenthesis if we had an opening one
-    if has_paren {
-        if *index >= tokens.len() || tokens[*index] != Token::RParen {
-            return Err("Expected closing ')' after condition".to_string());
-        }
-        *index += 1; // skip ')'
-    }
-    
-    Ok(condition)
-}
-
 // Helper function to parse if statements with elif support
-fn parse_if_statement(tokens: &[Token], start_index: usize) -> Result<(AST, usize), String> {
-    let mut i = start_index + 1; // skip 'if'
-    
-    if i >= tokens.len() {
+fn parse_if_statement(tokens: &mut TokenStream) -> Result<AST, String> {
+    tokens.next(); // skip 'if'
+
+    if tokens.peek().is_none() {
         return Err("Unexpected end of tokens after 'if'".to_string());
     }
-    
+
     // Parse condition (with potential parentheses)
-    let condition = parse_condition(tokens, &mut i)?;
-    
-    // Expect '{'
-    if i >= tokens.len() || tokens[i] != Token::LBrace {
-        return Err("Expected '{' after if condition".to_string());
-    }
-    i += 1; // skip '{'
-    
-    // Parse then body
-    let mut then_body = Vec::new();
-    let mut brace_count = 1;
-    
-    while i < tokens.len() && brace_count > 0 {
-        match &tokens[i] {
-            Token::LBrace => {
-                brace_count += 1;
-                i += 1;
-            }
-            Token::RBrace => {
-                brace_count -= 1;
-                if brace_count > 0 {
-                    i += 1;
-                }
-            }
-            _ => {
-                match parse_single_statement(tokens, &mut i) {
-                    Ok(Some(node)) => then_body.push(node),
-                    Ok(None) => {},
-                    Err(e) => return Err(format!("Error in if body: {}", e)),
-                }
-            }
-        }
-    }
-    
-    if brace_count != 0 {
-        return Err("Unmatched braces in if statement".to_string());
-    }
-    
-    i += 1; // skip closing '}'
-    
+    let condition = parse_expression(tokens)?;
+    tokens.expect(Token::LBrace, "after if condition")?;
+    let then_body = parse_block(tokens, "if body", "if statement")?;
+
     // Parse elif branches
     let mut elif_branches = Vec::new();
-    
-    while i < tokens.len() && tokens[i] == Token::Elif {
-        i += 1; // skip 'elif'
-        
-        // Parse elif condition
-        let elif_condition = parse_condition(tokens, &mut i)?;
-        
-        // Expect '{'
-        if i >= tokens.len() || tokens[i] != Token::LBrace {
-            return Err("Expected '{' after elif condition".to_string());
-        }
-        i += 1; // skip '{'
-        
-        // Parse elif body
-        let mut elif_body = Vec::new();
-        let mut brace_count = 1;
-        
-        while i < tokens.len() && brace_count > 0 {
-            match &tokens[i] {
-                Token::LBrace => {
-                    brace_count += 1;
-                    i += 1;
-                }
-                Token::RBrace => {
-                    brace_count -= 1;
-                    if brace_count > 0 {
-                        i += 1;
-                    }
-                }
-                _ => {
-                    match parse_single_statement(tokens, &mut i) {
-                        Ok(Some(node)) => elif_body.push(node),
-                        Ok(None) => {},
-                        Err(e) => return Err(format!("Error in elif body: {}", e)),
-                    }
-                }
-            }
-        }
-        
-        if brace_count != 0 {
-            return Err("Unmatched braces in elif statement".to_string());
-        }
-        
-        i += 1; // skip closing '}'
+    while tokens.eat(&Token::Elif) {
+        let elif_condition = parse_expression(tokens)?;
+        tokens.expect(Token::LBrace, "after elif condition")?;
+        let elif_body = parse_block(tokens, "elif body", "elif statement")?;
         elif_branches.push((elif_condition, elif_body));
     }
-    
+
     // Check for else
-    let else_body = if i < tokens.len() && tokens[i] == Token::Else {
-        i += 1; // skip 'else'
-        
-        if i >= tokens.len() || tokens[i] != Token::LBrace {
-            return Err("Expected '{' after 'else'".to_string());
-        }
-        i += 1; // skip '{'
-        
-        let mut else_statements = Vec::new();
-        let mut brace_count = 1;
-        
-        while i < tokens.len() && brace_count > 0 {
-            match &tokens[i] {
-                Token::LBrace => {
-                    brace_count += 1;
-                    i += 1;
-                }
-                Token::RBrace => {
-                    brace_count -= 1;
-                    if brace_count > 0 {
-                        i += 1;
-                    }
-                }
-                _ => {
-                    match parse_single_statement(tokens, &mut i) {
-                        Ok(Some(node)) => else_statements.push(node),
-                        Ok(None) => {},
-                        Err(e) => return Err(format!("Error in else body: {}", e)),
-                    }
-                }
-            }
-        }
-        
-        if brace_count != 0 {
-            return Err("Unmatched braces in else statement".to_string());
-        }
-        
-        i += 1; // skip closing '}'
-        Some(else_statements)
+    let else_body = if tokens.eat(&Token::Else) {
+        tokens.expect(Token::LBrace, "after 'else'")?;
+        Some(parse_block(tokens, "else body", "else statement")?)
     } else {
         None
     };
-    
-    let if_node = AST::If {
+
+    Ok(AST::If {
         condition,
         then_body,
         elif_branches,
         else_body,
-    };
-    
-    Ok((if_node, i))
+    })
 }
 
-// Helper function to parse a single AST node (for reuse in function bodies and top-level)
-fn parse_single_statement(tokens: &[Token], i: &mut usize) -> Result<Option<AST>, String> {
-    if *i >= tokens.len() {
-        return Ok(None);
+// `while cond { ... }`, the condition parenthesized or not, as for `if`
+fn parse_while_statement(tokens: &mut TokenStream) -> Result<AST, String> {
+    tokens.next(); // skip 'while'
+
+    if tokens.peek().is_none() {
+        return Err("Unexpected end of tokens after 'while'".to_string());
     }
 
-    match &tokens[*i] {
-        Token::Let => {
-            match parse_let_statement(tokens, *i) {
-                Ok((ast_node, new_index)) => {
-                    *i = new_index;
-                    Ok(Some(ast_node))
+    let condition = parse_expression(tokens)?;
+    tokens.expect(Token::LBrace, "after while condition")?;
+    let body = parse_block(tokens, "while body", "while loop")?;
+    Ok(AST::While { condition, body })
+}
+
+// `for i in start..end { ... }`
+fn parse_for_statement(tokens: &mut TokenStream) -> Result<AST, String> {
+    tokens.next(); // skip 'for'
+
+    let variable = match tokens.next() {
+        Some(Token::Ident(name)) => name.to_string(),
+        other => return Err(format!("Expected loop variable after 'for', got: {:?}", other)),
+    };
+    tokens.expect(Token::In, "after the loop variable")?;
+    let start = parse_expression(tokens)?;
+    tokens.expect(Token::DotDot, "between the bounds of the range")?;
+    let end = parse_expression(tokens)?;
+    tokens.expect(Token::LBrace, "after for range")?;
+    let body = parse_block(tokens, "for body", "for loop")?;
+    Ok(AST::For {
+        variable,
+        start,
+        end,
+        body,
+    })
+}
+
+// The statements of a block whose '{' has been consumed, through the '}'
+// that closes it; stray braces in between nest. `body` and `statement` name
+// the block in errors ("Error in if body", "Unmatched braces in if statement").
+fn parse_block(tokens: &mut TokenStream, body: &str, statement: &str) -> Result<Vec<AST>, String> {
+    let mut statements = Vec::new();
+    let mut depth = 1;
+
+    loop {
+        match tokens.peek() {
+            Some(Token::LBrace) => {
+                depth += 1;
+                tokens.next();
+            }
+            Some(Token::RBrace) => {
+                tokens.next();
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(statements);
                 }
-                Err(e) => Err(e),
             }
+            Some(_) => match parse_single_statement(tokens) {
+                Ok(Some(node)) => statements.push(node),
+                Ok(None) => {} // Skip unhandled tokens
+                Err(e) => return Err(format!("Error in {}: {}", body, e)),
+            },
+            None => return Err(format!("Unmatched braces in {}", statement)),
         }
+    }
+}
+
+// The value of a literal token; an unsuffixed float is an f32
+fn literal(token: &Token) -> Option<ASTValue> {
+    let value = match token {
+        Token::String(s) => ASTValue::Str(s.clone()),
+        // Too big for an i32 makes it an i64
+        Token::Integer(n) => i32::try_from(*n).map_or(ASTValue::Int64(*n), ASTValue::Int),
+        Token::Integer64(n) => ASTValue::Int64(*n),
+        Token::UInt8(n) => ASTValue::UInt8(*n),
+        Token::UInt32(n) => ASTValue::UInt32(*n),
+        Token::UInt64(n) => ASTValue::UInt64(*n),
+        Token::Float32(f) | Token::DefaultFloat(f) => ASTValue::Float32(*f),
+        Token::Float64(f) => ASTValue::Float64(*f),
+        Token::Bool(b) => ASTValue::Bool(*b),
+        Token::Char(c) => ASTValue::Char(*c),
+        Token::Null => ASTValue::Null,
+        _ => return None,
+    };
+    Some(value)
+}
+
+// Helper function to parse a single AST node (for reuse in function bodies and top-level)
+fn parse_single_statement(tokens: &mut TokenStream) -> Result<Option<AST>, String> {
+    let token = match tokens.peek() {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+
+    match token {
+        Token::Let | Token::Const => parse_let_statement(tokens).map(Some),
         Token::Semicolon => {
-            *i += 1;
-            Ok(Some(AST::NewLine))
+            // Statement separators carry no meaning once parsed
+            tokens.next();
+            Ok(None)
         }
         Token::Use => {
-            if let Some(Token::Ident(package)) = tokens.get(*i + 1) {
-                let ast_node = AST::Import(package.clone());
-                *i += 2;
-                Ok(Some(ast_node))
-            } else {
-                Err("Invalid import statement - expected package name".to_string())
+            tokens.next(); // skip 'use'
+            match tokens.next() {
+                Some(Token::Ident(package)) => Ok(Some(AST::Import(package.to_string()))),
+                _ => Err("Invalid import statement - expected package name".to_string()),
             }
         }
-        Token::If => {
-            match parse_if_statement(tokens, *i) {
-                Ok((ast_node, new_index)) => {
-                    *i = new_index;
-                    Ok(Some(ast_node))
-                }
-                Err(e) => Err(e),
-            }
+        Token::If => parse_if_statement(tokens).map(Some),
+        Token::While => parse_while_statement(tokens).map(Some),
+        Token::For => parse_for_statement(tokens).map(Some),
+        Token::In | Token::Loop | Token::Break | Token::Continue => {
+            Err(format!("{} is reserved for loops, but only `while` and `for` are supported so far", spelling(token)))
+        }
+        Token::Struct | Token::Enum => {
+            Err(format!("{} is reserved for user-defined types, which aren't supported yet", spelling(token)))
         }
+        Token::Match => Err("'match' is reserved for pattern matching, which isn't supported yet".to_string()),
+        Token::Impl => Err("impl blocks can only appear at the top level".to_string()),
         Token::Return => {
-            *i += 1;
-            if let Some(val_token) = tokens.get(*i) {
-                let ret_expr = match val_token {
-                    Token::Integer(n) => {
-                        *i += 1;
-                        AST::Return(ASTValue::Int(*n))
-                    }
-                    Token::Integer64(n) => {
-                        *i += 1;
-                        AST::Return(ASTValue::Int64(*n))
-                    }
-                    Token::Float32(f) => {
-                        *i += 1;
-                        AST::Return(ASTValue::Float32(*f))
-                    }
-                    Token::Float64(f) => {
-                        *i += 1;
-                        AST::Return(ASTValue::Float64(*f))
-                    }
-                    Token::DefaultFloat(f) => {
-                        *i += 1;
-                        AST::Return(ASTValue::Float32(*f))
-                    }
-                    Token::String(s) => {
-                        *i += 1;
-                        AST::Return(ASTValue::Str(s.clone()))
-                    }
-                    Token::Bool(b) => {
-                        *i += 1;
-                        AST::Return(ASTValue::Bool(*b))
-                    }
-                    Token::Ident(name) => {
-                        // Check if it's a function call
-                        if tokens.get(*i + 1) == Some(&Token::LParen) {
-                            let mut k = *i;
-                            match parse_value(tokens, &mut k) {
-                                Ok(func_call_value) => {
-                                    *i = k;
-                                    AST::Return(func_call_value)
-                                }
-                                Err(_) => {
-                                    *i += 1;
-                                    AST::Return(ASTValue::VarRef(name.clone()))
-                                }
-                            }
-                        } else {
-                            *i += 1;
-                            AST::Return(ASTValue::VarRef(name.clone()))
-                        }
-                    }
-                    _ => return Err(format!("Unexpected token after 'return': {:?}", val_token)),
-                };
-                Ok(Some(ret_expr))
-            } else {
-                Err("Expected value after 'return'".to_string())
+            tokens.next(); // skip 'return'
+            if tokens.peek().is_none() {
+                return Err("Expected value after 'return'".to_string());
             }
+            let value = parse_expression(tokens).map_err(|e| format!("Error parsing return value: {}", e))?;
+            Ok(Some(AST::Return(value)))
         }
         // Handle method calls
-        Token::Ident(obj) if tokens.get(*i + 1) == Some(&Token::Dot) => {
-            if let Some(Token::Ident(method)) = tokens.get(*i + 2) {
-                if tokens.get(*i + 3) == Some(&Token::LParen) {
-                    let mut args = Vec::new();
-                    let mut j = *i + 4;
-
-                    while let Some(tok) = tokens.get(j) {
-                        match tok {
-                            Token::RParen => break,
-                            Token::String(s) => args.push(AST::Literal(ASTValue::Str(s.clone()))),
-                            Token::Integer(n) => args.push(AST::Literal(ASTValue::Int(*n))),
-                            Token::Integer64(n) => args.push(AST::Literal(ASTValue::Int64(*n))),
-                            Token::Float32(f) => args.push(AST::Literal(ASTValue::Float32(*f))),
-                            Token::Float64(f) => args.push(AST::Literal(ASTValue::Float64(*f))),
-                            Token::DefaultFloat(f) => args.push(AST::Literal(ASTValue::Float32(*f))),
-                            Token::Bool(b) => args.push(AST::Literal(ASTValue::Bool(*b))),
-                            Token::Ident(name) => args.push(AST::VarRef(name.clone())),
-                            Token::Comma => {}
-                            _ => return Err(format!("Unexpected token in method call args: {:?}", tok)),
-                        }
-                        j += 1;
-                    }
-
-                    if tokens.get(j) != Some(&Token::RParen) {
-                        return Err("Expected closing paren for method call".to_string());
+        Token::Ident(obj) if tokens.peek_nth(1) == Some(&Token::Dot) => {
+            tokens.advance(2); // skip the object and '.'
+            let method = match tokens.next() {
+                Some(Token::Ident(method)) => method,
+                _ => return Err(format!("Expected method after '{}.'", obj)),
+            };
+            tokens.expect(Token::LParen, &format!("after method {}", method))?;
+
+            // Any expression can be an argument
+            let mut args = Vec::new();
+            loop {
+                match tokens.peek() {
+                    None | Some(Token::RParen) => break,
+                    Some(Token::Comma) => {
+                        tokens.next();
                     }
-                    
-                    let call_node = AST::Call {
-                        object: obj.clone(),
-                        method: method.clone(),
-                        args,
-                    };
-                    *i = j + 1;
-                    Ok(Some(call_node))
-                } else {
-                    Err(format!("Expected '(' after method {}", method))
+                    Some(_) => args.push(parse_expression(tokens)?),
                 }
-            } else {
-                Err(format!("Expected method after '{}.'", obj))
             }
+            tokens.expect(Token::RParen, "to close method call")?;
+
+            Ok(Some(AST::Call {
+                object: obj.to_string(),
+                method: method.to_string(),
+                args,
+            }))
         }
         // Handle standalone variable references and literals
         Token::Ident(name) => {
-            *i += 1;
-            Ok(Some(AST::VarRef(name.clone())))
-        }
-        Token::String(s) => {
-            *i += 1;
-            Ok(Some(AST::Literal(ASTValue::Str(s.clone()))))
-        }
-        Token::Integer(n) => {
-            *i += 1;
-            Ok(Some(AST::Literal(ASTValue::Int(*n))))
-        }
-        Token::Integer64(n) => {
-            *i += 1;
-            Ok(Some(AST::Literal(ASTValue::Int64(*n))))
-        }
-        Token::Float32(f) => {
-            *i += 1;
-            Ok(Some(AST::Literal(ASTValue::Float32(*f))))
-        }
-        Token::Float64(f) => {
-            *i += 1;
-            Ok(Some(AST::Literal(ASTValue::Float64(*f))))
-        }
-        Token::DefaultFloat(f) => {
-            *i += 1;
-            Ok(Some(AST::Literal(ASTValue::Float32(*f))))
-        }
-        Token::Bool(b) => {
-            *i += 1;
-            Ok(Some(AST::Literal(ASTValue::Bool(*b))))
+            tokens.next();
+            Ok(Some(AST::VarRef(name.to_string())))
         }
         _ => {
-            // Skip unknown tokens
-            *i += 1;
-            Ok(None)
+            // A literal stands on its own; skip any other token
+            tokens.next();
+            Ok(literal(token).map(AST::Literal))
         }
     }
 }
 
-pub fn parseTokens(tokens: &[Token]) -> Vec<AST> {
-    let mut ast = Vec::with_capacity(tokens.len() / 3);
-    let mut i = 0;
-
-    while i < tokens.len() {
-        match &tokens[i] {
-            // Handle function definitions: return_type fn name(...) { ... }
-            Token::Void | Token::I32Type | Token::I64Type | Token::F32Type | Token::F64Type | Token::StringType | Token::BoolType => {
-                if tokens.get(i + 1) == Some(&Token::Func) {
-                    // This is a function definition
-                    let mut j = i;
-
-                    // Get return type
-                    let return_type = match &tokens[j] {
-                        Token::I32Type => Some("i32".to_string()),
-                        Token::I64Type => Some("i64".to_string()),
-                        Token::F32Type => Some("f32".to_string()),
-                        Token::F64Type => Some("f64".to_string()),
-                        Token::Void => Some("void".to_string()),
-                        Token::StringType => Some("string".to_string()),
-                        Token::BoolType => Some("bool".to_string()),
-                        _ => return ast, // This should not happen
-                    };
-                    j += 2; // skip return type and 'fn'/'func'
-
-                    // Function name
-                    let name = if let Some(Token::Ident(name)) = tokens.get(j) {
-                        j += 1;
-                        name.clone()
-                    } else {
-                        panic!("Expected function name after fn/func");
-                    };
+// Helper function to parse a function definition starting at the 'fn'/'func'
+// token. `receiver` is the type of an impl block the function is a method
+// of, which makes its first parameter a bare `self` of that type.
+fn parse_function_def(
+    tokens: &mut TokenStream,
+    mut return_type: Option<String>,
+    attributes: Vec<Attribute>,
+    public: bool,
+    receiver: Option<&str>,
+) -> Result<AST, String> {
+    tokens.next(); // skip 'fn'/'func'
+
+    // Function name
+    let name = match tokens.next() {
+        Some(Token::Ident(name)) => name.to_string(),
+        _ => return Err("Expected function name after fn/func".to_string()),
+    };
 
-                    // Parameter list - must start with '('
-                    let mut params = Vec::new();
-                    if tokens.get(j) != Some(&Token::LParen) {
-                        panic!("Expected '(' after function name {}", name);
-                    }
-                    j += 1;
+    // Parameter list - must start with '('
+    tokens.expect(Token::LParen, &format!("after function name {}", name))?;
 
-                    // Parse parameters in format: type: name, type: name, ...
-                    while tokens.get(j) != Some(&Token::RParen) {
-                        let param_type_token = tokens.get(j).expect("Expected parameter type");
-                        let param_type = token_to_type_string(param_type_token)
-                            .unwrap_or_else(|| panic!("Expected parameter type, got: {:?}", param_type_token));
-                        j += 1;
+    // Parse parameters in format: type: name, type: name, ...
+    let mut params = Vec::new();
+    if let Some(ty) = receiver {
+        match tokens.next() {
+            Some(Token::Ident(name)) if &**name == "self" => params.push((name.to_string(), ty.to_string())),
+            _ => return Err(format!("Expected 'self' as the first parameter of method {}", name)),
+        }
+        tokens.eat(&Token::Comma);
+    }
+    while !tokens.eat(&Token::RParen) {
+        let param_type_token = tokens
+            .next()
+            .ok_or_else(|| format!("Unexpected end of tokens in parameters of {}", name))?;
+        let param_type = token_to_type_string(param_type_token)
+            .ok_or_else(|| format!("Expected parameter type, got: {:?}", param_type_token))?;
+
+        tokens.expect(Token::Colon, &format!("after parameter type {}", param_type))?;
+
+        let param_name = match tokens.next() {
+            Some(Token::Ident(n)) => n.to_string(),
+            _ => return Err("Expected parameter name after ':'".to_string()),
+        };
+
+        params.push((param_name, param_type));
+        tokens.eat(&Token::Comma);
+    }
 
-                        if tokens.get(j) != Some(&Token::Colon) {
-                            panic!("Expected ':' after parameter type {}", param_type);
-                        }
-                        j += 1;
+    // `fn name(...) -> type`, the other way to write `type fn name(...)`
+    if tokens.eat(&Token::Arrow) {
+        let ty = match tokens.next() {
+            Some(Token::Void) => "void".to_string(),
+            Some(token) => token_to_type_string(token)
+                .ok_or_else(|| format!("Expected return type after '->' in {}, got: {:?}", name, token))?,
+            None => return Err(format!("Expected return type after '->' in {}", name)),
+        };
+        if return_type.is_some() {
+            return Err(format!("Function {} has a return type both before 'fn' and after '->'", name));
+        }
+        return_type = Some(ty);
+    }
 
-                        let param_name = if let Some(Token::Ident(n)) = tokens.get(j) {
-                            j += 1;
-                            n.clone()
-                        } else {
-                            panic!("Expected parameter name after ':'");
-                        };
+    // Body must start with '{'
+    tokens.expect(Token::LBrace, &format!("to start function body for {}", name))?;
+    let body = parse_block(tokens, &format!("function body of {}", name), &format!("function {}", name))?;
+
+    Ok(AST::FuncDef {
+        name,
+        params,
+        return_type,
+        body,
+        attributes,
+        public,
+    })
+}
 
-                        params.push((param_name, param_type));
+// After an error, skip to the end of the broken top-level item so parsing can
+// carry on with the next one. An item ends at a ';' or at the '}' closing its
+// outermost block, whichever comes first. Always consumes at least one token,
+// even a stray one at the very end.
+fn skip_item(tokens: &mut TokenStream) {
+    let mut depth = 0;
 
-                        if tokens.get(j) == Some(&Token::Comma) {
-                            j += 1; // skip comma
-                        }
-                    }
-                    j += 1; // skip closing RParen
+    for token in tokens.by_ref() {
+        match token {
+            Token::LBrace => depth += 1,
+            Token::RBrace => {
+                depth -= 1;
+                if depth <= 0 {
+                    return;
+                }
+            }
+            Token::Semicolon if depth == 0 => return,
+            _ => {}
+        }
+    }
+}
 
-                    // Body must start with '{'
-                    if tokens.get(j) != Some(&Token::LBrace) {
-                        panic!("Expected '{{' to start function body for {}", name);
-                    }
-                    j += 1;
-
-                    // Parse body
-                    let mut body = Vec::new();
-                    let mut brace_count = 1;
-                    while j < tokens.len() && brace_count > 0 {
-                        match &tokens[j] {
-                            Token::LBrace => { 
-                                brace_count += 1; 
-                                j += 1; 
-                            }
-                            Token::RBrace => { 
-                                brace_count -= 1; 
-                                if brace_count > 0 {
-                                    j += 1;
-                                }
-                            }
-                            _ => {
-                                // Use the helper function to parse statements in function body
-                                match parse_single_statement(tokens, &mut j) {
-                                    Ok(Some(node)) => body.push(node),
-                                    Ok(None) => {}, // Skip unhandled tokens
-                                    Err(e) => panic!("Error in function body: {}", e),
-                                }
-                            }
-                        }
-                    }
+// Parses a function definition at the cursor, with or without `pub` and a
+// leading return type. Returns None, consuming nothing, if there isn't one
+// there.
+fn try_parse_function(
+    tokens: &mut TokenStream,
+    attributes: Vec<Attribute>,
+    receiver: Option<&str>,
+) -> Option<Result<AST, String>> {
+    let public = tokens.eat(&Token::Pub);
+    match tokens.peek() {
+        // Handle function definitions: return_type fn name(...) { ... }
+        Some(token @ (Token::Void | Token::I32Type | Token::I64Type | Token::U8Type | Token::U32Type | Token::U64Type | Token::F32Type | Token::F64Type | Token::StringType | Token::BoolType | Token::CharType))
+            if tokens.peek_nth(1) == Some(&Token::Func) =>
+        {
+            // Get return type
+            let return_type = if matches!(token, Token::Void) {
+                Some("void".to_string())
+            } else {
+                token_to_type_string(token)
+            };
+            tokens.next();
+            Some(parse_function_def(tokens, return_type, attributes, public, receiver))
+        }
 
-                    if brace_count != 0 {
-                        panic!("Unmatched braces in function {}", name);
-                    }
+        // Handle standalone 'fn' without return type
+        Some(Token::Func) => Some(parse_function_def(tokens, None, attributes, public, receiver)),
+
+        _ if public => Some(Err("Expected a function definition after 'pub'".to_string())),
+        _ => None,
+    }
+}
 
-                    ast.push(AST::FuncDef {
-                        name,
-                        params,
-                        return_type,
-                        body,
-                    });
-
-                    i = j + 1; // +1 to skip the final closing brace
-                } else {
-                    // Not a function definition, treat as regular token
-                    match parse_single_statement(tokens, &mut i) {
-                        Ok(Some(node)) => ast.push(node),
-                        Ok(None) => {}, // Skip
-                        Err(e) => panic!("{}", e),
+// `impl type { ... }`: methods on a built-in type, called as
+// `value.method(...)` on a variable of that type. Each takes that variable
+// as `self` and becomes a function named `type.method`, which no identifier
+// can spell, so methods never clash with functions or each other's types.
+fn parse_impl(tokens: &mut TokenStream) -> Result<Vec<AST>, String> {
+    tokens.next(); // skip 'impl'
+    let ty = match tokens.next() {
+        Some(Token::Ident(name)) => return Err(format!("Expected a built-in type after 'impl', found '{}'", name)),
+        Some(token) => token_to_type_string(token),
+        None => None,
+    }
+    .ok_or("Expected a built-in type after 'impl'")?;
+    tokens.expect(Token::LBrace, &format!("to start impl {}", ty))?;
+
+    let mut methods = Vec::new();
+    while !tokens.eat(&Token::RBrace) {
+        if tokens.peek().is_none() {
+            return Err(format!("Expected '}}' to close impl {}", ty));
+        }
+        let attributes = parse_attributes(tokens)?;
+        if tokens.peek() == Some(&Token::Pub) {
+            return Err(format!("Methods can't be `pub`; only functions are exported (in impl {})", ty));
+        }
+        let mut method = match try_parse_function(tokens, attributes, Some(&ty)) {
+            Some(method) => method?,
+            None => return Err(format!("Expected a method definition in impl {}", ty)),
+        };
+        if let AST::FuncDef { name, .. } = &mut method {
+            *name = format!("{}.{}", ty, name);
+        }
+        methods.push(method);
+    }
+    Ok(methods)
+}
+
+// Helper function to parse one or more `#[name(args)]` attributes
+fn parse_attributes(tokens: &mut TokenStream) -> Result<Vec<Attribute>, String> {
+    let mut attributes = Vec::new();
+
+    while tokens.eat(&Token::HashBracket) {
+        let name = match tokens.next() {
+            Some(Token::Ident(name)) => name.to_string(),
+            // `const` is a keyword in statements but still names #[const]
+            Some(Token::Const) => "const".to_string(),
+            other => return Err(format!("Expected attribute name after '#[', found: {:?}", other)),
+        };
+
+        let mut args = Vec::new();
+        if tokens.eat(&Token::LParen) {
+            loop {
+                match tokens.next() {
+                    Some(Token::RParen) => break,
+                    Some(Token::Ident(arg)) => args.push(arg.to_string()),
+                    Some(Token::String(arg)) => args.push(arg.clone()),
+                    Some(Token::Comma) => {}
+                    other => {
+                        return Err(format!("Unexpected token in arguments of attribute {}: {:?}", name, other))
                     }
                 }
             }
-            
-            // Handle standalone 'fn' without return type
-            Token::Func => {
-                let mut j = i + 1; // skip 'fn'
-
-                // Function name
-                let name = if let Some(Token::Ident(name)) = tokens.get(j) {
-                    j += 1;
-                    name.clone()
-                } else {
-                    panic!("Expected function name after fn/func");
-                };
-
-                // Parameter parsing (same as above)
-                let mut params = Vec::new();
-                if tokens.get(j) != Some(&Token::LParen) {
-                    panic!("Expected '(' after function name {}", name);
-                }
-                j += 1;
+        }
 
-                while tokens.get(j) != Some(&Token::RParen) {
-                    let param_type_token = tokens.get(j).expect("Expected parameter type");
-                    let param_type = token_to_type_string(param_type_token)
-                        .unwrap_or_else(|| panic!("Expected parameter type, got: {:?}", param_type_token));
-                    j += 1;
+        tokens.expect(Token::RBracket, &format!("to close attribute {}", name))?;
+        attributes.push(Attribute { name, args });
+    }
 
-                    if tokens.get(j) != Some(&Token::Colon) {
-                        panic!("Expected ':' after parameter type {}", param_type);
-                    }
-                    j += 1;
+    Ok(attributes)
+}
 
-                    let param_name = if let Some(Token::Ident(n)) = tokens.get(j) {
-                        j += 1;
-                        n.clone()
-                    } else {
-                        panic!("Expected parameter name after ':'");
-                    };
+// Parses a file one top-level item at a time as its tokens come in, e.g.
+// straight from tokenizer::lex, so only the tokens of the item being parsed
+// are held at once. Each item is the nodes it parsed to, or a syntax error
+// with the span of the whole item; parsing carries on after that item.
+pub struct Items<I> {
+    lexed: I,
+    tokens: Vec<Token>,
+    spans: Vec<Span>,
+}
 
-                    params.push((param_name, param_type));
+impl<I: Iterator<Item = (Token, Span)>> Items<I> {
+    pub fn new(lexed: I) -> Self {
+        Items {
+            lexed,
+            tokens: Vec::new(),
+            spans: Vec::new(),
+        }
+    }
 
-                    if tokens.get(j) == Some(&Token::Comma) {
-                        j += 1;
+    // Reads tokens up to where skip_item would stop if it started at
+    // `tokens[from]`: enough for any item that doesn't look further, and
+    // for skipping it if it's broken. False if the input ran out first.
+    fn read_item(&mut self, from: usize) -> bool {
+        let mut depth = 0;
+        let mut at = from;
+        loop {
+            if at == self.tokens.len() {
+                match self.lexed.next() {
+                    Some((token, span)) => {
+                        self.tokens.push(token);
+                        self.spans.push(span);
                     }
+                    None => return false,
                 }
-                j += 1;
-
-                // Body parsing (same as above)
-                if tokens.get(j) != Some(&Token::LBrace) {
-                    panic!("Expected '{{' to start function body for {}", name);
-                }
-                j += 1;
-
-                let mut body = Vec::new();
-                let mut brace_count = 1;
-                while j < tokens.len() && brace_count > 0 {
-                    match &tokens[j] {
-                        Token::LBrace => { 
-                            brace_count += 1; 
-                            j += 1; 
-                        }
-                        Token::RBrace => { 
-                            brace_count -= 1; 
-                            if brace_count > 0 {
-                                j += 1;
-                            }
-                        }
-                        _ => {
-                            match parse_single_statement(tokens, &mut j) {
-                                Ok(Some(node)) => body.push(node),
-                                Ok(None) => {},
-                                Err(e) => panic!("Error in function body: {}", e),
-                            }
-                        }
+            }
+            match self.tokens[at] {
+                Token::LBrace => depth += 1,
+                Token::RBrace => {
+                    depth -= 1;
+                    if depth <= 0 {
+                        return true;
                     }
                 }
-
-                ast.push(AST::FuncDef {
-                    name,
-                    params,
-                    return_type: None, // No explicit return type
-                    body,
-                });
-
-                i = j + 1;
+                Token::Semicolon if depth == 0 => return true,
+                _ => {}
             }
+            at += 1;
+        }
+    }
+}
 
-            _ => {
-                // Handle all other cases using the helper function
-                match parse_single_statement(tokens, &mut i) {
-                    Ok(Some(node)) => ast.push(node),
-                    Ok(None) => {}, // Skip unhandled tokens
-                    Err(e) => panic!("{}", e),
-                }
+impl<I: Iterator<Item = (Token, Span)>> Iterator for Items<I> {
+    type Item = Result<Vec<AST>, (String, Span)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut more = self.read_item(0);
+        if self.tokens.is_empty() {
+            return None;
+        }
+        loop {
+            let mut tokens = TokenStream::new(&self.tokens, &self.spans);
+            let result = parse_item(&mut tokens);
+            // An `if` without an `else` so far, say; parse it again with
+            // the next item's tokens too
+            if tokens.ran_out() && more {
+                more = self.read_item(self.tokens.len());
+                continue;
             }
+            let result = result.map_err(|e| {
+                tokens.rewind(0);
+                skip_item(&mut tokens);
+                (e, tokens.span_from(0))
+            });
+            let used = tokens.checkpoint();
+            self.tokens.drain(..used);
+            self.spans.drain(..used);
+            return Some(result);
         }
     }
+}
 
-    ast
+// Parses the top-level item at the cursor
+fn parse_item(tokens: &mut TokenStream) -> Result<Vec<AST>, String> {
+    if let Some(func) = try_parse_function(tokens, Vec::new(), None) {
+        func.map(|func| vec![func])
+    } else if tokens.peek() == Some(&Token::HashBracket) {
+        parse_attributes(tokens).and_then(|attributes| match try_parse_function(tokens, attributes, None) {
+            Some(func) => func.map(|func| vec![func]),
+            None => Err("Expected a function definition after attributes".to_string()),
+        })
+    } else if tokens.peek() == Some(&Token::Impl) {
+        parse_impl(tokens)
+    } else {
+        // Handle all other cases using the helper function
+        parse_single_statement(tokens).map(|node| node.into_iter().collect())
+    }
 }
 
-// Helper function to parse values (literals, variables, function calls)
-fn parse_value(tokens: &[Token], index: &mut usize) -> Result<ASTValue, String> {
-    if *index >= tokens.len() {
-        return Err("Unexpected end of tokens".to_string());
+// Builds the value for one binary operator from its two sides
+type Binary = fn(Box<ASTValue>, Box<ASTValue>) -> ASTValue;
+
+// The operator a binary operator token stands for, and how tightly it
+// binds: loosest first, as in C, so `a || b && c == d < e + f * g` is
+// `a || (b && (c == (d < (e + (f * g)))))`
+fn binary_operator(token: &Token) -> Option<(u8, Binary)> {
+    let operator: (u8, Binary) = match token {
+        Token::OrOr => (1, ASTValue::Or),
+        Token::AndAnd => (2, ASTValue::And),
+        Token::EqEq => (3, ASTValue::EqualEqual),
+        Token::Eq => (3, ASTValue::Equal),
+        Token::NotEq => (3, ASTValue::NotEqual),
+        Token::Less => (4, ASTValue::LessThan),
+        Token::Greater => (4, ASTValue::GreaterThan),
+        Token::LessEq => (4, ASTValue::LessEqual),
+        Token::GreaterEq => (4, ASTValue::GreaterEqual),
+        Token::Plus => (5, ASTValue::Add),
+        Token::Minus => (5, ASTValue::Sub),
+        Token::Star => (6, ASTValue::Mul),
+        Token::Slash => (6, ASTValue::Div),
+        Token::Percent => (6, ASTValue::Rem),
+        _ => return None,
+    };
+    Some(operator)
+}
+
+// `!` and unary `-` bind tighter than any binary operator
+const PREFIX_POWER: u8 = 7;
+
+// Helper function to parse expressions: arithmetic, comparisons and logic
+// in one precedence-climbing (Pratt) parser. Binary operators of the same
+// precedence group from the left, so `a - b - c` is `(a - b) - c`.
+fn parse_expression(tokens: &mut TokenStream) -> Result<ASTValue, String> {
+    parse_binary(tokens, 0)
+}
+
+// An expression whose binary operators all bind tighter than `min_power`
+fn parse_binary(tokens: &mut TokenStream, min_power: u8) -> Result<ASTValue, String> {
+    let mut value = parse_prefix(tokens)?;
+    while let Some((power, op)) = tokens
+        .peek()
+        .and_then(binary_operator)
+        .filter(|&(power, _)| power > min_power)
+    {
+        tokens.next();
+        // Only tighter operators go on the right, which makes this one
+        // group from the left
+        let rhs = parse_binary(tokens, power)?;
+        value = op(Box::new(value), Box::new(rhs));
     }
+    Ok(value)
+}
 
-    match &tokens[*index] {
-        Token::String(s) => {
-            let value = ASTValue::Str(s.clone());
-            *index += 1;
-            Ok(value)
-        }
-        Token::Integer(n) => {
-            let value = ASTValue::Int(*n);
-            *index += 1;
-            Ok(value)
-        }
-        Token::Integer64(n) => {
-            let value = ASTValue::Int64(*n);
-            *index += 1;
-            Ok(value)
-        }
-        Token::Float32(f) => {
-            let value = ASTValue::Float32(*f);
-            *index += 1;
-            Ok(value)
-        }
-        Token::Float64(f) => {
-            let value = ASTValue::Float64(*f);
-            *index += 1;
-            Ok(value)
-        }
-        Token::DefaultFloat(f) => {
-            let value = ASTValue::Float32(*f);
-            *index += 1;
-            Ok(value)
-        }
-        Token::Bool(b) => {
-            let value = ASTValue::Bool(*b);
-            *index += 1;
+// An operand with any `!` and `-` in front of it. A negated number literal
+// is folded into a negative literal, so it still takes the type of
+// whatever it's used with.
+fn parse_prefix(tokens: &mut TokenStream) -> Result<ASTValue, String> {
+    if tokens.eat(&Token::Bang) {
+        let operand = parse_binary(tokens, PREFIX_POWER)?;
+        return Ok(ASTValue::Not(Box::new(operand)));
+    }
+    if tokens.eat(&Token::Minus) {
+        let value = match parse_binary(tokens, PREFIX_POWER)? {
+            ASTValue::Int(n) => ASTValue::Int(n.wrapping_neg()),
+            ASTValue::Int64(n) => ASTValue::Int64(n.wrapping_neg()),
+            ASTValue::Float32(f) => ASTValue::Float32(-f),
+            ASTValue::Float64(f) => ASTValue::Float64(-f),
+            operand => ASTValue::Neg(Box::new(operand)),
+        };
+        return Ok(value);
+    }
+    parse_operand(tokens)
+}
+
+// A single operand: a literal, variable, function call or parenthesized value
+fn parse_operand(tokens: &mut TokenStream) -> Result<ASTValue, String> {
+    let token = tokens.peek().ok_or("Unexpected end of tokens")?;
+    if let Some(value) = literal(token) {
+        tokens.next();
+        return Ok(value);
+    }
+
+    match token {
+        Token::LParen => {
+            tokens.next(); // skip '('
+            let value = parse_expression(tokens)?;
+            tokens.expect(Token::RParen, "after expression")?;
             Ok(value)
         }
         Token::Ident(name) => {
-            // Check if this is a function call (identifier followed by '(')
-            if tokens.get(*index + 1) == Some(&Token::LParen) {
-                let func_name = name.clone();
-                *index += 2; // skip function name and '('
-                
-                let mut args = Vec::new();
-                
+            // Check if this is a function call (identifier followed by '('),
+            // or `object.method(` which calls the qualified name, e.g.
+            // compiler.version()
+            let call = match (tokens.peek_nth(1), tokens.peek_nth(2), tokens.peek_nth(3)) {
+                (Some(Token::LParen), _, _) => Some((name.to_string(), 2)),
+                (Some(Token::Dot), Some(Token::Ident(method)), Some(Token::LParen)) => {
+                    Some((format!("{}.{}", name, method), 4))
+                }
+                _ => None,
+            };
+            if let Some((func_name, skip)) = call {
+                tokens.advance(skip); // skip the name and '('
+
                 // Parse arguments
-                while *index < tokens.len() && tokens[*index] != Token::RParen {
-                    match &tokens[*index] {
-                        Token::String(s) => {
-                            args.push(ASTValue::Str(s.clone()));
-                            *index += 1;
-                        }
-                        Token::Integer(n) => {
-                            args.push(ASTValue::Int(*n));
-                            *index += 1;
-                        }
-                        Token::Integer64(n) => {
-                            args.push(ASTValue::Int64(*n));
-                            *index += 1;
-                        }
-                        Token::Float32(f) => {
-                            args.push(ASTValue::Float32(*f));
-                            *index += 1;
-                        }
-                        Token::Float64(f) => {
-                            args.push(ASTValue::Float64(*f));
-                            *index += 1;
-                        }
-                        Token::DefaultFloat(f) => {
-                            args.push(ASTValue::Float32(*f));
-                            *index += 1;
-                        }
-                        Token::Bool(b) => {
-                            args.push(ASTValue::Bool(*b));
-                            *index += 1;
-                        }
-                        Token::Ident(arg_name) => {
-                            // Check if this argument is also a function call
-                            if tokens.get(*index + 1) == Some(&Token::LParen) {
-                                let nested_call = parse_value(tokens, index)?;
-                                args.push(nested_call);
-                            } else {
-                                args.push(ASTValue::VarRef(arg_name.clone()));
-                                *index += 1;
-                            }
-                        }
-                        Token::Comma => {
-                            *index += 1; // skip comma
+                let mut args = Vec::new();
+                loop {
+                    match tokens.peek() {
+                        None | Some(Token::RParen) => break,
+                        Some(Token::Comma) => {
+                            tokens.next(); // skip comma
                         }
-                        _ => return Err(format!("Unexpected token in function arguments: {:?}", tokens[*index])),
+                        Some(_) => args.push(parse_expression(tokens)?),
                     }
                 }
-                
-                if *index >= tokens.len() || tokens[*index] != Token::RParen {
-                    return Err("Expected closing ')' for function call".to_string());
-                }
-                *index += 1; // skip ')'
-                
+                tokens.expect(Token::RParen, "to close function call")?;
+
                 Ok(ASTValue::FuncCall {
                     name: func_name,
                     args,
                 })
             } else {
                 // It's just a variable reference
-                let value = ASTValue::VarRef(name.clone());
-                *index += 1;
-                Ok(value)
+                tokens.next();
+                Ok(ASTValue::VarRef(name.to_string()))
             }
         }
-        _ => Err(format!("Expected value, got: {:?}", tokens[*index])),
+        Token::F32Type | Token::F64Type => float_constant(tokens),
+        _ => Err(format!("Expected value, got: {:?}", token)),
     }
 }
+
+// `f64.nan` or `f64.inf`, and the same for f32. Negate `inf` for -infinity.
+fn float_constant(tokens: &mut TokenStream) -> Result<ASTValue, String> {
+    let wide = tokens.next() == Some(&Token::F64Type);
+    let ty = if wide { "f64" } else { "f32" };
+    tokens.expect(Token::Dot, &format!("after '{}' in an expression", ty))?;
+    let value = match tokens.next() {
+        Some(Token::Ident(name)) if name.as_ref() == "nan" => f64::NAN,
+        Some(Token::Ident(name)) if name.as_ref() == "inf" => f64::INFINITY,
+        Some(Token::Ident(name)) => {
+            return Err(format!("{} has no constant '{}', only 'nan' and 'inf'", ty, name));
+        }
+        other => return Err(format!("Expected 'nan' or 'inf' after '{}.', got: {:?}", ty, other)),
+    };
+    Ok(if wide {
+        ASTValue::Float64(value)
+    } else {
+        ASTValue::Float32(value as f32)
+    })
+}
@@ -0,0 +1,10 @@
+use crate::modules::parser::AST;
+
+// A custom pass run over the whole program after parsing and before codegen.
+// Lints can inspect the tree and hand it back untouched; transforms return
+// the rewritten tree. Returning Err aborts the compilation with that message.
+pub trait AstPass {
+    fn name(&self) -> &str;
+
+    fn run(&mut self, ast: Vec<AST>) -> Result<Vec<AST>, String>;
+}
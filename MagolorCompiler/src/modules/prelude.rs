@@ -0,0 +1,895 @@
+use crate::modules::IR::{CodegenCtx, FunctionCodegen, VarType};
+use crate::modules::parser::ASTValue;
+use crate::modules::runtime;
+use crate::modules::typeck::{FunctionSig, is_number_literal};
+use crate::modules::types::Type;
+use inkwell::builder::Builder;
+use inkwell::intrinsics::Intrinsic;
+use inkwell::module::Linkage;
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, IntValue, PointerValue};
+use inkwell::{FloatPredicate, IntPredicate};
+
+// The built-in modules every program can use without a `use` line: console
+// for output, assert for checks, strings for basic string helpers (`string`
+// is taken by the type), math for numbers, process for leaving the program,
+// and time and random for the host's clock and randomness. With
+// --no-prelude a program starts with none of them and `use`s the ones it
+// wants by name.
+pub const MODULES: [&str; 7] = ["console", "assert", "strings", "math", "process", "time", "random"];
+
+// What --sandbox takes away: process acts on the process running the
+// program, not just on its values
+pub const SANDBOXED: [&str; 1] = ["process"];
+
+// Programs written before the prelude existed spell `use Console;`
+const LEGACY_NAMES: [(&str, &str); 1] = [("Console", "console")];
+
+// Emits one call to a builtin where the builder is, given the arguments as
+// written. Returns the result, or None for void.
+pub type Lowering =
+    for<'a, 'ctx> fn(&FunctionCodegen<'a, 'ctx>, &[ASTValue]) -> Option<BasicValueEnum<'ctx>>;
+
+pub enum Params {
+    Fixed(&'static [(&'static str, Type)]),
+    // Numbers that all have one type, which is also the type of the result;
+    // `ret` is unused. As in arithmetic, a literal takes the others' type.
+    Numeric(&'static [&'static str]),
+    // Any number of arguments of any type
+    Variadic,
+}
+
+// A function of one of the prelude's modules, called as `module.function()`.
+// The type checker checks calls against `params` and `ret` and codegen
+// hands them to `lower`, so adding a builtin is adding an entry to BUILTINS.
+pub struct Builtin {
+    pub name: &'static str,
+    pub params: Params,
+    pub ret: Type,
+    pub lower: Lowering,
+    // Never returns to the caller. Codegen ends the block after the call, so
+    // a branch that ends in one doesn't fall through to anything.
+    pub noreturn: bool,
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    // Each argument on its own line
+    Builtin {
+        name: "console.print",
+        params: Params::Variadic,
+        ret: Type::Void,
+        lower: print,
+        noreturn: false,
+    },
+    // Each argument on its own line, spelled the way it would be in source:
+    // strings and chars in quotes, integers in full and floats to six
+    // places (scientific from 1e13 up). Escapes aren't re-escaped.
+    Builtin {
+        name: "console.debug",
+        params: Params::Variadic,
+        ret: Type::Void,
+        lower: debug,
+        noreturn: false,
+    },
+    // Exits with status 1 if the condition is false
+    Builtin {
+        name: "assert.that",
+        params: Params::Fixed(&[("condition", Type::Bool)]),
+        ret: Type::Void,
+        lower: assert_that,
+        noreturn: false,
+    },
+    Builtin {
+        name: "strings.len",
+        params: Params::Fixed(&[("s", Type::Str)]),
+        ret: Type::I32,
+        lower: strings_len,
+        noreturn: false,
+    },
+    Builtin {
+        name: "strings.eq",
+        params: Params::Fixed(&[("a", Type::Str), ("b", Type::Str)]),
+        ret: Type::Bool,
+        lower: strings_eq,
+        noreturn: false,
+    },
+    // The smaller; for floats, a NaN loses to the other argument
+    Builtin {
+        name: "math.min",
+        params: Params::Numeric(&["a", "b"]),
+        ret: Type::Void,
+        lower: math_min,
+        noreturn: false,
+    },
+    // The larger; for floats, a NaN loses to the other argument
+    Builtin {
+        name: "math.max",
+        params: Params::Numeric(&["a", "b"]),
+        ret: Type::Void,
+        lower: math_max,
+        noreturn: false,
+    },
+    // max(min(x, high), low), so `low` wins if the bounds are reversed. A
+    // NaN x comes out as `high`, or `low` if that's smaller.
+    Builtin {
+        name: "math.clamp",
+        params: Params::Numeric(&["x", "low", "high"]),
+        ret: Type::Void,
+        lower: math_clamp,
+        noreturn: false,
+    },
+    // Wraps for the most negative integer, whose absolute value doesn't fit:
+    // math.abs(-2147483648) is -2147483648. The absolute value of a NaN is a
+    // NaN.
+    Builtin {
+        name: "math.abs",
+        params: Params::Numeric(&["x"]),
+        ret: Type::Void,
+        lower: math_abs,
+        noreturn: false,
+    },
+    // Ends the program with the given status, without returning from main
+    Builtin {
+        name: "process.exit",
+        params: Params::Fixed(&[("code", Type::I32)]),
+        ret: Type::Void,
+        lower: process_exit,
+        noreturn: true,
+    },
+    // Seconds since the Unix epoch, by the host's clock
+    Builtin {
+        name: "time.now",
+        params: Params::Fixed(&[]),
+        ret: Type::I64,
+        lower: time_now,
+        noreturn: false,
+    },
+    // 64 random bits from the host (getentropy, rand_s or WASI's
+    // random_get), so any i64, negative ones included
+    Builtin {
+        name: "random.next",
+        params: Params::Fixed(&[]),
+        ret: Type::I64,
+        lower: random_next,
+        noreturn: false,
+    },
+];
+
+impl Builtin {
+    // "strings" for strings.len
+    pub fn module(&self) -> &'static str {
+        self.name.split('.').next().unwrap_or(self.name)
+    }
+
+    // None unless the parameter types are fixed; no signature can describe
+    // the others
+    pub fn sig(&self) -> Option<FunctionSig> {
+        let Params::Fixed(params) = self.params else {
+            return None;
+        };
+        Some(FunctionSig {
+            params: params.iter().map(|(name, ty)| (name.to_string(), *ty)).collect(),
+            ret: self.ret,
+        })
+    }
+}
+
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+// The built-in module a `use` line names, if it names one
+pub fn builtin_module(name: &str) -> Option<&'static str> {
+    MODULES.iter().copied().find(|module| *module == name).or_else(|| {
+        LEGACY_NAMES
+            .iter()
+            .find(|(legacy, _)| *legacy == name)
+            .map(|(_, module)| *module)
+    })
+}
+
+fn print<'ctx>(fcg: &FunctionCodegen<'_, 'ctx>, args: &[ASTValue]) -> Option<BasicValueEnum<'ctx>> {
+    let cg = fcg.cg;
+    for arg in args {
+        let text = match arg {
+            ASTValue::Str(s) => cg.const_str(s),
+            ASTValue::Int(n) => cg.const_str(&n.to_string()),
+            ASTValue::Int64(n) => cg.const_str(&n.to_string()),
+            ASTValue::UInt8(n) => cg.const_str(&n.to_string()),
+            ASTValue::UInt32(n) => cg.const_str(&n.to_string()),
+            ASTValue::UInt64(n) => cg.const_str(&n.to_string()),
+            ASTValue::Float32(f) => cg.const_str(&f.to_string()),
+            ASTValue::Float64(f) => cg.const_str(&f.to_string()),
+            ASTValue::Bool(b) => cg.const_str(if *b { "true" } else { "false" }),
+            ASTValue::Char(c) => cg.const_str(&(*c as char).to_string()),
+            ASTValue::VarRef(name) => {
+                let (ptr, var_type) = *fcg
+                    .lookup(name)
+                    .unwrap_or_else(|| panic!("Unknown variable {}", name));
+                let value = cg.builder
+                    .build_load(cg.basic_type(var_type), ptr, name)
+                    .expect("load failed");
+                match var_type {
+                    VarType::Str => value.into_pointer_value(),
+                    VarType::Bool => cg.builder
+                        .build_select(
+                            value.into_int_value(),
+                            cg.const_str("true"),
+                            cg.const_str("false"),
+                            "bool_str_select",
+                        )
+                        .expect("select failed")
+                        .into_pointer_value(),
+                    VarType::Char => char_to_str(cg, value.into_int_value()),
+                    number => {
+                        print_number(cg, value, number.is_unsigned());
+                        continue;
+                    }
+                }
+            }
+            // Anything else is worked out at run time and printed the way
+            // console.debug prints it, but with strings and chars unquoted
+            other => match fcg.compile_arithmetic(other) {
+                BasicValueEnum::PointerValue(s) => s,
+                BasicValueEnum::IntValue(v) if v.get_type().get_bit_width() == 1 => cg.builder
+                    .build_select(v, cg.const_str("true"), cg.const_str("false"), "bool_str_select")
+                    .expect("select failed")
+                    .into_pointer_value(),
+                BasicValueEnum::IntValue(v) if v.get_type().get_bit_width() == 8 && !fcg.is_unsigned(other) => {
+                    char_to_str(cg, v)
+                }
+                number => {
+                    print_number(cg, number, fcg.is_unsigned(other));
+                    continue;
+                }
+            },
+        };
+        cg.builder
+            .build_call(cg.puts_fn, &[text.into()], "call_puts")
+            .expect("build_call failed");
+    }
+    None
+}
+
+fn debug<'ctx>(fcg: &FunctionCodegen<'_, 'ctx>, args: &[ASTValue]) -> Option<BasicValueEnum<'ctx>> {
+    let cg = fcg.cg;
+    for arg in args {
+        let (helper, value): (FunctionValue<'ctx>, BasicMetadataValueEnum<'ctx>) =
+            match fcg.compile_arithmetic(arg) {
+                BasicValueEnum::PointerValue(s) => (debug_str_fn(cg), s.into()),
+                BasicValueEnum::IntValue(v) if v.get_type().get_bit_width() == 1 => {
+                    let text = cg.builder
+                        .build_select(v, cg.const_str("true"), cg.const_str("false"), "bool_str_select")
+                        .expect("select failed");
+                    (cg.puts_fn, text.into())
+                }
+                // A u8 is a number, not a char
+                BasicValueEnum::IntValue(v) if v.get_type().get_bit_width() == 8 && !fcg.is_unsigned(arg) => {
+                    (cg.puts_fn, quoted_char(cg, v).into())
+                }
+                number => {
+                    print_number(cg, number, fcg.is_unsigned(arg));
+                    continue;
+                }
+            };
+        cg.builder
+            .build_call(helper, &[value], "debug")
+            .expect("build_call failed");
+    }
+    None
+}
+
+// An integer or float on a line of its own, in decimal
+fn print_number<'ctx>(cg: &CodegenCtx<'ctx>, number: BasicValueEnum<'ctx>, unsigned: bool) {
+    let (helper, value): (FunctionValue<'ctx>, BasicMetadataValueEnum<'ctx>) = match number {
+        BasicValueEnum::IntValue(v) if unsigned => {
+            let wide = cg.builder
+                .build_int_z_extend_or_bit_cast(v, cg.i64_type, "debug_uint")
+                .expect("zext failed");
+            (debug_uint_fn(cg), wide.into())
+        }
+        BasicValueEnum::IntValue(v) => {
+            let wide = cg.builder
+                .build_int_s_extend_or_bit_cast(v, cg.i64_type, "debug_int")
+                .expect("sext failed");
+            (debug_int_fn(cg), wide.into())
+        }
+        BasicValueEnum::FloatValue(v) => {
+            let wide = cg.builder
+                .build_float_cast(v, cg.f64_type, "debug_float")
+                .expect("float cast failed");
+            (debug_float_fn(cg), wide.into())
+        }
+        other => unreachable!("printing {:?} as a number", other),
+    };
+    cg.builder
+        .build_call(helper, &[value], "debug")
+        .expect("build_call failed");
+}
+
+// 'c' as a C string on the stack, for puts
+fn quoted_char<'ctx>(cg: &CodegenCtx<'ctx>, ch: IntValue<'ctx>) -> PointerValue<'ctx> {
+    let buf = cg.builder
+        .build_array_alloca(cg.i8_type, cg.i32_type.const_int(4, false), "quoted_char")
+        .expect("alloca failed");
+    let quote = cg.i8_type.const_int(b'\'' as u64, false);
+    for (index, byte) in [quote, ch, quote, cg.i8_type.const_zero()].into_iter().enumerate() {
+        let ptr = byte_ptr(&cg.builder, cg, buf, cg.i64_type.const_int(index as u64, false));
+        cg.builder.build_store(ptr, byte).expect("store failed");
+    }
+    buf
+}
+
+// void __magolor_debug_int(i64): prints the number in decimal. Like the
+// other debug helpers it's generated the first time a module needs it, with
+// its own builder so the caller's position is left alone.
+fn debug_int_fn<'ctx>(cg: &CodegenCtx<'ctx>) -> FunctionValue<'ctx> {
+    const NAME: &str = "__magolor_debug_int";
+    if let Some(function) = cg.module.get_function(NAME) {
+        return function;
+    }
+    let fn_type = cg.context.void_type().fn_type(&[cg.i64_type.into()], false);
+    let function = cg.module.add_function(NAME, fn_type, Some(Linkage::Internal));
+    let builder = cg.context.create_builder();
+    builder.position_at_end(cg.context.append_basic_block(function, "entry"));
+
+    let value = function.get_nth_param(0).expect("missing parameter").into_int_value();
+    let end = terminated_buffer(&builder, cg, 24);
+    let negative = builder
+        .build_int_compare(IntPredicate::SLT, value, cg.i64_type.const_zero(), "negative")
+        .expect("build_int_compare failed");
+    // i64::MIN negates to itself, which is still right read as unsigned
+    let negated = builder.build_int_neg(value, "negated").expect("build_int_neg failed");
+    let magnitude = builder
+        .build_select(negative, negated, value, "magnitude")
+        .expect("select failed")
+        .into_int_value();
+    let start = emit_digits(&builder, cg, function, magnitude, negative, end);
+    builder.build_call(cg.puts_fn, &[start.into()], "call_puts").expect("build_call failed");
+    builder.build_return(None).expect("build_return failed");
+    function
+}
+
+// void __magolor_debug_uint(i64), for the unsigned types: the number is
+// read as a u64
+fn debug_uint_fn<'ctx>(cg: &CodegenCtx<'ctx>) -> FunctionValue<'ctx> {
+    const NAME: &str = "__magolor_debug_uint";
+    if let Some(function) = cg.module.get_function(NAME) {
+        return function;
+    }
+    let fn_type = cg.context.void_type().fn_type(&[cg.i64_type.into()], false);
+    let function = cg.module.add_function(NAME, fn_type, Some(Linkage::Internal));
+    let builder = cg.context.create_builder();
+    builder.position_at_end(cg.context.append_basic_block(function, "entry"));
+
+    let value = function.get_nth_param(0).expect("missing parameter").into_int_value();
+    let end = terminated_buffer(&builder, cg, 24);
+    let start = emit_digits(&builder, cg, function, value, cg.bool_type.const_zero(), end);
+    builder.build_call(cg.puts_fn, &[start.into()], "call_puts").expect("build_call failed");
+    builder.build_return(None).expect("build_return failed");
+    function
+}
+
+// void __magolor_debug_float(f64)
+fn debug_float_fn<'ctx>(cg: &CodegenCtx<'ctx>) -> FunctionValue<'ctx> {
+    const NAME: &str = "__magolor_debug_float";
+    if let Some(function) = cg.module.get_function(NAME) {
+        return function;
+    }
+    let fn_type = cg.context.void_type().fn_type(&[cg.f64_type.into()], false);
+    let function = cg.module.add_function(NAME, fn_type, Some(Linkage::Internal));
+    let builder = cg.context.create_builder();
+    let block = |name: &str| cg.context.append_basic_block(function, name);
+    let (entry, nan, number, infinite, finite) =
+        (block("entry"), block("nan"), block("number"), block("infinite"), block("finite"));
+    let (scale, exponent, fixed) = (block("scale"), block("exponent"), block("fixed"));
+    let float = |f: f64| cg.f64_type.const_float(f);
+    let int = |n: u64| cg.i64_type.const_int(n, false);
+    let puts = |text: BasicValueEnum<'ctx>| {
+        builder.build_call(cg.puts_fn, &[text.into()], "call_puts").expect("build_call failed");
+        builder.build_return(None).expect("build_return failed");
+    };
+
+    builder.position_at_end(entry);
+    let x = function.get_nth_param(0).expect("missing parameter").into_float_value();
+    let end = terminated_buffer(&builder, cg, 32);
+    let is_nan = builder
+        .build_float_compare(FloatPredicate::UNO, x, x, "is_nan")
+        .expect("build_float_compare failed");
+    builder.build_conditional_branch(is_nan, nan, number).expect("build_cond_br failed");
+
+    builder.position_at_end(nan);
+    puts(cg.const_str("NaN").into());
+
+    builder.position_at_end(number);
+    let negative = builder
+        .build_float_compare(FloatPredicate::OLT, x, float(0.0), "negative")
+        .expect("build_float_compare failed");
+    let negated = builder.build_float_neg(x, "negated").expect("build_float_neg failed");
+    let magnitude = builder
+        .build_select(negative, negated, x, "magnitude")
+        .expect("select failed")
+        .into_float_value();
+    let is_infinite = builder
+        .build_float_compare(FloatPredicate::OEQ, magnitude, float(f64::INFINITY), "is_infinite")
+        .expect("build_float_compare failed");
+    builder.build_conditional_branch(is_infinite, infinite, finite).expect("build_cond_br failed");
+
+    builder.position_at_end(infinite);
+    let text = builder
+        .build_select(negative, cg.const_str("-inf"), cg.const_str("inf"), "inf_str")
+        .expect("select failed");
+    puts(text);
+
+    // Beyond this, the six places no longer fit in a u64
+    builder.position_at_end(finite);
+    let large = builder
+        .build_float_compare(FloatPredicate::OGE, magnitude, float(1e13), "large")
+        .expect("build_float_compare failed");
+    builder.build_conditional_branch(large, scale, fixed).expect("build_cond_br failed");
+
+    // Divide by ten until there's one digit before the point
+    builder.position_at_end(scale);
+    let mantissa = builder.build_phi(cg.f64_type, "mantissa").expect("build_phi failed");
+    let power = builder.build_phi(cg.i64_type, "power").expect("build_phi failed");
+    let next_mantissa = builder
+        .build_float_div(mantissa.as_basic_value().into_float_value(), float(10.0), "next_mantissa")
+        .expect("build_float_div failed");
+    let next_power = builder
+        .build_int_add(power.as_basic_value().into_int_value(), int(1), "next_power")
+        .expect("build_int_add failed");
+    mantissa.add_incoming(&[(&magnitude, finite), (&next_mantissa, scale)]);
+    power.add_incoming(&[(&int(0), finite), (&next_power, scale)]);
+    let again = builder
+        .build_float_compare(FloatPredicate::OGE, next_mantissa, float(10.0), "again")
+        .expect("build_float_compare failed");
+    builder.build_conditional_branch(again, scale, exponent).expect("build_cond_br failed");
+
+    builder.position_at_end(exponent);
+    let no = cg.bool_type.const_zero();
+    let exponent_start = emit_digits(&builder, cg, function, next_power, no, end);
+    let e = byte_ptr(&builder, cg, exponent_start, cg.i64_type.const_all_ones());
+    builder.build_store(e, cg.i8_type.const_int(b'e' as u64, false)).expect("store failed");
+    let exponent_end = builder.get_insert_block().expect("builder is not positioned");
+    builder.build_unconditional_branch(fixed).expect("build_br failed");
+
+    builder.position_at_end(fixed);
+    let value = builder.build_phi(cg.f64_type, "value").expect("build_phi failed");
+    value.add_incoming(&[(&magnitude, finite), (&next_mantissa, exponent_end)]);
+    let tail = builder.build_phi(cg.i8_ptr, "tail").expect("build_phi failed");
+    tail.add_incoming(&[(&end, finite), (&e, exponent_end)]);
+    let millionths = builder
+        .build_float_mul(value.as_basic_value().into_float_value(), float(1e6), "millionths")
+        .expect("build_float_mul failed");
+    let rounded = builder
+        .build_float_add(millionths, float(0.5), "rounded")
+        .expect("build_float_add failed");
+    let scaled = builder
+        .build_float_to_unsigned_int(rounded, cg.i64_type, "scaled")
+        .expect("build_float_to_unsigned_int failed");
+    let whole = builder
+        .build_int_unsigned_div(scaled, int(1_000_000), "whole")
+        .expect("build_int_unsigned_div failed");
+    let mut fraction = builder
+        .build_int_unsigned_rem(scaled, int(1_000_000), "fraction")
+        .expect("build_int_unsigned_rem failed");
+    let mut cursor = tail.as_basic_value().into_pointer_value();
+    for _ in 0..6 {
+        cursor = store_digit(&builder, cg, fraction, cursor);
+        fraction = builder
+            .build_int_unsigned_div(fraction, int(10), "fraction")
+            .expect("build_int_unsigned_div failed");
+    }
+    let point = byte_ptr(&builder, cg, cursor, cg.i64_type.const_all_ones());
+    builder.build_store(point, cg.i8_type.const_int(b'.' as u64, false)).expect("store failed");
+    let start = emit_digits(&builder, cg, function, whole, negative, point);
+    puts(start.into());
+    function
+}
+
+// void __magolor_debug_str(ptr): the string in double quotes
+fn debug_str_fn<'ctx>(cg: &CodegenCtx<'ctx>) -> FunctionValue<'ctx> {
+    const NAME: &str = "__magolor_debug_str";
+    if let Some(function) = cg.module.get_function(NAME) {
+        return function;
+    }
+    let fn_type = cg.context.void_type().fn_type(&[cg.i8_ptr.into()], false);
+    let function = cg.module.add_function(NAME, fn_type, Some(Linkage::Internal));
+    let builder = cg.context.create_builder();
+    let block = |name: &str| cg.context.append_basic_block(function, name);
+    let (entry, scan, quote_buf, copy, copy_byte, done) =
+        (block("entry"), block("scan"), block("quote_buf"), block("copy"), block("copy_byte"), block("done"));
+    let int = |n: u64| cg.i64_type.const_int(n, false);
+    let quote = cg.i8_type.const_int(b'"' as u64, false);
+
+    builder.position_at_end(entry);
+    let s = function.get_nth_param(0).expect("missing parameter").into_pointer_value();
+    builder.build_unconditional_branch(scan).expect("build_br failed");
+
+    builder.position_at_end(scan);
+    let len = builder.build_phi(cg.i64_type, "len").expect("build_phi failed");
+    let len_val = len.as_basic_value().into_int_value();
+    let byte = builder
+        .build_load(cg.i8_type, byte_ptr(&builder, cg, s, len_val), "byte")
+        .expect("load failed")
+        .into_int_value();
+    let next_len = builder.build_int_add(len_val, int(1), "next_len").expect("build_int_add failed");
+    len.add_incoming(&[(&int(0), entry), (&next_len, scan)]);
+    let at_end = builder
+        .build_int_compare(IntPredicate::EQ, byte, cg.i8_type.const_zero(), "at_end")
+        .expect("build_int_compare failed");
+    let size = builder.build_int_add(len_val, int(3), "size").expect("build_int_add failed");
+    builder.build_conditional_branch(at_end, quote_buf, scan).expect("build_cond_br failed");
+
+    // Both quotes, the bytes between them and the NUL
+    builder.position_at_end(quote_buf);
+    let buf = builder.build_array_alloca(cg.i8_type, size, "quoted").expect("alloca failed");
+    builder.build_unconditional_branch(copy).expect("build_br failed");
+
+    builder.position_at_end(copy);
+    let index = builder.build_phi(cg.i64_type, "index").expect("build_phi failed");
+    let i = index.as_basic_value().into_int_value();
+    let copied = builder
+        .build_int_compare(IntPredicate::EQ, i, len_val, "copied")
+        .expect("build_int_compare failed");
+    builder.build_conditional_branch(copied, done, copy_byte).expect("build_cond_br failed");
+
+    builder.position_at_end(copy_byte);
+    let next = builder.build_int_add(i, int(1), "next").expect("build_int_add failed");
+    let byte = builder
+        .build_load(cg.i8_type, byte_ptr(&builder, cg, s, i), "byte")
+        .expect("load failed");
+    builder.build_store(byte_ptr(&builder, cg, buf, next), byte).expect("store failed");
+    builder.build_unconditional_branch(copy).expect("build_br failed");
+    index.add_incoming(&[(&int(0), quote_buf), (&next, copy_byte)]);
+
+    builder.position_at_end(done);
+    builder.build_store(buf, quote).expect("store failed");
+    let closing = builder.build_int_add(len_val, int(1), "closing").expect("build_int_add failed");
+    builder.build_store(byte_ptr(&builder, cg, buf, closing), quote).expect("store failed");
+    let nul = builder.build_int_add(len_val, int(2), "nul").expect("build_int_add failed");
+    builder
+        .build_store(byte_ptr(&builder, cg, buf, nul), cg.i8_type.const_zero())
+        .expect("store failed");
+    builder.build_call(cg.puts_fn, &[buf.into()], "call_puts").expect("build_call failed");
+    builder.build_return(None).expect("build_return failed");
+    function
+}
+
+// A `size`-byte stack buffer for text written backwards from its end.
+// Returns a pointer to its last byte, which holds the NUL.
+fn terminated_buffer<'ctx>(builder: &Builder<'ctx>, cg: &CodegenCtx<'ctx>, size: u64) -> PointerValue<'ctx> {
+    let buf = builder
+        .build_array_alloca(cg.i8_type, cg.i64_type.const_int(size, false), "buf")
+        .expect("alloca failed");
+    let end = byte_ptr(builder, cg, buf, cg.i64_type.const_int(size - 1, false));
+    builder.build_store(end, cg.i8_type.const_zero()).expect("store failed");
+    end
+}
+
+// Writes the decimal digits of the unsigned `magnitude` just before `end`,
+// and a '-' before them if `negative`. Returns where the text starts.
+fn emit_digits<'ctx>(
+    builder: &Builder<'ctx>,
+    cg: &CodegenCtx<'ctx>,
+    function: FunctionValue<'ctx>,
+    magnitude: IntValue<'ctx>,
+    negative: IntValue<'ctx>,
+    end: PointerValue<'ctx>,
+) -> PointerValue<'ctx> {
+    let start = builder.get_insert_block().expect("builder is not positioned");
+    let digit = cg.context.append_basic_block(function, "digit");
+    let sign = cg.context.append_basic_block(function, "sign");
+    builder.build_unconditional_branch(digit).expect("build_br failed");
+
+    // At least one digit, so 0 comes out as "0"
+    builder.position_at_end(digit);
+    let rest = builder.build_phi(cg.i64_type, "rest").expect("build_phi failed");
+    let cursor = builder.build_phi(cg.i8_ptr, "cursor").expect("build_phi failed");
+    let rest_val = rest.as_basic_value().into_int_value();
+    let written = store_digit(builder, cg, rest_val, cursor.as_basic_value().into_pointer_value());
+    let next = builder
+        .build_int_unsigned_div(rest_val, cg.i64_type.const_int(10, false), "next")
+        .expect("build_int_unsigned_div failed");
+    rest.add_incoming(&[(&magnitude, start), (&next, digit)]);
+    cursor.add_incoming(&[(&end, start), (&written, digit)]);
+    let more = builder
+        .build_int_compare(IntPredicate::NE, next, cg.i64_type.const_zero(), "more")
+        .expect("build_int_compare failed");
+    builder.build_conditional_branch(more, digit, sign).expect("build_cond_br failed");
+
+    // The byte before the digits is free either way, so the '-' always goes
+    // in and only the start moves
+    builder.position_at_end(sign);
+    let minus = byte_ptr(builder, cg, written, cg.i64_type.const_all_ones());
+    builder.build_store(minus, cg.i8_type.const_int(b'-' as u64, false)).expect("store failed");
+    builder
+        .build_select(negative, minus, written, "start")
+        .expect("select failed")
+        .into_pointer_value()
+}
+
+// Stores the last decimal digit of `n` in the byte before `cursor` and
+// returns a pointer to it
+fn store_digit<'ctx>(
+    builder: &Builder<'ctx>,
+    cg: &CodegenCtx<'ctx>,
+    n: IntValue<'ctx>,
+    cursor: PointerValue<'ctx>,
+) -> PointerValue<'ctx> {
+    let digit = builder
+        .build_int_unsigned_rem(n, cg.i64_type.const_int(10, false), "digit")
+        .expect("build_int_unsigned_rem failed");
+    let digit = builder.build_int_truncate(digit, cg.i8_type, "digit").expect("trunc failed");
+    let ch = builder
+        .build_int_add(digit, cg.i8_type.const_int(b'0' as u64, false), "digit_char")
+        .expect("build_int_add failed");
+    let ptr = byte_ptr(builder, cg, cursor, cg.i64_type.const_all_ones());
+    builder.build_store(ptr, ch).expect("store failed");
+    ptr
+}
+
+// `base + offset`, a byte pointer
+fn byte_ptr<'ctx>(
+    builder: &Builder<'ctx>,
+    cg: &CodegenCtx<'ctx>,
+    base: PointerValue<'ctx>,
+    offset: IntValue<'ctx>,
+) -> PointerValue<'ctx> {
+    unsafe {
+        builder
+            .build_in_bounds_gep(cg.i8_type, base, &[offset], "byte_ptr")
+            .expect("gep failed")
+    }
+}
+
+// A char as a one-character C string on the stack, for puts
+fn char_to_str<'ctx>(cg: &CodegenCtx<'ctx>, ch: IntValue<'ctx>) -> PointerValue<'ctx> {
+    let buf = cg.builder
+        .build_array_alloca(cg.i8_type, cg.i32_type.const_int(2, false), "char_str")
+        .expect("alloca failed");
+    let end = unsafe {
+        cg.builder
+            .build_in_bounds_gep(cg.i8_type, buf, &[cg.i32_type.const_int(1, false)], "char_str_end")
+            .expect("gep failed")
+    };
+    cg.builder.build_store(buf, ch).expect("store failed");
+    cg.builder.build_store(end, cg.i8_type.const_zero()).expect("store failed");
+    buf
+}
+
+fn assert_that<'ctx>(fcg: &FunctionCodegen<'_, 'ctx>, args: &[ASTValue]) -> Option<BasicValueEnum<'ctx>> {
+    let cg = fcg.cg;
+    let condition = fcg
+        .compile_arithmetic_as(&args[0], cg.bool_type.into())
+        .into_int_value();
+    let failed = fcg.append_block("assert_failed");
+    let passed = fcg.append_block("assert_passed");
+    cg.builder
+        .build_conditional_branch(condition, passed, failed)
+        .expect("build_cond_br failed");
+
+    fcg.position_at_end(failed);
+    let message = format!(
+        "assertion failed in function {}\n",
+        fcg.function.get_name().to_string_lossy()
+    );
+    cg.builder
+        .build_call(
+            cg.write_fn,
+            &[
+                cg.i32_type.const_int(2, false).into(),
+                cg.const_str(&message).into(),
+                cg.i64_type.const_int(message.len() as u64, false).into(),
+            ],
+            "report_assertion",
+        )
+        .expect("build_call failed");
+    cg.builder
+        .build_call(cg.exit_fn, &[cg.i32_type.const_int(1, false).into()], "")
+        .expect("build_call failed");
+    cg.builder.build_unreachable().expect("build_unreachable failed");
+
+    fcg.position_at_end(passed);
+    None
+}
+
+fn process_exit<'ctx>(fcg: &FunctionCodegen<'_, 'ctx>, args: &[ASTValue]) -> Option<BasicValueEnum<'ctx>> {
+    let cg = fcg.cg;
+    let code = fcg.compile_arithmetic_as(&args[0], cg.i32_type.into());
+    cg.builder
+        .build_call(cg.exit_fn, &[code.into()], "")
+        .expect("build_call failed");
+    None
+}
+
+fn time_now<'ctx>(fcg: &FunctionCodegen<'_, 'ctx>, _: &[ASTValue]) -> Option<BasicValueEnum<'ctx>> {
+    let cg = fcg.cg;
+    let function = runtime::time_fn(cg.context, &cg.module, cg.target, cg.toolchain);
+    cg.builder
+        .build_call(function, &[], "now")
+        .expect("build_call failed")
+        .try_as_basic_value()
+        .left()
+}
+
+fn random_next<'ctx>(fcg: &FunctionCodegen<'_, 'ctx>, _: &[ASTValue]) -> Option<BasicValueEnum<'ctx>> {
+    let cg = fcg.cg;
+    let function = runtime::random_fn(cg.context, &cg.module, cg.target, cg.toolchain);
+    cg.builder
+        .build_call(function, &[], "random")
+        .expect("build_call failed")
+        .try_as_basic_value()
+        .left()
+}
+
+fn strings_len<'ctx>(fcg: &FunctionCodegen<'_, 'ctx>, args: &[ASTValue]) -> Option<BasicValueEnum<'ctx>> {
+    let cg = fcg.cg;
+    let s = fcg.compile_arithmetic_as(&args[0], cg.i8_ptr.into()).into_pointer_value();
+    let start = cg.builder.get_insert_block().expect("builder is not positioned");
+    let scan = fcg.append_block("len_scan");
+    let done = fcg.append_block("len_done");
+    cg.builder.build_unconditional_branch(scan).expect("build_br failed");
+
+    fcg.position_at_end(scan);
+    let index = cg.builder.build_phi(cg.i32_type, "len_index").expect("build_phi failed");
+    let i = index.as_basic_value().into_int_value();
+    let byte = byte_at(cg, s, i, "len_byte");
+    let next = cg.builder
+        .build_int_add(i, cg.i32_type.const_int(1, false), "len_next")
+        .expect("build_int_add failed");
+    index.add_incoming(&[(&cg.i32_type.const_zero(), start), (&next, scan)]);
+    let at_end = cg.builder
+        .build_int_compare(IntPredicate::EQ, byte, cg.i8_type.const_zero(), "len_at_end")
+        .expect("build_int_compare failed");
+    cg.builder
+        .build_conditional_branch(at_end, done, scan)
+        .expect("build_cond_br failed");
+
+    fcg.position_at_end(done);
+    Some(i.into())
+}
+
+fn strings_eq<'ctx>(fcg: &FunctionCodegen<'_, 'ctx>, args: &[ASTValue]) -> Option<BasicValueEnum<'ctx>> {
+    let cg = fcg.cg;
+    let a = fcg.compile_arithmetic_as(&args[0], cg.i8_ptr.into()).into_pointer_value();
+    let b = fcg.compile_arithmetic_as(&args[1], cg.i8_ptr.into()).into_pointer_value();
+    let start = cg.builder.get_insert_block().expect("builder is not positioned");
+    let compare = fcg.append_block("eq_compare");
+    let same = fcg.append_block("eq_same");
+    let done = fcg.append_block("eq_done");
+    cg.builder.build_unconditional_branch(compare).expect("build_br failed");
+
+    fcg.position_at_end(compare);
+    let index = cg.builder.build_phi(cg.i32_type, "eq_index").expect("build_phi failed");
+    let i = index.as_basic_value().into_int_value();
+    let byte_a = byte_at(cg, a, i, "eq_byte_a");
+    let byte_b = byte_at(cg, b, i, "eq_byte_b");
+    let matches = cg.builder
+        .build_int_compare(IntPredicate::EQ, byte_a, byte_b, "eq_matches")
+        .expect("build_int_compare failed");
+    cg.builder
+        .build_conditional_branch(matches, same, done)
+        .expect("build_cond_br failed");
+
+    // Equal so far; the strings are equal if that was the end of both
+    fcg.position_at_end(same);
+    let next = cg.builder
+        .build_int_add(i, cg.i32_type.const_int(1, false), "eq_next")
+        .expect("build_int_add failed");
+    index.add_incoming(&[(&cg.i32_type.const_zero(), start), (&next, same)]);
+    let at_end = cg.builder
+        .build_int_compare(IntPredicate::EQ, byte_a, cg.i8_type.const_zero(), "eq_at_end")
+        .expect("build_int_compare failed");
+    cg.builder
+        .build_conditional_branch(at_end, done, compare)
+        .expect("build_cond_br failed");
+
+    fcg.position_at_end(done);
+    let result = cg.builder.build_phi(cg.bool_type, "eq_result").expect("build_phi failed");
+    result.add_incoming(&[
+        (&cg.bool_type.const_zero(), compare),
+        (&cg.bool_type.const_all_ones(), same),
+    ]);
+    Some(result.as_basic_value())
+}
+
+// Byte `index` of the string `s`
+fn byte_at<'ctx>(cg: &CodegenCtx<'ctx>, s: PointerValue<'ctx>, index: IntValue<'ctx>, name: &str) -> IntValue<'ctx> {
+    let ptr = unsafe {
+        cg.builder
+            .build_in_bounds_gep(cg.i8_type, s, &[index], name)
+            .expect("gep failed")
+    };
+    cg.builder
+        .build_load(cg.i8_type, ptr, name)
+        .expect("load failed")
+        .into_int_value()
+}
+
+fn math_min<'ctx>(fcg: &FunctionCodegen<'_, 'ctx>, args: &[ASTValue]) -> Option<BasicValueEnum<'ctx>> {
+    let [a, b] = numeric_args(fcg, args)[..] else {
+        unreachable!("math.min takes 2 arguments");
+    };
+    let unsigned = args.iter().any(|arg| fcg.is_unsigned(arg));
+    let name = match (a.is_int_value(), unsigned) {
+        (false, _) => "llvm.minnum",
+        (true, false) => "llvm.smin",
+        (true, true) => "llvm.umin",
+    };
+    Some(call_intrinsic(fcg.cg, name, a.get_type(), &[a.into(), b.into()]))
+}
+
+fn math_max<'ctx>(fcg: &FunctionCodegen<'_, 'ctx>, args: &[ASTValue]) -> Option<BasicValueEnum<'ctx>> {
+    let [a, b] = numeric_args(fcg, args)[..] else {
+        unreachable!("math.max takes 2 arguments");
+    };
+    let unsigned = args.iter().any(|arg| fcg.is_unsigned(arg));
+    let name = match (a.is_int_value(), unsigned) {
+        (false, _) => "llvm.maxnum",
+        (true, false) => "llvm.smax",
+        (true, true) => "llvm.umax",
+    };
+    Some(call_intrinsic(fcg.cg, name, a.get_type(), &[a.into(), b.into()]))
+}
+
+fn math_clamp<'ctx>(fcg: &FunctionCodegen<'_, 'ctx>, args: &[ASTValue]) -> Option<BasicValueEnum<'ctx>> {
+    let [x, low, high] = numeric_args(fcg, args)[..] else {
+        unreachable!("math.clamp takes 3 arguments");
+    };
+    let unsigned = args.iter().any(|arg| fcg.is_unsigned(arg));
+    let (min, max) = match (x.is_int_value(), unsigned) {
+        (false, _) => ("llvm.minnum", "llvm.maxnum"),
+        (true, false) => ("llvm.smin", "llvm.smax"),
+        (true, true) => ("llvm.umin", "llvm.umax"),
+    };
+    let below_high = call_intrinsic(fcg.cg, min, x.get_type(), &[x.into(), high.into()]);
+    Some(call_intrinsic(fcg.cg, max, x.get_type(), &[below_high.into(), low.into()]))
+}
+
+fn math_abs<'ctx>(fcg: &FunctionCodegen<'_, 'ctx>, args: &[ASTValue]) -> Option<BasicValueEnum<'ctx>> {
+    let cg = fcg.cg;
+    let [x] = numeric_args(fcg, args)[..] else {
+        unreachable!("math.abs takes 1 argument");
+    };
+    if fcg.is_unsigned(&args[0]) {
+        // Already its own absolute value
+        Some(x)
+    } else if x.is_int_value() {
+        // The flag says whether abs(INT_MIN) may be poison; it wraps instead
+        Some(call_intrinsic(cg, "llvm.abs", x.get_type(), &[x.into(), cg.bool_type.const_zero().into()]))
+    } else {
+        Some(call_intrinsic(cg, "llvm.fabs", x.get_type(), &[x.into()]))
+    }
+}
+
+// The arguments of a Params::Numeric builtin, converted to the type the
+// type checker gave the call: the first argument's that isn't a literal, or
+// else the first argument's
+fn numeric_args<'ctx>(fcg: &FunctionCodegen<'_, 'ctx>, args: &[ASTValue]) -> Vec<BasicValueEnum<'ctx>> {
+    let values: Vec<BasicValueEnum<'ctx>> = args.iter().map(|arg| fcg.compile_arithmetic(arg)).collect();
+    let ty: BasicTypeEnum<'ctx> = args
+        .iter()
+        .position(|arg| !is_number_literal(arg))
+        .map_or(values[0], |i| values[i])
+        .get_type();
+    values.into_iter().map(|value| fcg.convert(value, ty)).collect()
+}
+
+// Calls the LLVM intrinsic `name`, overloaded on the operand type `ty`
+fn call_intrinsic<'ctx>(
+    cg: &CodegenCtx<'ctx>,
+    name: &str,
+    ty: BasicTypeEnum<'ctx>,
+    args: &[BasicMetadataValueEnum<'ctx>],
+) -> BasicValueEnum<'ctx> {
+    let function = Intrinsic::find(name)
+        .and_then(|intrinsic| intrinsic.get_declaration(&cg.module, &[ty]))
+        .unwrap_or_else(|| panic!("{} is missing", name));
+    cg.builder
+        .build_call(function, args, name)
+        .expect("build_call failed")
+        .try_as_basic_value()
+        .left()
+        .unwrap_or_else(|| panic!("{} returned void", name))
+}
@@ -0,0 +1,101 @@
+use crate::modules::codegen::{CodegenOptions, Target};
+use crate::modules::compiler::Compiler;
+use crate::modules::diagnostics::Severity;
+use crate::modules::tokenizer::KeywordCase;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+// The compiler as a Python module, for notebooks and build scripts. Built
+// with maturin, which enables the `python` feature (see pyproject.toml):
+//
+//   import magolor
+//   ir, diagnostics = magolor.compile(source, "hello.mg", target="wasm32-wasi")
+//
+// `ir` is the module's LLVM IR as bytes, or None if there were errors.
+
+#[pyclass(module = "magolor", name = "Diagnostic", frozen, get_all)]
+pub struct PyDiagnostic {
+    // "error" or "warning"
+    severity: String,
+    message: String,
+    // 1-based; None for problems not tied to a position
+    line: Option<usize>,
+    column: Option<usize>,
+    // The diagnostic as the command line prints it
+    rendered: String,
+}
+
+#[pymethods]
+impl PyDiagnostic {
+    fn __str__(&self) -> &str {
+        &self.rendered
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Diagnostic({:?}, {:?})", self.severity, self.message)
+    }
+}
+
+// `filename` names the source in diagnostics and is where `use` looks for
+// imported modules' .magi files. `sandbox` is for untrusted source, e.g. a
+// playground: it can't import anything from disk or use process. With `prelude=False` the
+// source has to `use` the built-in modules (console, math, time, ...) itself.
+// `keyword_case="insensitive"` accepts keywords in any case, like `Fn`.
+#[pyfunction]
+#[pyo3(signature = (source, filename = "<input>", *, target = "native", no_main = false, sandbox = false, prelude = true, keyword_case = "lowercase"))]
+fn compile<'py>(
+    py: Python<'py>,
+    source: &str,
+    filename: &str,
+    target: &str,
+    no_main: bool,
+    sandbox: bool,
+    prelude: bool,
+    keyword_case: &str,
+) -> PyResult<(Option<Bound<'py, PyBytes>>, Vec<PyDiagnostic>)> {
+    let target = Target::from_name(target)
+        .ok_or_else(|| PyValueError::new_err(format!("unknown target '{}'", target)))?;
+    let keyword_case = KeywordCase::from_name(keyword_case)
+        .ok_or_else(|| PyValueError::new_err(format!("unknown keyword case '{}'", keyword_case)))?;
+
+    let mut compiler = Compiler::new();
+    compiler.set_codegen_options(CodegenOptions {
+        target,
+        no_main,
+        ..CodegenOptions::default()
+    });
+    compiler.set_sandbox(sandbox);
+    compiler.set_prelude(prelude);
+    compiler.set_keyword_case(keyword_case);
+    let (ir, diagnostics) = compiler.compile(filename, source);
+
+    let sources = compiler.source_map();
+    let diagnostics = diagnostics
+        .sorted()
+        .into_iter()
+        .map(|diagnostic| {
+            let location = diagnostic.span.and_then(|span| sources.location(span.start));
+            PyDiagnostic {
+                severity: match diagnostic.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                }
+                .to_string(),
+                message: diagnostic.message.clone(),
+                line: location.map(|(_, line, _)| line),
+                column: location.map(|(_, _, col)| col),
+                rendered: sources.render(diagnostic),
+            }
+        })
+        .collect();
+
+    Ok((ir.map(|ir| PyBytes::new(py, ir.as_bytes())), diagnostics))
+}
+
+#[pymodule]
+fn magolor(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyDiagnostic>()?;
+    m.add_function(wrap_pyfunction!(compile, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use crate::modules::diagnostics::Span;
+use crate::modules::tokenizer::Token;
+use crate::modules::typeck::TypeInfo;
+use crate::modules::types::Type;
+
+// Answers "what is the type of the thing at this offset" for hover and inlay
+// hints. The AST doesn't carry positions, so this works on the spanned token
+// stream and uses the type checker's tables for the actual answer.
+pub fn type_at(tokens: &[(Token, Span)], info: &TypeInfo, offset: usize) -> Option<Type> {
+    let index = token_index_at(tokens, offset)?;
+
+    match &tokens[index].0 {
+        Token::Integer(n) if i32::try_from(*n).is_ok() => Some(Type::I32),
+        Token::Integer(_) => Some(Type::I64),
+        Token::Integer64(_) => Some(Type::I64),
+        Token::UInt8(_) => Some(Type::U8),
+        Token::UInt32(_) => Some(Type::U32),
+        Token::UInt64(_) => Some(Type::U64),
+        Token::Float32(_) | Token::DefaultFloat(_) => Some(Type::F32),
+        Token::Float64(_) => Some(Type::F64),
+        Token::Bool(_) => Some(Type::Bool),
+        Token::Char(_) => Some(Type::Char),
+        Token::String(_) => Some(Type::Str),
+        Token::Ident(name) => {
+            let is_call = matches!(tokens.get(index + 1), Some((Token::LParen, _)));
+            let is_fn_name = index > 0 && tokens[index - 1].0 == Token::Func;
+            if is_call || is_fn_name {
+                return info.return_type(name);
+            }
+
+            let function = enclosing_function(tokens, index)?;
+            info.local_type(&function, name)
+        }
+        _ => None,
+    }
+}
+
+pub fn token_index_at(tokens: &[(Token, Span)], offset: usize) -> Option<usize> {
+    // Tokens are in source order, so the candidate is the last one starting
+    // at or before the offset
+    let index = tokens.partition_point(|(_, span)| span.start <= offset);
+    if index == 0 {
+        return None;
+    }
+    let (_, span) = &tokens[index - 1];
+    (offset < span.end).then_some(index - 1)
+}
+
+// Name of the function whose body contains the token at `index`
+pub fn enclosing_function(tokens: &[(Token, Span)], index: usize) -> Option<String> {
+    let mut current = None;
+    let mut pending = None;
+    let mut depth = 0;
+
+    for (i, (tok, _)) in tokens.iter().enumerate() {
+        match tok {
+            Token::Func => {
+                if let Some((Token::Ident(name), _)) = tokens.get(i + 1) {
+                    pending = Some(name.clone());
+                }
+            }
+            Token::LBrace => {
+                if depth == 0 {
+                    current = pending.take();
+                }
+                depth += 1;
+            }
+            Token::RBrace => {
+                depth -= 1;
+                if depth == 0 && i < index {
+                    current = None;
+                }
+            }
+            _ => {}
+        }
+        if i == index {
+            // Parameters sit between the name and the body
+            return current.or(pending).map(|name| name.to_string());
+        }
+    }
+
+    None
+}
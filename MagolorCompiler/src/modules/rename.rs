@@ -0,0 +1,76 @@
+use crate::modules::diagnostics::Span;
+use crate::modules::index::{Symbol, SymbolIndex};
+use crate::modules::source::{SourceFile, SourceMap};
+use crate::modules::tokenizer::{Token, tokenizeFile};
+
+// Renames the symbol under `offset` (a source map offset) everywhere it is
+// defined or used and returns the rewritten text of every file that changed.
+// Refuses rather than guessing whenever the result could change meaning.
+pub fn rename(
+    sources: &SourceMap,
+    index: &SymbolIndex,
+    offset: usize,
+    new_name: &str,
+) -> Result<Vec<(String, String)>, String> {
+    // Must lex as exactly one identifier, which also rules out keywords
+    match tokenizeFile(new_name).as_slice() {
+        [Token::Ident(name)] if &**name == new_name => {}
+        _ => return Err(format!("'{}' is not a valid identifier", new_name)),
+    }
+
+    let symbol = index
+        .symbol_at(offset)
+        .ok_or_else(|| "no renameable symbol at that position".to_string())?;
+
+    if symbol.name() == new_name {
+        return Ok(Vec::new());
+    }
+
+    let collision = match symbol {
+        // The calls are `value.method(...)`, and which ones are this method
+        // depends on the type of value, which the index doesn't know
+        Symbol::Method { .. } => {
+            return Err(format!("cannot rename method {}: its calls can't be found without types", symbol));
+        }
+        Symbol::Function(_) => index.get(&Symbol::Function(new_name.to_string())).is_some(),
+        // A local can't take the name of another local in the same function,
+        // and sharing a name with a function is too easy to misread
+        Symbol::Local { function, .. } => {
+            index
+                .get(&Symbol::Local {
+                    function: function.clone(),
+                    name: new_name.to_string(),
+                })
+                .is_some()
+                || index.get(&Symbol::Function(new_name.to_string())).is_some()
+        }
+    };
+    if collision {
+        return Err(format!("cannot rename {} to '{}': name already in use", symbol, new_name));
+    }
+
+    let entry = index.get(symbol).expect("symbol came from the index");
+    let mut edits: Vec<(&SourceFile, Vec<Span>)> = Vec::new();
+    for span in entry.definition.iter().chain(entry.references.iter()) {
+        let (file, local) = sources
+            .local_span(*span)
+            .ok_or_else(|| format!("{} has a position outside every loaded file", symbol))?;
+        match edits.iter_mut().find(|(f, _)| f.path() == file.path()) {
+            Some((_, spans)) => spans.push(local),
+            None => edits.push((file, vec![local])),
+        }
+    }
+
+    Ok(edits
+        .into_iter()
+        .map(|(file, mut spans)| {
+            // Rewrite back to front so earlier offsets stay valid
+            spans.sort_by_key(|span| std::cmp::Reverse(span.start));
+            let mut output = file.text().to_string();
+            for span in spans {
+                output.replace_range(span.start..span.end, new_name);
+            }
+            (file.path().to_string(), output)
+        })
+        .collect())
+}
@@ -0,0 +1,430 @@
+use crate::modules::codegen::{Target, Toolchain};
+use inkwell::AddressSpace;
+use inkwell::IntPredicate;
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::module::{Linkage, Module};
+use inkwell::types::{FunctionType, IntType};
+use inkwell::values::{BasicMetadataValueEnum, FunctionValue, IntValue, PointerValue};
+
+// The functions generated code calls into: puts for console.print, write and
+// exit for the stack check. They keep the libc signatures on every target so
+// call sites don't care which one they're compiled for. Natively they are
+// libc's; wasm has no libc to link, so there they are defined here on top of
+// whatever the host imports.
+pub struct Runtime<'ctx> {
+    pub puts: FunctionValue<'ctx>,
+    pub write: FunctionValue<'ctx>,
+    pub exit: FunctionValue<'ctx>,
+}
+
+const WASI_MODULE: &str = "wasi_snapshot_preview1";
+const STDOUT: u64 = 1;
+// WASI's wall clock, as opposed to the monotonic one
+const WASI_REALTIME: u64 = 0;
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+pub fn define_runtime<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    target: Target,
+) -> Runtime<'ctx> {
+    match target {
+        Target::Native => declare_libc(context, module),
+        Target::Wasm32Wasi => define_wasi(context, module),
+        Target::Wasm32Unknown => define_browser(context, module),
+    }
+}
+
+fn declare_libc<'ctx>(context: &'ctx Context, module: &Module<'ctx>) -> Runtime<'ctx> {
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+
+    let puts_type = i32_type.fn_type(&[ptr_type.into()], false);
+    let write_type = i64_type.fn_type(&[i32_type.into(), ptr_type.into(), i64_type.into()], false);
+    let exit_type = context.void_type().fn_type(&[i32_type.into()], false);
+
+    let exit = module.add_function("exit", exit_type, None);
+    mark_noreturn(context, exit);
+
+    Runtime {
+        puts: module.add_function("puts", puts_type, None),
+        write: module.add_function("write", write_type, None),
+        exit,
+    }
+}
+
+// WASI preview1: output goes through fd_write and exit is proc_exit
+fn define_wasi<'ctx>(context: &'ctx Context, module: &Module<'ctx>) -> Runtime<'ctx> {
+    let i32_type = context.i32_type();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+
+    // errno fd_write(fd, const iovec *iovs, size_t iovs_len, size_t *nwritten)
+    let fd_write_type = i32_type.fn_type(
+        &[i32_type.into(), ptr_type.into(), i32_type.into(), ptr_type.into()],
+        false,
+    );
+    let fd_write = import(context, module, WASI_MODULE, "fd_write", "__wasi_fd_write", fd_write_type);
+
+    let exit_type = context.void_type().fn_type(&[i32_type.into()], false);
+    let exit = import(context, module, WASI_MODULE, "proc_exit", "__wasi_proc_exit", exit_type);
+    mark_noreturn(context, exit);
+
+    let write = define_wasi_write(context, module, fd_write);
+    let puts = define_puts(context, module, write);
+
+    Runtime { puts, write, exit }
+}
+
+// wasm32-unknown-unknown in a browser: the page supplies env.magolor_print,
+// which the --emit js-glue script implements on top of console.log
+fn define_browser<'ctx>(context: &'ctx Context, module: &Module<'ctx>) -> Runtime<'ctx> {
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+
+    let print_type = context.void_type().fn_type(&[ptr_type.into(), i32_type.into()], false);
+    let print = import(context, module, "env", "magolor_print", "__env_magolor_print", print_type);
+
+    // Every fd ends up on the console, so write just forwards the bytes
+    let write_type = i64_type.fn_type(&[i32_type.into(), ptr_type.into(), i64_type.into()], false);
+    let write = module.add_function("__magolor_write", write_type, Some(Linkage::Internal));
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(write, "entry"));
+    let buf = write.get_nth_param(1).unwrap().into_pointer_value();
+    let len = write.get_nth_param(2).unwrap().into_int_value();
+    let len32 = builder
+        .build_int_truncate(len, i32_type, "len32")
+        .expect("build_int_truncate failed");
+    builder
+        .build_call(print, &[buf.into(), len32.into()], "")
+        .expect("build_call failed");
+    builder.build_return(Some(&len)).expect("build_return failed");
+
+    // There's no process to end; trapping unwinds back into the JS caller
+    let exit_type = context.void_type().fn_type(&[i32_type.into()], false);
+    let exit = module.add_function("__magolor_exit", exit_type, Some(Linkage::Internal));
+    mark_noreturn(context, exit);
+    builder.position_at_end(context.append_basic_block(exit, "entry"));
+    builder.build_unreachable().expect("build_unreachable failed");
+
+    let puts = define_puts(context, module, write);
+
+    Runtime { puts, write, exit }
+}
+
+// i64 __magolor_time(): seconds since the Unix epoch, for time.now(). Unlike
+// the functions in Runtime it's only generated, along with whatever it
+// imports, the first time a module calls it.
+pub fn time_fn<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    target: Target,
+    toolchain: Toolchain,
+) -> FunctionValue<'ctx> {
+    const NAME: &str = "__magolor_time";
+    if let Some(function) = module.get_function(NAME) {
+        return function;
+    }
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+
+    let function = module.add_function(NAME, i64_type.fn_type(&[], false), Some(Linkage::Internal));
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(function, "entry"));
+
+    let seconds = match target {
+        Target::Native => {
+            // The Windows CRTs' time() is an inline wrapper around _time64
+            let name = match toolchain {
+                Toolchain::Msvc | Toolchain::MinGw => "_time64",
+                Toolchain::Unix | Toolchain::Apple => "time",
+            };
+            let time = module.add_function(name, i64_type.fn_type(&[ptr_type.into()], false), None);
+            call(&builder, time, &[ptr_type.const_null().into()])
+        }
+        Target::Wasm32Wasi => {
+            // errno clock_time_get(clockid, timestamp precision, timestamp *time),
+            // in nanoseconds
+            let fn_type = i32_type.fn_type(&[i32_type.into(), i64_type.into(), ptr_type.into()], false);
+            let clock_time_get =
+                import(context, module, WASI_MODULE, "clock_time_get", "__wasi_clock_time_get", fn_type);
+            let nanos = zeroed(&builder, i64_type, "nanos");
+            call(
+                &builder,
+                clock_time_get,
+                &[
+                    i32_type.const_int(WASI_REALTIME, false).into(),
+                    i64_type.const_int(NANOS_PER_SECOND, false).into(),
+                    nanos.into(),
+                ],
+            );
+            let nanos = builder
+                .build_load(i64_type, nanos, "nanos")
+                .expect("build_load failed")
+                .into_int_value();
+            builder
+                .build_int_signed_div(nanos, i64_type.const_int(NANOS_PER_SECOND, false), "seconds")
+                .expect("build_int_signed_div failed")
+        }
+        // The --emit js-glue loader reads Date.now()
+        Target::Wasm32Unknown => {
+            let fn_type = i64_type.fn_type(&[], false);
+            let now = import(context, module, "env", "magolor_time", "__env_magolor_time", fn_type);
+            call(&builder, now, &[])
+        }
+    };
+    builder.build_return(Some(&seconds)).expect("build_return failed");
+    function
+}
+
+// i64 __magolor_random(): 64 bits from the host's source of randomness, for
+// random.next(). Generated on first use, like time_fn. If the host can't
+// supply them the bits are zero.
+pub fn random_fn<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    target: Target,
+    toolchain: Toolchain,
+) -> FunctionValue<'ctx> {
+    const NAME: &str = "__magolor_random";
+    if let Some(function) = module.get_function(NAME) {
+        return function;
+    }
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+
+    let function = module.add_function(NAME, i64_type.fn_type(&[], false), Some(Linkage::Internal));
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(function, "entry"));
+
+    let bits = zeroed(&builder, i64_type, "bits");
+    match (target, toolchain) {
+        // errno_t rand_s(unsigned int *), 32 bits at a time
+        (Target::Native, Toolchain::Msvc | Toolchain::MinGw) => {
+            let rand_s = module.add_function("rand_s", i32_type.fn_type(&[ptr_type.into()], false), None);
+            let high = unsafe {
+                builder
+                    .build_gep(i32_type, bits, &[i32_type.const_int(1, false)], "high")
+                    .expect("build_gep failed")
+            };
+            call(&builder, rand_s, &[bits.into()]);
+            call(&builder, rand_s, &[high.into()]);
+        }
+        // int getentropy(void *buf, size_t len)
+        (Target::Native, Toolchain::Unix | Toolchain::Apple) => {
+            let fn_type = i32_type.fn_type(&[ptr_type.into(), i64_type.into()], false);
+            let getentropy = module.add_function("getentropy", fn_type, None);
+            call(&builder, getentropy, &[bits.into(), i64_type.const_int(8, false).into()]);
+        }
+        // errno random_get(u8 *buf, size_t buf_len)
+        (Target::Wasm32Wasi, _) => {
+            let fn_type = i32_type.fn_type(&[ptr_type.into(), i32_type.into()], false);
+            let random_get = import(context, module, WASI_MODULE, "random_get", "__wasi_random_get", fn_type);
+            call(&builder, random_get, &[bits.into(), i32_type.const_int(8, false).into()]);
+        }
+        // The --emit js-glue loader asks crypto.getRandomValues
+        (Target::Wasm32Unknown, _) => {
+            let fn_type = i64_type.fn_type(&[], false);
+            let random = import(context, module, "env", "magolor_random", "__env_magolor_random", fn_type);
+            let value = call(&builder, random, &[]);
+            builder.build_store(bits, value).expect("build_store failed");
+        }
+    }
+    let value = builder.build_load(i64_type, bits, "bits").expect("build_load failed");
+    builder.build_return(Some(&value)).expect("build_return failed");
+    function
+}
+
+// A zeroed stack slot for a host call to write into
+fn zeroed<'ctx>(builder: &Builder<'ctx>, ty: IntType<'ctx>, name: &str) -> PointerValue<'ctx> {
+    let slot = builder.build_alloca(ty, name).expect("alloca failed");
+    builder.build_store(slot, ty.const_zero()).expect("build_store failed");
+    slot
+}
+
+// Calls a function returning an integer and gives back the integer
+fn call<'ctx>(
+    builder: &Builder<'ctx>,
+    function: FunctionValue<'ctx>,
+    args: &[BasicMetadataValueEnum<'ctx>],
+) -> IntValue<'ctx> {
+    builder
+        .build_call(function, args, "call")
+        .expect("build_call failed")
+        .try_as_basic_value()
+        .left()
+        .expect("host function returned void")
+        .into_int_value()
+}
+
+// Declares `name` from the host module `from`, under a local symbol that
+// can't collide with a user function of the same name
+fn import<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    from: &str,
+    name: &str,
+    symbol: &str,
+    fn_type: FunctionType<'ctx>,
+) -> FunctionValue<'ctx> {
+    let function = module.add_function(symbol, fn_type, None);
+    function.add_attribute(
+        AttributeLoc::Function,
+        context.create_string_attribute("wasm-import-module", from),
+    );
+    function.add_attribute(
+        AttributeLoc::Function,
+        context.create_string_attribute("wasm-import-name", name),
+    );
+    function
+}
+
+fn mark_noreturn<'ctx>(context: &'ctx Context, function: FunctionValue<'ctx>) {
+    let kind = Attribute::get_named_enum_kind_id("noreturn");
+    function.add_attribute(AttributeLoc::Function, context.create_enum_attribute(kind, 0));
+}
+
+// i64 write(i32 fd, ptr buf, i64 len): one fd_write with a single iovec.
+// Returns the bytes written, or -1 if WASI reported an error.
+fn define_wasi_write<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    fd_write: FunctionValue<'ctx>,
+) -> FunctionValue<'ctx> {
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+    // struct iovec { const void *buf; size_t len; } with 32-bit size_t
+    let iovec_type = context.struct_type(&[ptr_type.into(), i32_type.into()], false);
+
+    let fn_type = i64_type.fn_type(&[i32_type.into(), ptr_type.into(), i64_type.into()], false);
+    let function = module.add_function("__magolor_write", fn_type, Some(Linkage::Internal));
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(function, "entry"));
+
+    let fd = function.get_nth_param(0).unwrap().into_int_value();
+    let buf = function.get_nth_param(1).unwrap().into_pointer_value();
+    let len = function.get_nth_param(2).unwrap().into_int_value();
+
+    let iovec = builder.build_alloca(iovec_type, "iovec").expect("alloca failed");
+    let buf_field = builder
+        .build_struct_gep(iovec_type, iovec, 0, "iovec_buf")
+        .expect("build_struct_gep failed");
+    builder.build_store(buf_field, buf).expect("build_store failed");
+    let len_field = builder
+        .build_struct_gep(iovec_type, iovec, 1, "iovec_len")
+        .expect("build_struct_gep failed");
+    let len32 = builder
+        .build_int_truncate(len, i32_type, "len32")
+        .expect("build_int_truncate failed");
+    builder.build_store(len_field, len32).expect("build_store failed");
+
+    let nwritten = builder.build_alloca(i32_type, "nwritten").expect("alloca failed");
+    let errno = builder
+        .build_call(
+            fd_write,
+            &[fd.into(), iovec.into(), i32_type.const_int(1, false).into(), nwritten.into()],
+            "errno",
+        )
+        .expect("build_call failed")
+        .try_as_basic_value()
+        .left()
+        .expect("fd_write returned void")
+        .into_int_value();
+
+    let written = builder
+        .build_load(i32_type, nwritten, "written")
+        .expect("build_load failed")
+        .into_int_value();
+    let written = builder
+        .build_int_z_extend(written, i64_type, "written64")
+        .expect("build_int_z_extend failed");
+    let failed = builder
+        .build_int_compare(IntPredicate::NE, errno, i32_type.const_zero(), "failed")
+        .expect("build_int_compare failed");
+    let result = builder
+        .build_select(failed, i64_type.const_all_ones(), written, "result")
+        .expect("build_select failed");
+    builder.build_return(Some(&result)).expect("build_return failed");
+
+    function
+}
+
+// i32 puts(ptr s) over the target's write: finds the terminating NUL, then
+// writes the string and a newline to stdout
+fn define_puts<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    write: FunctionValue<'ctx>,
+) -> FunctionValue<'ctx> {
+    let i8_type = context.i8_type();
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+
+    let fn_type = i32_type.fn_type(&[ptr_type.into()], false);
+    let function = module.add_function("__magolor_puts", fn_type, Some(Linkage::Internal));
+    let builder = context.create_builder();
+
+    let entry = context.append_basic_block(function, "entry");
+    let scan = context.append_basic_block(function, "scan");
+    let done = context.append_basic_block(function, "done");
+    let s = function.get_nth_param(0).unwrap().into_pointer_value();
+
+    builder.position_at_end(entry);
+    builder.build_unconditional_branch(scan).expect("build_br failed");
+
+    builder.position_at_end(scan);
+    let len = builder.build_phi(i64_type, "len").expect("build_phi failed");
+    let len_value = len.as_basic_value().into_int_value();
+    // Safety: the scan stops at the NUL every string literal ends with
+    let byte_ptr = unsafe {
+        builder
+            .build_gep(i8_type, s, &[len_value], "byte_ptr")
+            .expect("build_gep failed")
+    };
+    let byte = builder
+        .build_load(i8_type, byte_ptr, "byte")
+        .expect("build_load failed")
+        .into_int_value();
+    let at_end = builder
+        .build_int_compare(IntPredicate::EQ, byte, i8_type.const_zero(), "at_end")
+        .expect("build_int_compare failed");
+    let next = builder
+        .build_int_add(len_value, i64_type.const_int(1, false), "next")
+        .expect("build_int_add failed");
+    len.add_incoming(&[(&i64_type.const_zero(), entry), (&next, scan)]);
+    builder
+        .build_conditional_branch(at_end, done, scan)
+        .expect("build_cond_br failed");
+
+    builder.position_at_end(done);
+    let stdout = i32_type.const_int(STDOUT, false);
+    builder
+        .build_call(write, &[stdout.into(), s.into(), len_value.into()], "")
+        .expect("build_call failed");
+    let newline = builder
+        .build_global_string_ptr("\n", "newline")
+        .expect("build_global_string_ptr failed");
+    builder
+        .build_call(
+            write,
+            &[
+                stdout.into(),
+                newline.as_pointer_value().into(),
+                i64_type.const_int(1, false).into(),
+            ],
+            "",
+        )
+        .expect("build_call failed");
+    builder
+        .build_return(Some(&i32_type.const_zero()))
+        .expect("build_return failed");
+
+    function
+}
@@ -0,0 +1,191 @@
+use crate::modules::diagnostics::{Diagnostic, Span};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io;
+
+// Files at least this big are mapped instead of read; below it the syscalls
+// cost more than the copy
+const MMAP_THRESHOLD: u64 = 64 * 1024;
+
+enum Backing {
+    Owned(String),
+    // Checked to be UTF-8 when the file is opened
+    Mapped(Mmap),
+}
+
+// One loaded source file plus the start offset of every line, so turning a
+// span into line:col is a binary search instead of a rescan of the text
+pub struct SourceFile {
+    path: String,
+    backing: Backing,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        if len < MMAP_THRESHOLD {
+            return Ok(SourceFile::from_string(path, io::read_to_string(file)?));
+        }
+
+        // Safety: the map is read-only and private to us. Like every other
+        // compiler we assume the source isn't rewritten mid-compilation.
+        let map = unsafe { Mmap::map(&file)? };
+        if let Err(e) = std::str::from_utf8(&map) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+        Ok(SourceFile::new(path, Backing::Mapped(map)))
+    }
+
+    pub fn from_string(path: &str, text: String) -> Self {
+        SourceFile::new(path, Backing::Owned(text))
+    }
+
+    fn new(path: &str, backing: Backing) -> Self {
+        let mut file = SourceFile {
+            path: path.to_string(),
+            backing,
+            line_starts: Vec::new(),
+        };
+        file.line_starts = std::iter::once(0)
+            .chain(file.text().match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        file
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn text(&self) -> &str {
+        match &self.backing {
+            Backing::Owned(text) => text,
+            // Safety: validated in open() and the mapping is never written
+            Backing::Mapped(map) => unsafe { std::str::from_utf8_unchecked(map) },
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    // 1-based line and column of a byte offset, for printing positions
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let text = self.text();
+        let offset = offset.min(text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let line_start = self.line_starts[line - 1];
+        (line, text[line_start..offset].chars().count() + 1)
+    }
+
+    // Inverse of line_col; None if the position is past the end of its line
+    pub fn offset_at(&self, line: usize, col: usize) -> Option<usize> {
+        if line == 0 || col == 0 {
+            return None;
+        }
+        let line_start = *self.line_starts.get(line - 1)?;
+        let line_text = self.text()[line_start..].split('\n').next().unwrap_or("");
+        let line_text = line_text.strip_suffix('\r').unwrap_or(line_text);
+        if col - 1 == line_text.chars().count() {
+            return Some(line_start + line_text.len());
+        }
+        line_text
+            .char_indices()
+            .nth(col - 1)
+            .map(|(i, _)| line_start + i)
+    }
+
+    // `path:line:col: error: message`, falling back to the plain form for
+    // diagnostics without a position
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        match diagnostic.span {
+            Some(span) => {
+                let (line, col) = self.line_col(span.start);
+                let plain = Diagnostic {
+                    span: None,
+                    ..diagnostic.clone()
+                };
+                format!("{}:{}:{}: {}", self.path, line, col, plain)
+            }
+            None => diagnostic.to_string(),
+        }
+    }
+}
+
+// Every file loaded during a compilation, laid end to end in one offset
+// space so a plain Span can point into any of them. Each file starts one
+// past the end of the previous one, which keeps end-of-file offsets
+// unambiguous.
+#[derive(Default)]
+pub struct SourceMap {
+    // (global offset of the first byte, file), in load order
+    files: Vec<(usize, SourceFile)>,
+    next_base: usize,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap::default()
+    }
+
+    // Returns the global offset the file's local offsets are relative to
+    pub fn add(&mut self, file: SourceFile) -> usize {
+        let base = self.next_base;
+        self.next_base = base + file.text().len() + 1;
+        self.files.push((base, file));
+        base
+    }
+
+    pub fn load(&mut self, path: &str) -> io::Result<usize> {
+        Ok(self.add(SourceFile::open(path)?))
+    }
+
+    // Most recently loaded file with this path, and its base offset
+    pub fn file(&self, path: &str) -> Option<(usize, &SourceFile)> {
+        self.files
+            .iter()
+            .rev()
+            .find(|(_, file)| file.path() == path)
+            .map(|(base, file)| (*base, file))
+    }
+
+    // File containing a global offset, and the offset local to that file
+    pub fn lookup(&self, offset: usize) -> Option<(&SourceFile, usize)> {
+        let index = self.files.partition_point(|(base, _)| *base <= offset);
+        let (base, file) = self.files.get(index.checked_sub(1)?)?;
+        let local = offset - base;
+        (local <= file.text().len()).then_some((file, local))
+    }
+
+    // Splits a global span into its file and file-local range
+    pub fn local_span(&self, span: Span) -> Option<(&SourceFile, Span)> {
+        let (file, start) = self.lookup(span.start)?;
+        let end = start + (span.end - span.start);
+        Some((file, Span { start, end }))
+    }
+
+    // `(path, line, col)` of a global offset
+    pub fn location(&self, offset: usize) -> Option<(&str, usize, usize)> {
+        let (file, local) = self.lookup(offset)?;
+        let (line, col) = file.line_col(local);
+        Some((file.path(), line, col))
+    }
+
+    // Global offset of a 1-based position in a loaded file
+    pub fn offset_at(&self, path: &str, line: usize, col: usize) -> Option<usize> {
+        let (base, file) = self.file(path)?;
+        Some(base + file.offset_at(line, col)?)
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        match diagnostic.span.and_then(|span| self.local_span(span)) {
+            Some((file, span)) => file.render(&Diagnostic {
+                span: Some(span),
+                ..diagnostic.clone()
+            }),
+            None => diagnostic.to_string(),
+        }
+    }
+}
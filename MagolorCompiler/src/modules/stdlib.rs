@@ -0,0 +1,41 @@
+use crate::modules::compiler::Compiler;
+use crate::modules::consteval;
+use crate::modules::diagnostics::Severity;
+use crate::modules::interface::ModuleInterface;
+use crate::modules::parser::AST;
+
+// The parts of the standard library written in Magolor, as opposed to the
+// prelude's builtins, which codegen lowers by hand. They ship inside the
+// compiler as source. A program that `use`s one has it compiled on the
+// spot, and the functions it calls become part of the program's own module,
+// so there is no separate library to build or link.
+const MODULES: &[(&str, &str)] = &[("numbers", include_str!("../../std/numbers.mg"))];
+
+// A standard library module, type checked and ready to link into a program
+pub struct StdModule {
+    pub interface: ModuleInterface,
+    pub ast: Vec<AST>,
+}
+
+// Compiles `module`, if the standard library has one by that name. An error
+// here is a bug in the standard library rather than in the program using it.
+pub fn load(module: &str) -> Option<Result<StdModule, String>> {
+    let (_, source) = MODULES.iter().find(|(name, _)| *name == module)?;
+
+    let mut compiler = Compiler::new();
+    let ast = compiler.parse(&format!("std/{}.mg", module), source);
+    compiler.analyze(&ast);
+    let interface = compiler.interface().expect("analysis just ran");
+    let mut diagnostics = compiler.take_diagnostics();
+    let ast = consteval::fold_const_calls(ast, &mut diagnostics);
+
+    let error = diagnostics
+        .sorted()
+        .into_iter()
+        .find(|diagnostic| diagnostic.severity == Severity::Error)
+        .map(|diagnostic| diagnostic.message.clone());
+    Some(match error {
+        Some(message) => Err(format!("standard library module '{}' failed to compile: {}", module, message)),
+        None => Ok(StdModule { interface, ast }),
+    })
+}
@@ -1,36 +1,95 @@
-use logos::Logos;
+use crate::modules::diagnostics::Span;
+use logos::{FilterResult, Logos, SpannedIter};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::str::FromStr;
+
+// Hands out one shared allocation per distinct identifier, so a name that
+// appears a thousand times in a file is stored once and every token holding
+// it is a refcount away from any other
+#[derive(Debug, Default)]
+pub struct Interner {
+    names: HashSet<Rc<str>>,
+}
+
+impl Interner {
+    pub fn intern(&mut self, name: &str) -> Rc<str> {
+        if let Some(existing) = self.names.get(name) {
+            return existing.clone();
+        }
+        let name: Rc<str> = Rc::from(name);
+        self.names.insert(name.clone());
+        name
+    }
+}
 
 #[derive(Logos, Debug, PartialEq)]
+#[logos(extras = Interner)]
 pub enum Token {
-    #[regex(r#""([^"]*)""#, |lex| lex.slice().trim_matches('"').to_string())]
+    // A string can't run past the end of its line
+    #[regex(r#""([^"\n]*)""#, |lex| lex.slice().trim_matches('"').to_string())]
     String(String),
     
-    // Numeric literals
-    #[regex(r"[0-9]+\.[0-9]+f64", |lex| lex.slice().trim_end_matches("f64").parse::<f64>().ok())]
+    // Numeric literals. Digits may be grouped with underscores (1_000_000,
+    // 0xFF_FF), which are dropped before parsing. A float has a fraction, an
+    // exponent or both: 1.5, 2e-3, 1.5e10.
+    #[regex(r"[0-9][0-9_]*(\.[0-9][0-9_]*([eE][+-]?[0-9][0-9_]*)?|[eE][+-]?[0-9][0-9_]*)f64", |lex| decimal(lex.slice(), "f64"))]
     Float64(f64),
-    #[regex(r"[0-9]+\.[0-9]+f32", |lex| lex.slice().trim_end_matches("f32").parse::<f32>().ok())]
+    #[regex(r"[0-9][0-9_]*(\.[0-9][0-9_]*([eE][+-]?[0-9][0-9_]*)?|[eE][+-]?[0-9][0-9_]*)f32", |lex| decimal(lex.slice(), "f32"))]
     Float32(f32),
-    #[regex(r"[0-9]+\.[0-9]+", |lex| lex.slice().parse::<f32>().ok())] // Default float is f32
+    #[regex(r"[0-9][0-9_]*(\.[0-9][0-9_]*([eE][+-]?[0-9][0-9_]*)?|[eE][+-]?[0-9][0-9_]*)", |lex| decimal(lex.slice(), ""))] // Default float is f32
     DefaultFloat(f32),
-    #[regex(r"[0-9]+i64", |lex| lex.slice().trim_end_matches("i64").parse::<i64>().ok())]
+    #[regex(r"[0-9][0-9_]*i64", |lex| decimal(lex.slice(), "i64"))]
+    #[regex(r"0x[0-9a-fA-F_]+i64", |lex| hex(lex.slice(), "i64").map(|n| n as i64))]
     Integer64(i64),
-    #[regex(r"[0-9]+", |lex| lex.slice().parse::<i32>().ok())]
-    Integer(i32),
+    // Unsuffixed, so an i32 unless it doesn't fit; whether it fits where
+    // it's used is up to the type checker
+    #[regex(r"[0-9][0-9_]*", |lex| decimal(lex.slice(), ""))]
+    // Hex spells out bits, so 0xFFFF_FFFF is -1 rather than out of range
+    #[regex(r"0x[0-9a-fA-F_]+", |lex| hex(lex.slice(), "").and_then(|n| u32::try_from(n).ok()).map(|n| n as i32 as i64))]
+    Integer(i64),
+    // Unsigned literals always carry their suffix: 255u8, 4_000_000_000u32,
+    // 0xFFu8. Out of range for the type is a lex error, as with the others.
+    #[regex(r"[0-9][0-9_]*u8", |lex| decimal(lex.slice(), "u8"))]
+    #[regex(r"0x[0-9a-fA-F_]+u8", |lex| hex(lex.slice(), "u8").and_then(|n| u8::try_from(n).ok()))]
+    UInt8(u8),
+    #[regex(r"[0-9][0-9_]*u32", |lex| decimal(lex.slice(), "u32"))]
+    #[regex(r"0x[0-9a-fA-F_]+u32", |lex| hex(lex.slice(), "u32").and_then(|n| u32::try_from(n).ok()))]
+    UInt32(u32),
+    #[regex(r"[0-9][0-9_]*u64", |lex| decimal(lex.slice(), "u64"))]
+    #[regex(r"0x[0-9a-fA-F_]+u64", |lex| hex(lex.slice(), "u64"))]
+    UInt64(u64),
     
+    // 'a', or an escape: '\n', '\t', '\r', '\0', '\\', '\'' and '\"'. Chars
+    // are a byte, so only ASCII fits.
+    #[regex(r"'([^'\\\n]|\\.)'", |lex| char_literal(lex.slice()))]
+    Char(u8),
+
     // Boolean literals
     #[regex(r"true|false", |lex| lex.slice().parse::<bool>().ok())]
     Bool(bool),
+    #[token("null")]
+    Null,
     
     // Keywords
-    #[regex("fn|func|Fn|Func")]
+    #[regex("fn|func")]
     Func,
-    #[regex("return|Return")]
+    #[token("return")]
     Return,
     #[token("let")]
     Let,
+    #[token("mut")]
+    Mut,
+    #[token("const")]
+    Const,
     #[token("use")]
     Use,
-    #[regex("void|Void")]
+    #[token("pub")]
+    Pub,
+    #[token("impl")]
+    Impl,
+    #[token("void")]
     Void,
     #[token("if")]
     If,
@@ -38,6 +97,26 @@ pub enum Token {
     Else,
     #[token("elif")]
     Elif,
+    // Loops. `while` and `for ... in` parse; the rest are reserved
+    #[token("while")]
+    While,
+    #[token("for")]
+    For,
+    #[token("in")]
+    In,
+    #[token("loop")]
+    Loop,
+    #[token("break")]
+    Break,
+    #[token("continue")]
+    Continue,
+    // User-defined types and pattern matching, likewise reserved
+    #[token("struct")]
+    Struct,
+    #[token("enum")]
+    Enum,
+    #[token("match")]
+    Match,
     #[token(">")]
     Greater,
     #[token("<")]
@@ -51,19 +130,47 @@ pub enum Token {
     #[token("==")]
     EqEq,
 
+    // Arithmetic
+    #[token("+")]
+    Plus,
+    #[token("-")]
+    Minus,
+    #[token("*")]
+    Star,
+    #[token("/")]
+    Slash,
+    #[token("%")]
+    Percent,
+
+    // Logic
+    #[token("&&")]
+    AndAnd,
+    #[token("||")]
+    OrOr,
+    #[token("!")]
+    Bang,
+
     // Type keywords
     #[token("i32")]
     I32Type,
     #[token("i64")]
     I64Type,
+    #[token("u8")]
+    U8Type,
+    #[token("u32")]
+    U32Type,
+    #[token("u64")]
+    U64Type,
     #[token("f32")]
     F32Type,
     #[token("f64")]
     F64Type,
-    #[regex("String|string")]
+    #[token("string")]
     StringType,
-    #[regex("bool|Bool")]
+    #[token("bool")]
     BoolType,
+    #[token("char")]
+    CharType,
     
     // Punctuation
     #[token("(")]
@@ -84,18 +191,422 @@ pub enum Token {
     Comma,
     #[token(".")]
     Dot,
+    // `start..end` in a for loop
+    #[token("..")]
+    DotDot,
+    // `[` and `]` for arrays and indexing; nothing parses them yet. `#[`
+    // opens an attribute and is one token, so `#[inline]` never lexes an
+    // LBracket.
+    #[token("[")]
+    LBracket,
+    #[token("#[")]
+    HashBracket,
+    #[token("]")]
+    RBracket,
+    #[token("->")]
+    Arrow,
+    // Between a match pattern and its arm
+    #[token("=>")]
+    FatArrow,
     
     // Identifiers (must come after keywords to avoid conflicts)
-    #[regex("[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice().to_string())]
-    Ident(String),
+    #[regex("[a-zA-Z_][a-zA-Z0-9_]*", |lex| {
+        let name = lex.slice();
+        lex.extras.intern(name)
+    })]
+    Ident(Rc<str>),
     
-    // Skip whitespace
-    #[regex(r"[ \t\n\f]+", logos::skip)]
+    // Skip whitespace, including the \r of CRLF line endings, line
+    // comments up to (not including) the end of the line, and block
+    // comments, which don't nest. Lexer::keep_trivia gets them back.
+    #[regex(r"[ \t\r\n\f]+", logos::skip)]
+    #[regex(r"//[^\n]*", logos::skip)]
+    #[token("/*", block_comment)]
+    // A string or char literal that isn't closed on its line, or a char
+    // literal with more than one character in it. Lexing carries on right
+    // after it, so everything on the following lines still gets lexed.
+    #[regex(r#""[^"\n]*"#, |_| false)]
+    #[regex(r"'([^'\\\n]|\\.)*'?", |_| false, priority = 1)]
     Error,
 }
 
+// Skips the rest of a block comment after its `/*`. One that's never
+// closed runs to the end of the input and is an error.
+fn block_comment(lex: &mut logos::Lexer<Token>) -> FilterResult<(), ()> {
+    match lex.remainder().find("*/") {
+        Some(end) => {
+            lex.bump(end + 2);
+            FilterResult::Skip
+        }
+        None => {
+            lex.bump(lex.remainder().len());
+            FilterResult::Error(())
+        }
+    }
+}
+
+fn decimal<T: FromStr>(slice: &str, suffix: &str) -> Option<T> {
+    slice.trim_end_matches(suffix).replace('_', "").parse().ok()
+}
+
+fn hex(slice: &str, suffix: &str) -> Option<u64> {
+    let digits = slice.trim_start_matches("0x").trim_end_matches(suffix).replace('_', "");
+    u64::from_str_radix(&digits, 16).ok()
+}
+
+fn char_literal(slice: &str) -> Option<u8> {
+    let byte = match &slice[1..slice.len() - 1] {
+        "\\n" => b'\n',
+        "\\t" => b'\t',
+        "\\r" => b'\r',
+        "\\0" => 0,
+        "\\\\" => b'\\',
+        "\\'" => b'\'',
+        "\\\"" => b'"',
+        c if c.len() == 1 => c.as_bytes()[0],
+        _ => return None,
+    };
+    Some(byte)
+}
+
 pub fn tokenizeFile(input: &str) -> Vec<Token> {
-    Token::lexer(input)
-        .filter_map(|tok| tok.ok()) // only keep valid tokens
+    lex(input, KeywordCase::default())
+        .filter_map(|lexed| lexed.ok().map(|(tok, _)| tok)) // only keep valid tokens
         .collect()
 }
+
+// Input that isn't any token: a stray character, or a literal that matched
+// but didn't convert (an out-of-range number, an unknown escape)
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub slice: String,
+    pub span: Span,
+}
+
+impl LexError {
+    pub fn message(&self) -> String {
+        if self.slice.starts_with("/*") {
+            "unterminated block comment".to_string()
+        } else if self.slice.starts_with('"') {
+            "unterminated string literal".to_string()
+        } else if self.slice.starts_with('\'') && (self.slice.len() == 1 || !self.slice.ends_with('\'')) {
+            "unterminated character literal".to_string()
+        } else if self.slice.starts_with('\'') {
+            format!("invalid character literal {}", self.slice)
+        } else if self.slice.starts_with(|c: char| c.is_ascii_digit()) {
+            // The pattern matched, so the digits are fine and it's the value
+            format!("number literal {} is out of range for its type", self.slice)
+        } else if self.slice.chars().count() == 1 {
+            format!("unexpected character '{}'", self.slice)
+        } else {
+            format!("invalid token '{}'", self.slice)
+        }
+    }
+}
+
+// How keywords may be capitalized. Identifiers are case-sensitive either
+// way: `Count` and `count` are different variables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeywordCase {
+    // Exactly as documented: `fn`, `return`, `i32`, `true`. `Fn` is an
+    // identifier.
+    #[default]
+    Lowercase,
+    // Any capitalization of a keyword, a built-in type name, `true`,
+    // `false` or `null`: `Fn`, `RETURN` and `If` all work
+    Insensitive,
+}
+
+impl KeywordCase {
+    pub fn from_name(name: &str) -> Option<KeywordCase> {
+        match name {
+            "lowercase" => Some(KeywordCase::Lowercase),
+            "insensitive" => Some(KeywordCase::Insensitive),
+            _ => None,
+        }
+    }
+}
+
+// What the lexer skips between tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    // `// ...`, without the newline that ends it
+    LineComment,
+    // `/* ... */`
+    BlockComment,
+}
+
+// A run of whitespace or one comment; the text is the input at `span`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
+// Lexes on demand: each call to next() reads just far enough into the input
+// for one more token, so nothing is buffered and a caller can stop early
+pub struct Lexer<'s> {
+    tokens: SpannedIter<'s, Token>,
+    input: &'s str,
+    case: KeywordCase,
+    keep_trivia: bool,
+    // The literal an error was recovered as, to be returned after it
+    recovered: Option<(Token, Span)>,
+    // Where the last token (or error) ended, and the trivia since then
+    end: usize,
+    trivia: Vec<Trivia>,
+}
+
+impl Lexer<'_> {
+    // Also collect the whitespace and comments in front of each token, for
+    // tools that reproduce the source text, like a formatter. Compiling
+    // doesn't need them, so by default they're skipped without a trace.
+    pub fn keep_trivia(mut self, keep: bool) -> Self {
+        self.keep_trivia = keep;
+        self
+    }
+
+    // The trivia in front of the token next() last returned; once it has
+    // returned None, whatever follows the last token. Always empty without
+    // keep_trivia.
+    pub fn trivia(&self) -> &[Trivia] {
+        &self.trivia
+    }
+
+    fn collect_trivia(&mut self, until: usize) {
+        self.trivia.clear();
+        let mut start = self.end;
+        while start < until {
+            let rest = &self.input[start..until];
+            let (kind, len) = if rest.starts_with("//") {
+                (TriviaKind::LineComment, rest.find('\n').unwrap_or(rest.len()))
+            } else if rest.starts_with("/*") {
+                (TriviaKind::BlockComment, rest.find("*/").map_or(rest.len(), |end| end + 2))
+            } else {
+                (TriviaKind::Whitespace, rest.find('/').unwrap_or(rest.len()))
+            };
+            self.trivia.push(Trivia {
+                kind,
+                span: Span { start, end: start + len },
+            });
+            start += len;
+        }
+        self.end = until;
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<(Token, Span), LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(recovered) = self.recovered.take() {
+            self.trivia.clear();
+            return Some(Ok(recovered));
+        }
+        let Some((tok, range)) = self.tokens.next() else {
+            if self.keep_trivia {
+                self.collect_trivia(self.input.len());
+            }
+            return None;
+        };
+        let span = Span { start: range.start, end: range.end };
+        if self.keep_trivia {
+            self.collect_trivia(span.start);
+            self.end = span.end;
+        }
+        Some(match tok {
+            Ok(Token::Ident(name)) if self.case == KeywordCase::Insensitive => {
+                Ok((fold_keyword(&name).unwrap_or(Token::Ident(name)), span))
+            }
+            Ok(tok) => Ok((tok, span)),
+            Err(()) => {
+                let slice = &self.input[range];
+                self.recovered = recover(slice).map(|tok| (tok, span));
+                Err(LexError {
+                    slice: slice.to_string(),
+                    span,
+                })
+            }
+        })
+    }
+}
+
+// The literal a malformed string, char or number literal was most likely
+// meant to be; for a number out of range, any number will do. The lexer
+// returns it right after the error, so the parser sees a value where one
+// was written and doesn't pile on errors of its own.
+fn recover(slice: &str) -> Option<Token> {
+    match slice.as_bytes().first()? {
+        b'"' => Some(Token::String(slice[1..].to_string())),
+        b'\'' => Some(Token::Char(slice.as_bytes().get(1).copied().filter(|&c| c != b'\'').unwrap_or(0))),
+        b'0'..=b'9' => Some(Token::Integer(0)),
+        _ => None,
+    }
+}
+
+// Every token with the byte range it came from, in order, with whatever
+// couldn't be lexed in between, as they're asked for
+pub fn lex(input: &str, case: KeywordCase) -> Lexer<'_> {
+    Lexer {
+        tokens: Token::lexer(input).spanned(),
+        input,
+        case,
+        keep_trivia: false,
+        recovered: None,
+        end: 0,
+        trivia: Vec::new(),
+    }
+}
+
+// Same as lex, all at once
+pub fn tokenize(input: &str, case: KeywordCase) -> Vec<Result<(Token, Span), LexError>> {
+    lex(input, case).collect()
+}
+
+// The keyword an identifier spells in some other case, e.g. Return for
+// `RETURN`. The lexer only knows the lowercase spellings, so this is
+// whatever the lowercased name lexes to, unless that's still an identifier.
+fn fold_keyword(name: &str) -> Option<Token> {
+    let lower = name.to_lowercase();
+    if lower == name {
+        return None;
+    }
+    let mut lexer = Token::lexer(&lower);
+    match (lexer.next(), lexer.next()) {
+        (Some(Ok(token)), None) if !matches!(token, Token::Ident(_)) => Some(token),
+        _ => None,
+    }
+}
+
+// Same as tokenizeFile but keeps the byte range each token came from, for
+// tools that need to map an offset back to a token
+pub fn tokenize_with_spans(input: &str, case: KeywordCase) -> Vec<(Token, Span)> {
+    lex(input, case).filter_map(Result::ok).collect()
+}
+
+// The parser's cursor over a file: the tokens in order, the span each came
+// from, and how far it has got. Looking or reading past the end gives None
+// rather than panicking, so running out of input is just another token the
+// parser didn't expect.
+pub struct TokenStream<'a> {
+    tokens: &'a [Token],
+    spans: &'a [Span],
+    position: usize,
+    // Whether anything has looked for a token past the last one
+    ran_out: Cell<bool>,
+}
+
+impl<'a> TokenStream<'a> {
+    // `spans[i]` is where `tokens[i]` came from
+    pub fn new(tokens: &'a [Token], spans: &'a [Span]) -> Self {
+        debug_assert_eq!(tokens.len(), spans.len());
+        TokenStream { tokens, spans, position: 0, ran_out: Cell::new(false) }
+    }
+
+    // Whether the parser has asked for a token past the last one, so what
+    // it made of these tokens might change if there were more of them
+    pub fn ran_out(&self) -> bool {
+        self.ran_out.get()
+    }
+
+    // The next token, without consuming it
+    pub fn peek(&self) -> Option<&'a Token> {
+        self.peek_nth(0)
+    }
+
+    // The token `n` places after the next one; peek_nth(0) is peek()
+    pub fn peek_nth(&self, n: usize) -> Option<&'a Token> {
+        let token = self.tokens.get(self.position + n);
+        if token.is_none() {
+            self.ran_out.set(true);
+        }
+        token
+    }
+
+    pub fn advance(&mut self, n: usize) {
+        self.position = (self.position + n).min(self.tokens.len());
+    }
+
+    // Consumes the next token if it is `token`
+    pub fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    // Consumes `token` or fails with "Expected '<token>' <context>", e.g.
+    // expect(Token::LBrace, "after if condition")
+    pub fn expect(&mut self, token: Token, context: &str) -> Result<(), String> {
+        if self.eat(&token) {
+            Ok(())
+        } else {
+            Err(format!("Expected {} {}", spelling(&token), context))
+        }
+    }
+
+    // Where the cursor is now, to come back to with rewind or to measure a
+    // span from
+    pub fn checkpoint(&self) -> usize {
+        self.position
+    }
+
+    pub fn rewind(&mut self, checkpoint: usize) {
+        self.position = checkpoint;
+    }
+
+    // From the token at `checkpoint` to the last one consumed since, or just
+    // that token if nothing has been
+    pub fn span_from(&self, checkpoint: usize) -> Span {
+        let last = self.position.max(checkpoint + 1).min(self.spans.len()) - 1;
+        Span {
+            start: self.spans[checkpoint].start,
+            end: self.spans[last].end,
+        }
+    }
+}
+
+impl<'a> Iterator for TokenStream<'a> {
+    type Item = &'a Token;
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek()?;
+        self.position += 1;
+        Some(token)
+    }
+}
+
+// How a token is written, quoted, for error messages. Tokens that carry a
+// value come out in their debug form.
+pub fn spelling(token: &Token) -> String {
+    let text = match token {
+        Token::LParen => "(",
+        Token::RParen => ")",
+        Token::LBrace => "{",
+        Token::RBrace => "}",
+        Token::Semicolon => ";",
+        Token::Colon => ":",
+        Token::Eq => "=",
+        Token::Comma => ",",
+        Token::Dot => ".",
+        Token::DotDot => "..",
+        Token::LBracket => "[",
+        Token::HashBracket => "#[",
+        Token::RBracket => "]",
+        Token::Arrow => "->",
+        Token::FatArrow => "=>",
+        Token::While => "while",
+        Token::For => "for",
+        Token::In => "in",
+        Token::Loop => "loop",
+        Token::Break => "break",
+        Token::Continue => "continue",
+        Token::Struct => "struct",
+        Token::Enum => "enum",
+        Token::Match => "match",
+        other => return format!("{:?}", other),
+    };
+    format!("'{}'", text)
+}
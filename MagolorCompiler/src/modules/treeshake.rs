@@ -0,0 +1,108 @@
+use crate::modules::diagnostics::Diagnostics;
+use crate::modules::parser::{AST, ASTValue};
+use crate::modules::visitor::{AstVisitor, walk_ast, walk_value};
+use std::collections::{HashMap, HashSet};
+
+// Drops every function that can't be reached from one of `roots`, so unused
+// code never makes it into the module. Without any root present (e.g. a file
+// of helpers with no main and nothing `pub`) there is nothing to measure
+// against and the tree is returned untouched.
+//
+// `written` is what was reachable in the program as written, before
+// constant folding replaced calls with their results: a #[const] function
+// whose calls were all folded is dropped, but it was used.
+pub fn shake(
+    ast: Vec<AST>,
+    roots: &[&str],
+    written: Option<&HashSet<String>>,
+    diagnostics: &mut Diagnostics,
+) -> Vec<AST> {
+    let Some(reachable) = reachable(&ast, roots) else {
+        return ast;
+    };
+
+    ast.into_iter()
+        .filter(|node| match node {
+            AST::FuncDef { name, .. } if !reachable.contains(name) => {
+                if !written.is_some_and(|written| written.contains(name)) {
+                    diagnostics.warning(format!("function '{}' is never used", name), None);
+                }
+                false
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+// Every function defined in `ast` that one of `roots` can end up calling,
+// the roots included. None if no root is defined.
+pub fn reachable(ast: &[AST], roots: &[&str]) -> Option<HashSet<String>> {
+    // Direct callees of every function
+    let mut calls: HashMap<&str, HashSet<String>> = HashMap::new();
+    for node in ast {
+        if let AST::FuncDef { name, body, .. } = node {
+            calls.insert(name, callees(body));
+        }
+    }
+
+    let mut reachable: HashSet<&str> = HashSet::new();
+    let mut worklist: Vec<&str> = roots
+        .iter()
+        .copied()
+        .filter(|root| calls.contains_key(root))
+        .collect();
+    if worklist.is_empty() {
+        return None;
+    }
+
+    while let Some(func) = worklist.pop() {
+        if !reachable.insert(func) {
+            continue;
+        }
+        if let Some(callees) = calls.get(func) {
+            for callee in callees {
+                if let Some((name, _)) = calls.get_key_value(callee.as_str()) {
+                    worklist.push(name);
+                } else if let Some((_, method)) = callee.split_once('.') {
+                    // `value.method(...)`, whose type isn't known here; keep
+                    // the method of every type that has one by that name
+                    worklist.extend(calls.keys().filter(|name| {
+                        name.split_once('.').is_some_and(|(_, defined)| defined == method)
+                    }));
+                }
+            }
+        }
+    }
+
+    Some(reachable.into_iter().map(String::from).collect())
+}
+
+// Names of the functions a body calls directly, as written: a method call
+// is `value.method`
+pub fn callees(body: &[AST]) -> HashSet<String> {
+    let mut collector = CallCollector {
+        callees: HashSet::new(),
+    };
+    collector.visit_program(body);
+    collector.callees
+}
+
+struct CallCollector {
+    callees: HashSet<String>,
+}
+
+impl AstVisitor for CallCollector {
+    fn visit_ast(&mut self, node: &AST) {
+        if let AST::Call { object, method, .. } = node {
+            self.callees.insert(format!("{}.{}", object, method));
+        }
+        walk_ast(self, node);
+    }
+
+    fn visit_value(&mut self, value: &ASTValue) {
+        if let ASTValue::FuncCall { name, .. } = value {
+            self.callees.insert(name.clone());
+        }
+        walk_value(self, value);
+    }
+}
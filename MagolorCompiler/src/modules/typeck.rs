@@ -0,0 +1,845 @@
+use crate::modules::diagnostics::{Diagnostics, Span};
+use crate::modules::interface::{INIT_FN, ModuleInterface, is_exported};
+use crate::modules::parser::{AST, ASTValue, Attribute, Mutability, token_to_type_string};
+use crate::modules::prelude;
+use crate::modules::tokenizer::Token;
+use crate::modules::types::Type;
+use crate::modules::visitor::{AstVisitor, walk_value};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionSig {
+    pub params: Vec<(String, Type)>,
+    pub ret: Type,
+}
+
+// Everything the checker learned, kept around so tools can query it later
+// without re-running the analysis
+#[derive(Debug, Clone, Default)]
+pub struct TypeInfo {
+    pub functions: HashMap<String, FunctionSig>,
+    // function name -> variable name -> type
+    pub locals: HashMap<String, HashMap<String, Type>>,
+    // Functions that came from another module's interface -> that module
+    pub imported: HashMap<String, String>,
+    // Functions this module declares `pub`, which make up its interface
+    pub public: HashSet<String>,
+}
+
+impl TypeInfo {
+    pub fn local_type(&self, function: &str, var: &str) -> Option<Type> {
+        self.locals.get(function)?.get(var).copied()
+    }
+
+    pub fn return_type(&self, function: &str) -> Option<Type> {
+        self.functions.get(function).map(|sig| sig.ret)
+    }
+}
+
+// `builtins` are the built-in modules in scope: the whole prelude, or with
+// --no-prelude only the ones the program `use`s. A sandboxed program never
+// has the ones in prelude::SANDBOXED. `tokens` are the program's, spanned,
+// for pointing at the literals that don't fit.
+pub fn check(
+    ast: &[AST],
+    imports: &[ModuleInterface],
+    builtins: &[&str],
+    sandbox: bool,
+    tokens: &[(Token, Span)],
+    diagnostics: &mut Diagnostics,
+) -> TypeInfo {
+    let mut info = TypeInfo::default();
+
+    // Prelude functions count as imported from their module, so they stay
+    // out of the program's interface
+    for builtin in prelude::BUILTINS {
+        if !builtins.contains(&builtin.module()) {
+            continue;
+        }
+        if let Some(sig) = builtin.sig() {
+            info.functions.insert(builtin.name.to_string(), sig);
+            info.imported.insert(builtin.name.to_string(), builtin.module().to_string());
+        }
+    }
+
+    for interface in imports {
+        for (name, sig) in &interface.functions {
+            if let Some(other) = info.imported.get(name) {
+                diagnostics.error(
+                    format!("'{}' is imported from both '{}' and '{}'", name, other, interface.module),
+                    None,
+                );
+                continue;
+            }
+            info.functions.insert(name.clone(), sig.clone());
+            info.imported.insert(name.clone(), interface.module.clone());
+        }
+    }
+
+    // First pass: collect every signature so calls can appear before definitions
+    for node in ast {
+        if let AST::FuncDef {
+            name,
+            params,
+            return_type,
+            attributes,
+            public,
+            ..
+        } = node
+        {
+            check_attributes(name, is_exported(name, *public), attributes, diagnostics);
+            if *public {
+                info.public.insert(name.clone());
+            }
+
+            let mut param_types = Vec::new();
+            for (param_name, param_type) in params {
+                match Type::from_name(param_type) {
+                    Some(ty) => param_types.push((param_name.clone(), ty)),
+                    None => diagnostics.error(
+                        format!("unknown type '{}' for parameter '{}' of '{}'", param_type, param_name, name),
+                        None,
+                    ),
+                }
+            }
+
+            let ret = match return_type.as_deref() {
+                None => Type::Void,
+                Some(rt) => Type::from_name(rt).unwrap_or_else(|| {
+                    diagnostics.error(format!("unknown return type '{}' for '{}'", rt, name), None);
+                    Type::Void
+                }),
+            };
+
+            if name == INIT_FN && (!params.is_empty() || ret != Type::Void) {
+                diagnostics.error(
+                    format!("'{}' runs before main and must be `void fn {}()`", name, name),
+                    None,
+                );
+            }
+
+            if let Some(module) = info.imported.remove(name) {
+                diagnostics.error(
+                    format!("function '{}' conflicts with the one imported from '{}'", name, module),
+                    None,
+                );
+            } else if info.functions.contains_key(name) {
+                diagnostics.error(format!("function '{}' is defined more than once", name), None);
+            }
+            info.functions.insert(
+                name.clone(),
+                FunctionSig {
+                    params: param_types,
+                    ret,
+                },
+            );
+        }
+    }
+
+    // Second pass: check bodies
+    let mut literals = literal_spans(tokens);
+    for node in ast {
+        if let AST::FuncDef { name, body, .. } = node {
+            let sig = info.functions[name].clone();
+            let mut checker = FunctionChecker {
+                name,
+                sig: &sig,
+                functions: &info.functions,
+                builtins,
+                sandbox,
+                literals: literals.remove(name.as_str()).unwrap_or_default(),
+                passed_literals: 0,
+                scope: sig.params.iter().cloned().collect(),
+                declared: sig.params.iter().cloned().collect(),
+                consts: HashSet::new(),
+                diagnostics,
+            };
+            checker.check_body(body);
+            if sig.ret != Type::Void && !always_returns(body) {
+                let message = format!("missing return: the function returns {}, but its end is reachable", sig.ret);
+                checker.error(message);
+            }
+            info.locals.insert(name.clone(), checker.declared);
+        }
+    }
+
+    info
+}
+
+struct FunctionChecker<'a> {
+    name: &'a str,
+    sig: &'a FunctionSig,
+    functions: &'a HashMap<String, FunctionSig>,
+    builtins: &'a [&'a str],
+    sandbox: bool,
+    // The function's integer literals, in source order, with where they are,
+    // and how many of them checking has got past; see literal_span
+    literals: Vec<(i64, Span)>,
+    passed_literals: usize,
+    // What's visible at the current statement
+    scope: HashMap<String, Type>,
+    // Every local the function declares, in any block, for hover
+    declared: HashMap<String, Type>,
+    // Names in scope that are bound by `const`
+    consts: HashSet<String>,
+    diagnostics: &'a mut Diagnostics,
+}
+
+impl FunctionChecker<'_> {
+    fn error(&mut self, message: String) {
+        self.diagnostics.error(format!("{} (in '{}')", message, self.name), None);
+    }
+
+    fn check_body(&mut self, body: &[AST]) {
+        for stmt in body {
+            self.check_statement(stmt);
+        }
+    }
+
+    // Locals declared inside a branch go out of scope when it ends
+    fn check_block(&mut self, body: &[AST]) {
+        let outer = (self.scope.clone(), self.consts.clone());
+        self.check_body(body);
+        (self.scope, self.consts) = outer;
+    }
+
+    // An integer literal takes the type it's used as, but only if its value
+    // fits: 3_000_000_000 can be an i64 and never an i32, and -1 is no
+    // unsigned type
+    fn check_range(&mut self, expected: Type, value: &ASTValue) {
+        let n = match value {
+            ASTValue::Int(n) => *n as i64,
+            ASTValue::Int64(n) => *n,
+            _ => return,
+        };
+        let fits = match expected {
+            Type::I32 => i32::try_from(n).is_ok(),
+            Type::U8 => u8::try_from(n).is_ok(),
+            Type::U32 => u32::try_from(n).is_ok(),
+            Type::U64 => n >= 0,
+            _ => true,
+        };
+        if fits {
+            return;
+        }
+        let message = if expected == Type::I32 {
+            format!("literal out of range for i32: {} only fits in an i64", n)
+        } else {
+            format!("literal out of range for {}: {}", expected, n)
+        };
+        let span = self.literal_span(n);
+        self.diagnostics.error(format!("{} (in '{}')", message, self.name), span);
+    }
+
+    // Checking goes through a function in source order, so the literal
+    // `type_of` just reached is the next one with its value
+    fn pass_literal(&mut self, n: i64) {
+        let rest = &self.literals[self.passed_literals..];
+        if let Some(at) = rest.iter().position(|(value, _)| *value == n) {
+            self.passed_literals += at + 1;
+        }
+    }
+
+    // Where the literal `n` being range checked is written: the last one
+    // with its value that checking got past
+    fn literal_span(&self, n: i64) -> Option<Span> {
+        self.literals[..self.passed_literals]
+            .iter()
+            .rev()
+            .find(|(value, _)| *value == n)
+            .map(|(_, span)| *span)
+    }
+
+    // `value.method(args)` as the call to the method it resolves to:
+    // `type.method(value, args)`, where `value` is a variable and `type` has
+    // an impl with that method. None for any other call.
+    fn method_call(&self, name: &str, args: &[ASTValue]) -> Option<ASTValue> {
+        let (receiver, method) = name.split_once('.')?;
+        let ty = self.scope.get(receiver)?;
+        let qualified = format!("{}.{}", ty, method);
+        if !self.functions.contains_key(&qualified) {
+            return None;
+        }
+        let mut with_receiver = vec![ASTValue::VarRef(receiver.to_string())];
+        with_receiver.extend(args.iter().cloned());
+        Some(ASTValue::FuncCall {
+            name: qualified,
+            args: with_receiver,
+        })
+    }
+
+    fn check_statement(&mut self, stmt: &AST) {
+        match stmt {
+            AST::VarDecl(ty, name, value, mutability) => {
+                let value_type = self.type_of(value);
+                if *mutability == Mutability::Const {
+                    let mut check = ConstantCheck {
+                        consts: &self.consts,
+                        constant: true,
+                    };
+                    check.visit_value(value);
+                    if !check.constant {
+                        self.error(format!(
+                            "const '{}' must be initialized with literals and other consts",
+                            name
+                        ));
+                    }
+                    self.consts.insert(name.clone());
+                } else {
+                    // A shadowing `let` hides the const
+                    self.consts.remove(name);
+                }
+                let var_type = if ty == "auto" {
+                    value_type
+                } else {
+                    match Type::from_name(ty) {
+                        Some(declared) => {
+                            if let Some(found) = value_type {
+                                if !assignable(declared, found, value) {
+                                    self.error(format!(
+                                        "mismatched types for '{}': expected {}, found {}",
+                                        name, declared, found
+                                    ));
+                                }
+                                self.check_range(declared, value);
+                            }
+                            Some(declared)
+                        }
+                        None => {
+                            self.error(format!("unknown type '{}' for variable '{}'", ty, name));
+                            None
+                        }
+                    }
+                };
+                if let Some(var_type) = var_type {
+                    self.scope.insert(name.clone(), var_type);
+                    self.declared.insert(name.clone(), var_type);
+                }
+            }
+            AST::Return(value) => {
+                if let Some(found) = self.type_of(value) {
+                    let expected = self.sig.ret;
+                    if expected != Type::Void && !assignable(expected, found, value) {
+                        self.error(format!(
+                            "mismatched return type: expected {}, found {}",
+                            expected, found
+                        ));
+                    }
+                    self.check_range(expected, value);
+                }
+            }
+            AST::Call {
+                object,
+                method,
+                args,
+            } => {
+                let name = format!("{}.{}", object, method);
+                if prelude::lookup(&name).is_some()
+                    || self.functions.contains_key(&name)
+                    || self.scope.contains_key(object)
+                {
+                    self.type_of(&ASTValue::FuncCall { name, args: args.clone() });
+                } else {
+                    self.error(format!("unknown method '{}'", name));
+                    for arg in args {
+                        self.type_of(arg);
+                    }
+                }
+            }
+            AST::If {
+                condition,
+                then_body,
+                elif_branches,
+                else_body,
+            } => {
+                self.check_condition(condition);
+                self.check_block(then_body);
+                for (elif_cond, elif_body) in elif_branches {
+                    self.check_condition(elif_cond);
+                    self.check_block(elif_body);
+                }
+                if let Some(else_body) = else_body {
+                    self.check_block(else_body);
+                }
+            }
+            AST::While { condition, body } => {
+                self.check_condition(condition);
+                self.check_block(body);
+            }
+            AST::For {
+                variable,
+                start,
+                end,
+                body,
+            } => {
+                let var_type = self.range_type(start, end);
+                let outer = (self.scope.clone(), self.consts.clone());
+                self.consts.remove(variable);
+                if let Some(var_type) = var_type {
+                    self.scope.insert(variable.clone(), var_type);
+                    self.declared.insert(variable.clone(), var_type);
+                }
+                self.check_body(body);
+                (self.scope, self.consts) = outer;
+            }
+            AST::VarRef(_) | AST::Literal(_) => {
+                self.diagnostics.warning(
+                    format!("statement has no effect: {:?} (in '{}')", stmt, self.name),
+                    None,
+                );
+            }
+            AST::FuncDef { .. } | AST::Import(_) => {}
+        }
+    }
+
+    fn check_condition(&mut self, cond: &ASTValue) {
+        if let Some(found) = self.type_of(cond) {
+            if found != Type::Bool {
+                self.error(format!("condition must be bool, found {}", found));
+            }
+        }
+    }
+
+    // The type of the variable counting through `start..end`: both bounds
+    // are integers of one type, except that a literal bound takes the type
+    // of the other, as in arithmetic
+    fn range_type(&mut self, start: &ASTValue, end: &ASTValue) -> Option<Type> {
+        let start_type = self.type_of(start);
+        let end_type = self.type_of(end);
+        let (s, e) = (start_type?, end_type?);
+        if !s.is_integer() || !e.is_integer() {
+            self.error(format!("range bounds must be integers, found {} and {}", s, e));
+            return None;
+        }
+        if s == e || (is_number_literal(end) && assignable(s, e, end)) {
+            self.check_range(s, end);
+            Some(s)
+        } else if is_number_literal(start) && assignable(e, s, start) {
+            self.check_range(e, start);
+            Some(e)
+        } else {
+            self.error(format!("mismatched types for '..': {} and {}", s, e));
+            None
+        }
+    }
+
+    // A call to a Params::Numeric builtin: the arguments must be numbers of
+    // one type, which the call returns. That is the type of the first
+    // argument that isn't a literal, so `math.max(x, 0)` works for any x.
+    fn numeric_call(
+        &mut self,
+        name: &str,
+        params: &[&str],
+        args: &[ASTValue],
+        arg_types: &[Option<Type>],
+    ) -> Option<Type> {
+        if params.len() != args.len() {
+            self.error(format!(
+                "'{}' takes {} argument(s) but {} were supplied",
+                name,
+                params.len(),
+                args.len()
+            ));
+            return None;
+        }
+        let types: Vec<Type> = arg_types.iter().copied().collect::<Option<_>>()?;
+        if let Some(found) = types.iter().find(|ty| !ty.is_numeric()) {
+            self.error(format!("'{}' takes numbers, found {}", name, found));
+            return None;
+        }
+
+        let ty = args.iter().position(|arg| !is_number_literal(arg)).map_or(types[0], |i| types[i]);
+        for ((param, arg), found) in params.iter().zip(args).zip(types) {
+            if found != ty && !(is_number_literal(arg) && assignable(ty, found, arg)) {
+                self.error(format!(
+                    "mismatched types for argument '{}' of '{}': expected {}, found {}",
+                    param, name, ty, found
+                ));
+            }
+            self.check_range(ty, arg);
+        }
+        Some(ty)
+    }
+
+    // Returns None when the type can't be determined; an error has already
+    // been reported in that case so callers don't pile on more
+    fn type_of(&mut self, value: &ASTValue) -> Option<Type> {
+        match value {
+            ASTValue::Str(_) => Some(Type::Str),
+            ASTValue::Int(n) => {
+                self.pass_literal(*n as i64);
+                Some(Type::I32)
+            }
+            ASTValue::Int64(n) => {
+                self.pass_literal(*n);
+                Some(Type::I64)
+            }
+            ASTValue::UInt8(_) => Some(Type::U8),
+            ASTValue::UInt32(_) => Some(Type::U32),
+            ASTValue::UInt64(_) => Some(Type::U64),
+            ASTValue::Float32(_) => Some(Type::F32),
+            ASTValue::Float64(_) => Some(Type::F64),
+            ASTValue::Bool(_) => Some(Type::Bool),
+            ASTValue::Char(_) => Some(Type::Char),
+            ASTValue::Null => {
+                self.error("'null' can't be used yet: there are no optional types for it to belong to".to_string());
+                None
+            }
+            ASTValue::VarRef(name) => match self.scope.get(name) {
+                Some(ty) => Some(*ty),
+                None => {
+                    self.error(format!("unknown variable '{}'", name));
+                    None
+                }
+            },
+            ASTValue::FuncCall { name, args } => {
+                if let Some(call) = self.method_call(name, args) {
+                    return self.type_of(&call);
+                }
+                let arg_types: Vec<Option<Type>> = args.iter().map(|a| self.type_of(a)).collect();
+                if let Some(builtin) = prelude::lookup(name) {
+                    if self.sandbox && prelude::SANDBOXED.contains(&builtin.module()) {
+                        self.error(format!("'{}' is not available in the sandbox", name));
+                        return None;
+                    }
+                    if !self.builtins.contains(&builtin.module()) {
+                        self.error(not_imported(builtin.module()));
+                        return None;
+                    }
+                    match builtin.params {
+                        // Takes anything, so there's no signature to check against
+                        prelude::Params::Variadic => return Some(builtin.ret),
+                        prelude::Params::Numeric(params) => {
+                            return self.numeric_call(name, params, args, &arg_types);
+                        }
+                        prelude::Params::Fixed(_) => {}
+                    }
+                }
+                let Some(sig) = self.functions.get(name) else {
+                    let method = name
+                        .split_once('.')
+                        .and_then(|(receiver, method)| Some((self.scope.get(receiver)?, method)));
+                    match method {
+                        Some((ty, method)) => self.error(format!("{} has no method '{}'", ty, method)),
+                        None => self.error(format!("unknown function '{}'", name)),
+                    }
+                    return None;
+                };
+
+                if sig.params.len() != args.len() {
+                    self.error(format!(
+                        "'{}' takes {} argument(s) but {} were supplied",
+                        name,
+                        sig.params.len(),
+                        args.len()
+                    ));
+                } else {
+                    for (((param_name, expected), found), arg) in
+                        sig.params.iter().zip(arg_types).zip(args)
+                    {
+                        if let Some(found) = found {
+                            if !assignable(*expected, found, arg) {
+                                self.error(format!(
+                                    "mismatched types for argument '{}' of '{}': expected {}, found {}",
+                                    param_name, name, expected, found
+                                ));
+                            }
+                            self.check_range(*expected, arg);
+                        }
+                    }
+                }
+                Some(sig.ret)
+            }
+            ASTValue::LessThan(lhs, rhs)
+            | ASTValue::GreaterThan(lhs, rhs)
+            | ASTValue::Equal(lhs, rhs)
+            | ASTValue::EqualEqual(lhs, rhs)
+            | ASTValue::NotEqual(lhs, rhs)
+            | ASTValue::LessEqual(lhs, rhs)
+            | ASTValue::GreaterEqual(lhs, rhs) => {
+                let lhs_type = self.type_of(lhs);
+                let rhs_type = self.type_of(rhs);
+                if let (Some(l), Some(r)) = (lhs_type, rhs_type) {
+                    // Codegen compares everything as integers, so only
+                    // numbers, bools and chars can be compared for now. A
+                    // bool isn't a number, so it's only compared with bools.
+                    let comparable = |t: Type| t.is_numeric() || matches!(t, Type::Bool | Type::Char);
+                    if !comparable(l) || !comparable(r) || (l == Type::Bool) != (r == Type::Bool) {
+                        self.error(format!("cannot compare {} with {}", l, r));
+                    } else if l.is_integer() && r.is_integer() && l.is_unsigned() != r.is_unsigned() {
+                        // Signed and unsigned integers order differently, so
+                        // they only mix when the signed side is a literal,
+                        // which takes the other side's type
+                        if is_number_literal(rhs) {
+                            self.check_range(l, rhs);
+                        } else if is_number_literal(lhs) {
+                            self.check_range(r, lhs);
+                        } else {
+                            self.error(format!("cannot compare {} with {}: one is signed and the other unsigned", l, r));
+                        }
+                    }
+                }
+                Some(Type::Bool)
+            }
+            ASTValue::Add(lhs, rhs)
+            | ASTValue::Sub(lhs, rhs)
+            | ASTValue::Mul(lhs, rhs)
+            | ASTValue::Div(lhs, rhs)
+            | ASTValue::Rem(lhs, rhs) => {
+                let lhs_type = self.type_of(lhs);
+                let rhs_type = self.type_of(rhs);
+                let (l, r) = (lhs_type?, rhs_type?);
+                let op = operator(value);
+                if !l.is_numeric() || !r.is_numeric() {
+                    self.error(format!("cannot apply '{}' to {} and {}", op, l, r));
+                    return None;
+                }
+                // A literal takes the type of the other side, so `x + 1`
+                // works whatever integer type x is. Otherwise an integer
+                // with a float is promoted to the float's type: `3 / 2`
+                // divides integers and is 1, `3 / 2.0` is 1.5
+                if l == r || (is_number_literal(rhs) && assignable(l, r, rhs)) {
+                    self.check_range(l, rhs);
+                    Some(l)
+                } else if is_number_literal(lhs) && assignable(r, l, lhs) {
+                    self.check_range(r, lhs);
+                    Some(r)
+                } else if l.is_float() && r.is_integer() {
+                    Some(l)
+                } else if l.is_integer() && r.is_float() {
+                    Some(r)
+                } else {
+                    self.error(format!("mismatched types for '{}': {} and {}", op, l, r));
+                    None
+                }
+            }
+            ASTValue::And(lhs, rhs) | ASTValue::Or(lhs, rhs) => {
+                let types = self.type_of(lhs).zip(self.type_of(rhs));
+                if let Some((l, r)) = types.filter(|&(l, r)| l != Type::Bool || r != Type::Bool) {
+                    self.error(format!("cannot apply '{}' to {} and {}", operator(value), l, r));
+                }
+                Some(Type::Bool)
+            }
+            ASTValue::Not(operand) => {
+                if let Some(found) = self.type_of(operand).filter(|ty| *ty != Type::Bool) {
+                    self.error(format!("cannot apply '!' to {}", found));
+                }
+                Some(Type::Bool)
+            }
+            ASTValue::Neg(operand) => {
+                let ty = self.type_of(operand)?;
+                if !ty.is_numeric() || ty.is_unsigned() {
+                    self.error(format!("cannot apply '-' to {}", ty));
+                    return None;
+                }
+                Some(ty)
+            }
+        }
+    }
+}
+
+// Clears `constant` on reaching anything that's only known at run time:
+// calls and variables that aren't consts
+struct ConstantCheck<'a> {
+    consts: &'a HashSet<String>,
+    constant: bool,
+}
+
+impl AstVisitor for ConstantCheck<'_> {
+    fn visit_value(&mut self, value: &ASTValue) {
+        match value {
+            ASTValue::VarRef(name) if self.consts.contains(name) => {}
+            ASTValue::VarRef(_) | ASTValue::FuncCall { .. } => self.constant = false,
+            _ => walk_value(self, value),
+        }
+    }
+}
+
+pub fn is_number_literal(value: &ASTValue) -> bool {
+    matches!(
+        value,
+        ASTValue::Int(_) | ASTValue::Int64(_) | ASTValue::Float32(_) | ASTValue::Float64(_)
+    )
+}
+
+// The operator a binary value was written with, for messages
+fn operator(value: &ASTValue) -> &'static str {
+    match value {
+        ASTValue::Add(..) => "+",
+        ASTValue::Sub(..) => "-",
+        ASTValue::Mul(..) => "*",
+        ASTValue::Div(..) => "/",
+        ASTValue::Rem(..) => "%",
+        ASTValue::And(..) => "&&",
+        ASTValue::Or(..) => "||",
+        _ => unreachable!("not a binary operator: {:?}", value),
+    }
+}
+
+// For a prelude function used with --no-prelude and no `use`
+// Every function's integer literals, keyed by its name as checked (methods
+// as `type.method`). A literal right after `-` counts as negative, with the
+// sign in its span, since that's how the parser folds it; for a subtraction
+// that leaves an extra entry nothing will ask for.
+fn literal_spans(tokens: &[(Token, Span)]) -> HashMap<String, Vec<(i64, Span)>> {
+    let mut literals: HashMap<String, Vec<(i64, Span)>> = HashMap::new();
+    let mut current: Option<String> = None;
+    let mut owner: Option<String> = None;
+    let mut depth = 0;
+    // Depth of the braces around a function body: 1 inside an impl
+    let mut outer = 0;
+    for (i, (tok, span)) in tokens.iter().enumerate() {
+        match tok {
+            Token::Impl if depth == 0 => {
+                owner = tokens.get(i + 1).and_then(|(ty, _)| token_to_type_string(ty));
+                outer = 1;
+            }
+            Token::Func if depth == outer => {
+                if let Some((Token::Ident(name), _)) = tokens.get(i + 1) {
+                    current = Some(match &owner {
+                        Some(ty) => format!("{}.{}", ty, name),
+                        None => name.to_string(),
+                    });
+                }
+            }
+            Token::LBrace => depth += 1,
+            Token::RBrace => {
+                depth -= 1;
+                if depth == outer {
+                    current = None;
+                }
+                if depth == 0 {
+                    owner = None;
+                    outer = 0;
+                }
+            }
+            Token::Integer(n) | Token::Integer64(n) => {
+                let Some(function) = &current else {
+                    continue;
+                };
+                let entry = literals.entry(function.clone()).or_default();
+                if let Some((Token::Minus, minus)) = i.checked_sub(1).map(|prev| &tokens[prev]) {
+                    entry.push((n.wrapping_neg(), Span { start: minus.start, end: span.end }));
+                }
+                entry.push((*n, *span));
+            }
+            _ => {}
+        }
+    }
+    literals
+}
+
+fn not_imported(module: &str) -> String {
+    format!("'{}' is not imported (add `use {};` or drop --no-prelude)", module, module)
+}
+
+fn check_attributes(function: &str, exported: bool, attributes: &[Attribute], diagnostics: &mut Diagnostics) {
+    let mut inline_seen = false;
+    let mut visibility_seen = false;
+
+    for attr in attributes {
+        match attr.name.as_str() {
+            "inline" => {
+                let args: Vec<&str> = attr.args.iter().map(String::as_str).collect();
+                if !matches!(args.as_slice(), [] | ["never"]) {
+                    diagnostics.error(
+                        format!("malformed attribute on '{}': expected #[inline] or #[inline(never)]", function),
+                        None,
+                    );
+                }
+                if inline_seen {
+                    diagnostics.error(format!("conflicting inline attributes on '{}'", function), None);
+                }
+                inline_seen = true;
+            }
+            "const" => {
+                if !attr.args.is_empty() {
+                    diagnostics.error(
+                        format!("malformed attribute on '{}': #[const] takes no arguments", function),
+                        None,
+                    );
+                }
+            }
+            "weak" => {
+                if !attr.args.is_empty() {
+                    diagnostics.error(
+                        format!("malformed attribute on '{}': #[weak] takes no arguments", function),
+                        None,
+                    );
+                }
+            }
+            "visibility" => {
+                let args: Vec<&str> = attr.args.iter().map(String::as_str).collect();
+                if !matches!(args.as_slice(), ["default" | "hidden" | "protected"]) {
+                    diagnostics.error(
+                        format!(
+                            "malformed attribute on '{}': expected #[visibility(\"default\")], \"hidden\" or \"protected\"",
+                            function
+                        ),
+                        None,
+                    );
+                }
+                if visibility_seen {
+                    diagnostics.error(format!("conflicting visibility attributes on '{}'", function), None);
+                }
+                visibility_seen = true;
+            }
+            other => diagnostics.warning(
+                format!("unknown attribute '#[{}]' on '{}' is ignored", other, function),
+                None,
+            ),
+        }
+
+        // Both describe the symbol other objects link against, which a
+        // function only has if it's exported
+        if matches!(attr.name.as_str(), "weak" | "visibility") && !exported {
+            diagnostics.error(
+                format!(
+                    "#[{}] has no effect on '{}', which isn't exported (only main and `pub` functions are)",
+                    attr.name, function
+                ),
+                None,
+            );
+        }
+    }
+}
+
+// Whether running `body` can't fall off its end: some statement returns,
+// exits, loops forever, or is an if/else whose every branch does
+fn always_returns(body: &[AST]) -> bool {
+    body.iter().any(|stmt| match stmt {
+        AST::Return(_) => true,
+        AST::Call { object, method, .. } => {
+            prelude::lookup(&format!("{}.{}", object, method)).is_some_and(|builtin| builtin.noreturn)
+        }
+        AST::While {
+            condition: ASTValue::Bool(true),
+            ..
+        } => true,
+        AST::If {
+            then_body,
+            elif_branches,
+            else_body: Some(else_body),
+            ..
+        } => {
+            always_returns(then_body)
+                && elif_branches.iter().all(|(_, body)| always_returns(body))
+                && always_returns(else_body)
+        }
+        _ => false,
+    })
+}
+
+// Integer literals fit any integer type and float literals any float type;
+// everything else has to match exactly
+fn assignable(expected: Type, found: Type, value: &ASTValue) -> bool {
+    if expected == found {
+        return true;
+    }
+    match value {
+        ASTValue::Int(_) | ASTValue::Int64(_) => expected.is_integer(),
+        ASTValue::Float32(_) | ASTValue::Float64(_) => expected.is_float(),
+        _ => false,
+    }
+}
@@ -0,0 +1,95 @@
+use crate::modules::parser::{AST, ASTValue};
+use crate::modules::visitor::{AstVisitor, walk_ast};
+
+// Debug-only invariant checker run between parsing and codegen.
+// Anything reported here is a front-end bug, not a user error, so we panic
+// with every violation found instead of trying to recover.
+pub fn validate_ast(ast: &[AST]) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    let mut validator = Validator {
+        current_function: None,
+        errors: Vec::new(),
+    };
+    validator.visit_program(ast);
+
+    if !validator.errors.is_empty() {
+        panic!("AST validation failed:\n  {}", validator.errors.join("\n  "));
+    }
+}
+
+struct Validator {
+    current_function: Option<String>,
+    errors: Vec<String>,
+}
+
+impl Validator {
+    fn location(&self) -> &str {
+        self.current_function.as_deref().unwrap_or("<top level>")
+    }
+
+    // The condition must be one of the shapes compile_condition knows how to
+    // lower to an i1. Type checking has already made sure a variable or call
+    // used as one is a bool
+    fn check_condition(&mut self, cond: &ASTValue) {
+        let is_bool = matches!(
+            cond,
+            ASTValue::Bool(_)
+                | ASTValue::LessThan(..)
+                | ASTValue::GreaterThan(..)
+                | ASTValue::Equal(..)
+                | ASTValue::EqualEqual(..)
+                | ASTValue::NotEqual(..)
+                | ASTValue::LessEqual(..)
+                | ASTValue::GreaterEqual(..)
+                | ASTValue::And(..)
+                | ASTValue::Or(..)
+                | ASTValue::Not(..)
+                | ASTValue::VarRef(_)
+                | ASTValue::FuncCall { .. }
+        );
+        if !is_bool {
+            let msg = format!("non-boolean condition {:?} in '{}'", cond, self.location());
+            self.errors.push(msg);
+        }
+    }
+}
+
+impl AstVisitor for Validator {
+    fn visit_ast(&mut self, node: &AST) {
+        match node {
+            AST::FuncDef { name, .. } => {
+                if self.current_function.is_some() {
+                    let msg = format!("nested function '{}' found in '{}'", name, self.location());
+                    self.errors.push(msg);
+                }
+
+                let outer = self.current_function.replace(name.clone());
+                walk_ast(self, node);
+                self.current_function = outer;
+            }
+            AST::Import(pkg) if self.current_function.is_some() => {
+                let msg = format!("import of '{}' found inside '{}'", pkg, self.location());
+                self.errors.push(msg);
+            }
+            AST::If {
+                condition,
+                elif_branches,
+                ..
+            } => {
+                self.check_condition(condition);
+                for (elif_cond, _) in elif_branches {
+                    self.check_condition(elif_cond);
+                }
+                walk_ast(self, node);
+            }
+            AST::While { condition, .. } => {
+                self.check_condition(condition);
+                walk_ast(self, node);
+            }
+            _ => walk_ast(self, node),
+        }
+    }
+}
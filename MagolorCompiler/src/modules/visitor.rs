@@ -0,0 +1,225 @@
+use crate::modules::parser::{AST, ASTValue};
+
+// Read-only traversal over the AST. Override the hooks you care about and
+// call the matching walk_* function to keep descending into children.
+pub trait AstVisitor {
+    fn visit_program(&mut self, ast: &[AST]) {
+        walk_body(self, ast);
+    }
+
+    fn visit_ast(&mut self, node: &AST) {
+        walk_ast(self, node);
+    }
+
+    fn visit_value(&mut self, value: &ASTValue) {
+        walk_value(self, value);
+    }
+}
+
+pub fn walk_body<V: AstVisitor + ?Sized>(visitor: &mut V, body: &[AST]) {
+    for node in body {
+        visitor.visit_ast(node);
+    }
+}
+
+pub fn walk_ast<V: AstVisitor + ?Sized>(visitor: &mut V, node: &AST) {
+    match node {
+        AST::Import(_) | AST::VarRef(_) => {}
+        AST::VarDecl(_, _, value, _) | AST::Literal(value) | AST::Return(value) => {
+            visitor.visit_value(value);
+        }
+        AST::Call { args, .. } => {
+            for arg in args {
+                visitor.visit_value(arg);
+            }
+        }
+        AST::While { condition, body } => {
+            visitor.visit_value(condition);
+            walk_body(visitor, body);
+        }
+        AST::For { start, end, body, .. } => {
+            visitor.visit_value(start);
+            visitor.visit_value(end);
+            walk_body(visitor, body);
+        }
+        AST::FuncDef { body, .. } => walk_body(visitor, body),
+        AST::If {
+            condition,
+            then_body,
+            elif_branches,
+            else_body,
+        } => {
+            visitor.visit_value(condition);
+            walk_body(visitor, then_body);
+            for (elif_cond, elif_body) in elif_branches {
+                visitor.visit_value(elif_cond);
+                walk_body(visitor, elif_body);
+            }
+            if let Some(else_body) = else_body {
+                walk_body(visitor, else_body);
+            }
+        }
+    }
+}
+
+pub fn walk_value<V: AstVisitor + ?Sized>(visitor: &mut V, value: &ASTValue) {
+    match value {
+        ASTValue::Str(_)
+        | ASTValue::Int(_)
+        | ASTValue::Int64(_)
+        | ASTValue::UInt8(_)
+        | ASTValue::UInt32(_)
+        | ASTValue::UInt64(_)
+        | ASTValue::Float32(_)
+        | ASTValue::Float64(_)
+        | ASTValue::Bool(_)
+        | ASTValue::Char(_)
+        | ASTValue::Null
+        | ASTValue::VarRef(_) => {}
+        ASTValue::FuncCall { args, .. } => {
+            for arg in args {
+                visitor.visit_value(arg);
+            }
+        }
+        ASTValue::LessThan(lhs, rhs)
+        | ASTValue::GreaterThan(lhs, rhs)
+        | ASTValue::Equal(lhs, rhs)
+        | ASTValue::EqualEqual(lhs, rhs)
+        | ASTValue::NotEqual(lhs, rhs)
+        | ASTValue::LessEqual(lhs, rhs)
+        | ASTValue::GreaterEqual(lhs, rhs)
+        | ASTValue::Add(lhs, rhs)
+        | ASTValue::Sub(lhs, rhs)
+        | ASTValue::Mul(lhs, rhs)
+        | ASTValue::Div(lhs, rhs)
+        | ASTValue::Rem(lhs, rhs)
+        | ASTValue::And(lhs, rhs)
+        | ASTValue::Or(lhs, rhs) => {
+            visitor.visit_value(lhs);
+            visitor.visit_value(rhs);
+        }
+        ASTValue::Not(operand) | ASTValue::Neg(operand) => visitor.visit_value(operand),
+    }
+}
+
+// Owning rewrite over the AST. Every hook returns the (possibly replaced)
+// node; the default implementations rebuild the tree unchanged.
+pub trait AstFolder {
+    fn fold_program(&mut self, ast: Vec<AST>) -> Vec<AST> {
+        fold_body(self, ast)
+    }
+
+    fn fold_ast(&mut self, node: AST) -> AST {
+        fold_ast_children(self, node)
+    }
+
+    fn fold_value(&mut self, value: ASTValue) -> ASTValue {
+        fold_value_children(self, value)
+    }
+}
+
+pub fn fold_body<F: AstFolder + ?Sized>(folder: &mut F, body: Vec<AST>) -> Vec<AST> {
+    body.into_iter().map(|node| folder.fold_ast(node)).collect()
+}
+
+pub fn fold_ast_children<F: AstFolder + ?Sized>(folder: &mut F, node: AST) -> AST {
+    match node {
+        AST::Import(_) | AST::VarRef(_) => node,
+        AST::VarDecl(ty, name, value, mutability) => {
+            AST::VarDecl(ty, name, folder.fold_value(value), mutability)
+        }
+        AST::Literal(value) => AST::Literal(folder.fold_value(value)),
+        AST::Return(value) => AST::Return(folder.fold_value(value)),
+        AST::Call {
+            object,
+            method,
+            args,
+        } => AST::Call {
+            object,
+            method,
+            args: args.into_iter().map(|arg| folder.fold_value(arg)).collect(),
+        },
+        AST::FuncDef {
+            name,
+            params,
+            return_type,
+            body,
+            attributes,
+            public,
+        } => AST::FuncDef {
+            name,
+            params,
+            return_type,
+            body: fold_body(folder, body),
+            attributes,
+            public,
+        },
+        AST::If {
+            condition,
+            then_body,
+            elif_branches,
+            else_body,
+        } => AST::If {
+            condition: folder.fold_value(condition),
+            then_body: fold_body(folder, then_body),
+            elif_branches: elif_branches
+                .into_iter()
+                .map(|(cond, body)| (folder.fold_value(cond), fold_body(folder, body)))
+                .collect(),
+            else_body: else_body.map(|body| fold_body(folder, body)),
+        },
+        AST::While { condition, body } => AST::While {
+            condition: folder.fold_value(condition),
+            body: fold_body(folder, body),
+        },
+        AST::For {
+            variable,
+            start,
+            end,
+            body,
+        } => AST::For {
+            variable,
+            start: folder.fold_value(start),
+            end: folder.fold_value(end),
+            body: fold_body(folder, body),
+        },
+    }
+}
+
+pub fn fold_value_children<F: AstFolder + ?Sized>(folder: &mut F, value: ASTValue) -> ASTValue {
+    match value {
+        ASTValue::FuncCall { name, args } => ASTValue::FuncCall {
+            name,
+            args: args.into_iter().map(|arg| folder.fold_value(arg)).collect(),
+        },
+        ASTValue::LessThan(l, r) => ASTValue::LessThan(fold_boxed(folder, l), fold_boxed(folder, r)),
+        ASTValue::GreaterThan(l, r) => {
+            ASTValue::GreaterThan(fold_boxed(folder, l), fold_boxed(folder, r))
+        }
+        ASTValue::Equal(l, r) => ASTValue::Equal(fold_boxed(folder, l), fold_boxed(folder, r)),
+        ASTValue::EqualEqual(l, r) => {
+            ASTValue::EqualEqual(fold_boxed(folder, l), fold_boxed(folder, r))
+        }
+        ASTValue::NotEqual(l, r) => ASTValue::NotEqual(fold_boxed(folder, l), fold_boxed(folder, r)),
+        ASTValue::LessEqual(l, r) => {
+            ASTValue::LessEqual(fold_boxed(folder, l), fold_boxed(folder, r))
+        }
+        ASTValue::GreaterEqual(l, r) => {
+            ASTValue::GreaterEqual(fold_boxed(folder, l), fold_boxed(folder, r))
+        }
+        ASTValue::Add(l, r) => ASTValue::Add(fold_boxed(folder, l), fold_boxed(folder, r)),
+        ASTValue::Sub(l, r) => ASTValue::Sub(fold_boxed(folder, l), fold_boxed(folder, r)),
+        ASTValue::Mul(l, r) => ASTValue::Mul(fold_boxed(folder, l), fold_boxed(folder, r)),
+        ASTValue::Div(l, r) => ASTValue::Div(fold_boxed(folder, l), fold_boxed(folder, r)),
+        ASTValue::Rem(l, r) => ASTValue::Rem(fold_boxed(folder, l), fold_boxed(folder, r)),
+        ASTValue::And(l, r) => ASTValue::And(fold_boxed(folder, l), fold_boxed(folder, r)),
+        ASTValue::Or(l, r) => ASTValue::Or(fold_boxed(folder, l), fold_boxed(folder, r)),
+        ASTValue::Not(v) => ASTValue::Not(fold_boxed(folder, v)),
+        ASTValue::Neg(v) => ASTValue::Neg(fold_boxed(folder, v)),
+        leaf => leaf,
+    }
+}
+
+fn fold_boxed<F: AstFolder + ?Sized>(folder: &mut F, value: Box<ASTValue>) -> Box<ASTValue> {
+    Box::new(folder.fold_value(*value))
+}
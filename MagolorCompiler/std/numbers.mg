@@ -0,0 +1,54 @@
+// Integer helpers. `use numbers;` to call them.
+
+// The greatest common divisor, never negative; gcd(0, 0) is 0
+pub i32 fn gcd(i32: a, i32: b) {
+    if (b == 0) {
+        if (a < 0) {
+            return 0 - a;
+        }
+        return a;
+    }
+    return gcd(b, a % b);
+}
+
+// The least common multiple, never negative; 0 if either is 0
+pub i32 fn lcm(i32: a, i32: b) {
+    if (a == 0) {
+        return 0;
+    }
+    let i32 multiple = a / gcd(a, b) * b;
+    if (multiple < 0) {
+        return 0 - multiple;
+    }
+    return multiple;
+}
+
+// -1, 0 or 1
+pub i32 fn sign(i32: x) {
+    if (x < 0) {
+        return 0 - 1;
+    }
+    if (x > 0) {
+        return 1;
+    }
+    return 0;
+}
+
+pub bool fn is_even(i32: x) {
+    if (x % 2 == 0) {
+        return true;
+    }
+    return false;
+}
+
+// `base` to the power `exp`, by squaring. A negative `exp` gives 1.
+pub i64 fn power(i64: base, i32: exp) {
+    if (exp < 1) {
+        return 1i64;
+    }
+    let i64 half = power(base, exp / 2);
+    if (exp % 2 == 0) {
+        return half * half;
+    }
+    return half * half * base;
+}
@@ -0,0 +1,117 @@
+// Grammar and type checker conformance tests, in the style of rustc's UI
+// tests. Every `.mg` under tests/corpus/pass must compile and every one
+// under tests/corpus/fail must not. The diagnostics each program produces,
+// rendered the way the command line prints them, must match the `.stderr`
+// file next to it; a missing `.stderr` means no diagnostics at all.
+// Codegen verifies every module it builds, so a pass program that comes out
+// as invalid LLVM IR fails here as a compiler panic. A `// compile-flags:`
+// line compiles the program as those command line flags would; only
+// --sandbox is supported so far.
+//
+// After an intended change in output, rerun with MAGOLOR_BLESS=1 to rewrite
+// the expectations, and review the diff.
+
+use MagolorCompiler::Compiler;
+use std::env;
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+const CORPUS: &str = "tests/corpus";
+
+#[test]
+fn corpus() {
+    let bless = env::var_os("MAGOLOR_BLESS").is_some();
+    let mut failures = Vec::new();
+    let mut count = 0;
+
+    for (dir, should_compile) in [("pass", true), ("fail", false)] {
+        for program in programs(&Path::new(CORPUS).join(dir)) {
+            count += 1;
+            if let Err(e) = check(&program, should_compile, bless) {
+                failures.push(format!("{}: {}", program.display(), e));
+            }
+        }
+    }
+
+    assert!(count > 0, "no programs found under {}", CORPUS);
+    assert!(
+        failures.is_empty(),
+        "{} of {} corpus programs failed:\n\n{}",
+        failures.len(),
+        count,
+        failures.join("\n\n")
+    );
+}
+
+// Sorted, so failures come out in a stable order
+fn programs(dir: &Path) -> Vec<PathBuf> {
+    let mut programs: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("cannot read {}: {}", dir.display(), e))
+        .map(|entry| entry.expect("cannot read directory entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "mg"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+fn check(program: &Path, should_compile: bool, bless: bool) -> Result<(), String> {
+    let source = fs::read_to_string(program).map_err(|e| format!("cannot read: {}", e))?;
+    // Forward slashes, so the expectations are the same on every platform
+    let file = program.to_string_lossy().replace('\\', "/");
+    let flags = compile_flags(&source);
+    if let Some(flag) = flags.iter().find(|flag| **flag != "--sandbox") {
+        return Err(format!("unsupported compile flag {}", flag));
+    }
+    let sandbox = flags.contains(&"--sandbox");
+
+    // A panic is a compiler bug whatever the program; report it and keep
+    // going with the rest of the corpus
+    let (ir, actual) = panic::catch_unwind(|| {
+        let mut compiler = Compiler::new();
+        compiler.set_sandbox(sandbox);
+        let (ir, diagnostics) = compiler.compile(&file, &source);
+        let mut rendered = String::new();
+        for diagnostic in diagnostics.sorted() {
+            rendered.push_str(&compiler.source_map().render(diagnostic));
+            rendered.push('\n');
+        }
+        (ir, rendered)
+    })
+    .map_err(|_| "the compiler panicked".to_string())?;
+
+    match (should_compile, ir.is_some()) {
+        (true, false) => return Err(format!("failed to compile:\n{}", actual)),
+        (false, true) => return Err(format!("compiled, but should have failed:\n{}", actual)),
+        _ => {}
+    }
+
+    let expected_path = program.with_extension("stderr");
+    if bless {
+        if actual.is_empty() {
+            let _ = fs::remove_file(&expected_path);
+        } else {
+            fs::write(&expected_path, &actual).map_err(|e| format!("cannot bless: {}", e))?;
+        }
+        return Ok(());
+    }
+
+    let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+    if actual != expected {
+        return Err(format!(
+            "diagnostics differ from {}\n--- expected\n{}--- actual\n{}",
+            expected_path.display(),
+            expected,
+            actual
+        ));
+    }
+    Ok(())
+}
+
+fn compile_flags(source: &str) -> Vec<&str> {
+    source
+        .lines()
+        .filter_map(|line| line.strip_prefix("// compile-flags:"))
+        .flat_map(str::split_whitespace)
+        .collect()
+}
@@ -0,0 +1,7 @@
+i32 fn add(i32: a, i32: b) {
+    return a;
+}
+
+void fn main() {
+    let i32 x = add(1);
+}
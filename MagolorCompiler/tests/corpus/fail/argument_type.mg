@@ -0,0 +1,7 @@
+i32 fn twice(i32: a) {
+    return a;
+}
+
+void fn main() {
+    let i32 x = twice("two");
+}
@@ -0,0 +1,10 @@
+void fn main() {
+    let i32 a = 1;
+    let i64 b = 2i64;
+    let f32 c = 1.5;
+    let i32 mixed = a + b;
+    let f64 d = 2.5f64;
+    let f32 float = c * d;
+    let f32 half = a / 2;
+    let i32 text = "one" + 1;
+}
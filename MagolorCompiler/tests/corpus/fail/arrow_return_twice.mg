@@ -0,0 +1,3 @@
+i32 fn add(i32: a, i32: b) -> i32 {
+    return a + b;
+}
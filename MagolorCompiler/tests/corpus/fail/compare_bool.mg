@@ -0,0 +1,10 @@
+// A bool isn't a number: true isn't 1, and no number is between them
+void fn main() {
+    let bool ready = true;
+    if (ready == 1) {
+        console.print("one");
+    }
+    if (ready < 1.5) {
+        console.print("half");
+    }
+}
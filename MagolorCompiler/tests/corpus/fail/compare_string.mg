@@ -0,0 +1,6 @@
+void fn main() {
+    let string s = "a";
+    if (s == 1) {
+        console.print("x");
+    }
+}
@@ -0,0 +1,6 @@
+void fn main() {
+    let i32 x = 1;
+    if (x) {
+        console.print("x");
+    }
+}
@@ -0,0 +1,8 @@
+#[const]
+i32 fn ratio(i32: n) {
+    return 10 / n;
+}
+
+void fn main() {
+    let i32 r = ratio(0);
+}
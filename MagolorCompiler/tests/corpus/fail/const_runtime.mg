@@ -0,0 +1,15 @@
+i32 fn seed() {
+    return 7;
+}
+
+fn main() {
+    let start = 1;
+    const i32 FROM_CALL = seed();
+    const i32 FROM_LET = start + 1;
+    const i32 BASE = 2;
+    if BASE > 1 {
+        let BASE = seed();
+        const i32 SHADOWED = BASE;
+    }
+    const i32 OUTER = BASE * 2;
+}
@@ -0,0 +1,9 @@
+#[const]
+i32 fn noisy() {
+    console.print("side effect");
+    return 1;
+}
+
+void fn main() {
+    let i32 x = noisy();
+}
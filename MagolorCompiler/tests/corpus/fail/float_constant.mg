@@ -0,0 +1,3 @@
+void fn main() {
+    let f64 small = f64.epsilon;
+}
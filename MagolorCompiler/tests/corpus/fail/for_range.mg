@@ -0,0 +1,10 @@
+void fn main() {
+    let i64 wide = 10i64;
+    let i32 narrow = 2;
+    for i in narrow..wide {
+        console.print("mixed");
+    }
+    for x in 0..2.5 {
+        console.print("float");
+    }
+}
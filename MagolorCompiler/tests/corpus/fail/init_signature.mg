@@ -0,0 +1,7 @@
+i32 fn init(i32: a) {
+    return a;
+}
+
+void fn main() {
+    console.print("x");
+}
@@ -0,0 +1,16 @@
+i32 fn narrow(i32: x) {
+    return x;
+}
+
+i64 fn wide(i64: x) {
+    return x;
+}
+
+void fn main() {
+    let i64 big = 5000000000;
+    let i32 small = 7;
+    let i32 shrunk = big;
+    let i64 grown = small;
+    let i32 passed_big = narrow(big);
+    let i64 passed_small = wide(small);
+}
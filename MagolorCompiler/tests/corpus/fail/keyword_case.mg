@@ -0,0 +1,5 @@
+// Keywords are lowercase unless compiled with --keyword-case insensitive
+void fn main() {
+    let String name = "magolor";
+    let Bool ready = true;
+}
@@ -0,0 +1,5 @@
+void fn main() {
+    let i32 a = 1 @ 2;
+    let i32 big = 99999999999;
+    console.print("after");
+}
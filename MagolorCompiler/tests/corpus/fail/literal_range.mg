@@ -0,0 +1,14 @@
+// An unsuffixed integer too big for an i32 is an i64, and only fits where
+// an i64 does
+i32 fn small() {
+    return 3_000_000_000;
+}
+
+void fn main() {
+    let i64 fine = 99999999999;
+    let inferred = 4_000_000_000;
+    let i32 count = 99999999999;
+    let i32 sum = count + 5_000_000_000;
+    let i32 low = math.min(count, 2147483648);
+    let i32 lowest = -2147483648;
+}
@@ -0,0 +1,6 @@
+// Literals too big for any type of their kind are caught by the lexer
+void fn main() {
+    let i64 huge = 99999999999999999999;
+    let u8 byte = 300u8;
+    let i32 after = 1;
+}
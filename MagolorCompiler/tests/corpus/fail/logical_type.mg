@@ -0,0 +1,10 @@
+void fn main() {
+    let i32 a = 1;
+    let string name = "magolor";
+    if (a && a > 0) {
+        console.print("int");
+    }
+    if !name {
+        console.print("string");
+    }
+}
@@ -0,0 +1,5 @@
+void fn main() {
+    loop {
+        console.print("tick");
+    }
+}
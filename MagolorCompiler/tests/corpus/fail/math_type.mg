@@ -0,0 +1,7 @@
+void fn main() {
+    let i32 a = 1;
+    let i64 b = 2i64;
+    let i32 mixed = math.min(a, b);
+    let string text = math.abs("one");
+    let i32 few = math.clamp(a, 0);
+}
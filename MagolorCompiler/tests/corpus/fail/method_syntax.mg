@@ -0,0 +1,22 @@
+impl i32 {
+    fn doubled() -> i32 {
+        return 2;
+    }
+}
+
+impl i64 {
+    pub fn doubled(self) -> i64 {
+        return self * 2;
+    }
+}
+
+impl counter {
+    fn next(self) -> i32 {
+        return 1;
+    }
+}
+
+void fn main() {
+    impl i32 {
+    }
+}
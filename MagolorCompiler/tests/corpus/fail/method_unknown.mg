@@ -0,0 +1,13 @@
+impl i32 {
+    fn doubled(self) -> i32 {
+        return self * 2;
+    }
+}
+
+void fn main() {
+    let bool ready = true;
+    let i32 count = 3;
+    let i32 twice = ready.doubled();
+    let f64 wrong = count.doubled();
+    count.halved();
+}
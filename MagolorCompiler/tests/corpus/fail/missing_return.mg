@@ -0,0 +1,23 @@
+i32 fn nothing() {}
+
+i32 fn sign(i32: n) {
+    if (n < 0) {
+        return -1;
+    } elif (n > 0) {
+        return 1;
+    }
+}
+
+i32 fn covered(i32: n) {
+    if (n < 0) {
+        return -1;
+    } else {
+        process.exit(1);
+    }
+}
+
+void fn main() {
+    let i32 a = nothing();
+    let i32 b = sign(3);
+    let i32 c = covered(3);
+}
@@ -0,0 +1,4 @@
+void fn main() {
+    let bool done = false;
+    let bool flipped = -done;
+}
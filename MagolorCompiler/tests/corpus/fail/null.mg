@@ -0,0 +1,4 @@
+void fn main() {
+    let str name = null;
+    console.print(null);
+}
@@ -0,0 +1,5 @@
+pub let i32 limit = 10;
+
+void fn main() {
+    console.print("x");
+}
@@ -0,0 +1,7 @@
+i32 fn f() {
+    return "not a number";
+}
+
+void fn main() {
+    console.print("x");
+}
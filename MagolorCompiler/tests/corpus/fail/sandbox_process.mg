@@ -0,0 +1,6 @@
+// compile-flags: --sandbox
+// Untrusted code can't end the process that compiled it, or any other
+void fn main() {
+    console.print("leaving");
+    process.exit(1);
+}
@@ -0,0 +1,9 @@
+use numbers;
+
+i32 fn gcd(i32: a, i32: b) {
+    return a;
+}
+
+void fn main() {
+    let bool even = is_even(2.5f64);
+}
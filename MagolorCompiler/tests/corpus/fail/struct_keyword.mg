@@ -0,0 +1,8 @@
+struct Point {
+    i32: x,
+    i32: y,
+}
+
+void fn main() {
+    console.print("after");
+}
@@ -0,0 +1,9 @@
+void fn main() {
+    console.print("fine");
+}
+
+let i32 = ;
+
+void fn after() {
+    console.print("still parsed");
+}
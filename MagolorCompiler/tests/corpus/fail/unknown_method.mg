@@ -0,0 +1,3 @@
+void fn main() {
+    strings.upper("magolor");
+}
@@ -0,0 +1,3 @@
+void fn main() {
+    let u16 x = 1;
+}
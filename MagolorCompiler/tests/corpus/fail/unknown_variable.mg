@@ -0,0 +1,3 @@
+void fn main() {
+    let i32 x = y;
+}
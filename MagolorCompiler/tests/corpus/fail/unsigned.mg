@@ -0,0 +1,9 @@
+void fn main() {
+    let u8 byte = 256;
+    let u32 count = -1;
+    let u32 x = 5u32;
+    let i32 y = 5;
+    let bool mixed = x < y;
+    let u64 negated = -5u64;
+    let u32 narrow = 5u8;
+}
@@ -0,0 +1,6 @@
+void fn main() {
+    console.print("before");
+}
+/* never closed
+void fn later() {
+}
@@ -0,0 +1,9 @@
+// Each bad literal is reported once, and the lines after it still get
+// checked
+void fn main() {
+    let str greeting = "hello;
+    let char initial = 'h;
+    let char pair = 'hi';
+    console.print(greeting);
+    let i32 count 3;
+}
@@ -0,0 +1,15 @@
+#[weak]
+i32 fn fallback() {
+    return 0;
+}
+
+#[visibility("secret")]
+pub i32 fn limit() {
+    return fallback();
+}
+
+#[visibility("hidden")]
+#[visibility("default")]
+pub i32 fn twice() {
+    return 2;
+}
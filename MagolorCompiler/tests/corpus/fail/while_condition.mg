@@ -0,0 +1,6 @@
+void fn main() {
+    let i32 count = 3;
+    while (count) {
+        console.print("tick");
+    }
+}
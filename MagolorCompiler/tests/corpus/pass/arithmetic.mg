@@ -0,0 +1,23 @@
+// * / % bind tighter than + -, and parentheses group
+i64 fn area(i64: width, i64: height) {
+    return width * height;
+}
+
+#[const]
+i32 fn seconds(i32: minutes) {
+    return minutes * 60 + 1_000 % 7;
+}
+
+void fn main() {
+    let i32 a = 7;
+    let i32 b = 2;
+    let i32 sum = a + b * 3;
+    let i32 grouped = (a + b) * 3;
+    let i32 rest = a % b - a / b;
+    let i64 big = area(3i64, 4i64) + 1;
+    let f64 ratio = 1.5f64 * 2.0f64 - 0.25f64;
+    let total = sum + seconds(2);
+    if (a - 1 > b * 2) {
+        console.print("bigger");
+    }
+}
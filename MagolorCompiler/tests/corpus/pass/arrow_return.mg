@@ -0,0 +1,14 @@
+// Return types can come before `fn` or after the parameters
+fn add(i32: a, i32: b) -> i32 {
+    return a + b;
+}
+
+i64 fn widen(i32: n) {
+    return 2i64;
+}
+
+fn main() -> void {
+    let i32 sum = add(1, 2);
+    let i64 wide = widen(sum);
+    console.print(wide);
+}
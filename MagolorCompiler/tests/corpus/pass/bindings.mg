@@ -0,0 +1,13 @@
+use console
+
+fn main() {
+    const i32 LIMIT = 10;
+    const SCALE = LIMIT * 4 + 2;
+    let mut i32 total = SCALE - LIMIT;
+    let mut count = 3;
+    let label = "bindings";
+
+    console.print(label);
+    let sum = total + count;
+    console.print(sum);
+}
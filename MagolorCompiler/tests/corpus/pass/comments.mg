@@ -0,0 +1,7 @@
+// Line comments run to the end of the line
+/* Block comments can span lines
+   and don't nest */
+void fn main() {
+    let i32 /* inline */ count = 3; // trailing
+    /**/ console.print(count);
+}
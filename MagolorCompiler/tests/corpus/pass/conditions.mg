@@ -0,0 +1,12 @@
+void fn main() {
+    let i32 x = 3;
+    if (x > 5) {
+        console.print("big");
+    } elif (x == 3) {
+        console.print("three");
+    } elif (x != 0) {
+        console.print("small");
+    } else {
+        console.print("zero");
+    }
+}
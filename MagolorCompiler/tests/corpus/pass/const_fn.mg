@@ -0,0 +1,13 @@
+#[const]
+i32 fn limit(i32: size) {
+    if (size > 5) {
+        return 100;
+    }
+    return 10;
+}
+
+#[inline]
+void fn main() {
+    let i32 n = limit(8);
+    console.print(n);
+}
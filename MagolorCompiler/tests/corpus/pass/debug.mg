@@ -0,0 +1,7 @@
+void fn main() {
+    let i32 count = 42;
+    let f64 ratio = 0.25;
+    let bool ready = true;
+    console.debug(count, ratio, ready);
+    console.debug("done", 'x', 1.5e20);
+}
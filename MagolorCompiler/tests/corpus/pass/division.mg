@@ -0,0 +1,33 @@
+// Two integers divide as integers, truncating toward zero: 7 / 2 is 3 and
+// -7 / 2 is -3. An integer with a float is promoted to the float's type
+// first, so 7 / 2.0 is 3.5, and that goes for every arithmetic operator
+#[const]
+f64 fn average(i64: total, i32: count) {
+    return total / (count * 1.0f64);
+}
+
+f32 fn scale(f32: value, i32: factor) {
+    return value * factor;
+}
+
+void fn main() {
+    let i32 a = 7;
+    let i32 b = 2;
+    let i64 wide = 9i64;
+    let f32 ratio = 0.5;
+    let f64 precise = 0.25f64;
+    let i32 quotient = a / b;
+    let i32 negative = -a / b;
+    let i32 remainder = -a % b;
+    let i64 long_quotient = wide / 2;
+    let f32 promoted = a / 2.0;
+    let f32 float_left = 2.0 / b;
+    let f32 mixed = a * ratio + b;
+    let f64 wide_mixed = wide / precise - a;
+    let f32 truncated_first = a / b * 1.0;
+    let f64 mean = average(10i64, 4);
+    let f32 scaled = scale(1.5, a);
+    let bool same = a / 2.0 == 3.5;
+    console.debug(quotient, negative, remainder, long_quotient);
+    console.debug(promoted, float_left, mixed, wide_mixed, truncated_first, mean, scaled, same);
+}
@@ -0,0 +1,16 @@
+// A branch that ends in process.exit needs no return of its own
+i32 fn checked(i32: count) {
+    if (count < 0) {
+        console.print("negative count");
+        process.exit(2);
+    } else {
+        return count;
+    }
+}
+
+void fn main() {
+    let i32 count = checked(3);
+    console.print(count);
+    process.exit(0);
+    console.print("never printed");
+}
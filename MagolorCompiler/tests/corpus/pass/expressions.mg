@@ -0,0 +1,27 @@
+// Any expression works wherever a value does: initializers, arguments to
+// functions and methods, and return values
+impl i32 {
+    fn scaled(self, i32: factor) -> i32 {
+        return self * factor;
+    }
+}
+
+bool fn positive(i32: n) {
+    return n > 0;
+}
+
+i32 fn twice(i32: n) {
+    return n + n;
+}
+
+void fn main() {
+    let i32 x = 4;
+    let i32 y = twice(x * 2 + 1) - 3;
+    let bool above = positive(x - y) || x == 4;
+    let i32 z = x.scaled(y % 3 + 1);
+    console.print(x * 2);
+    console.print(x < y && above);
+    console.debug(twice(x) - z, -x);
+    assert.that(twice(x) == 8);
+    z.scaled(x - 1);
+}
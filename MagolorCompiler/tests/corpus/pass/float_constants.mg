@@ -0,0 +1,24 @@
+// f64.nan and f64.inf (and their f32 twins). Comparisons with NaN are
+// ordered, so only `!=` holds, and NaN isn't equal to itself
+#[const]
+bool fn is_nan(f64: x) {
+    return x != x;
+}
+
+bool fn finite(f64: x) {
+    return x == x && x < f64.inf && x > -f64.inf;
+}
+
+void fn main() {
+    let f64 missing = f64.nan;
+    let f64 huge = f64.inf;
+    let f32 tiny = -f32.inf;
+    let bool nan = is_nan(f64.nan);
+    let bool number = is_nan(1.5f64);
+    let bool same = missing == missing;
+    let bool ordered = missing < huge || missing >= huge;
+    let bool bounded = finite(huge);
+    console.debug(missing, huge, tiny, nan, number, same, ordered, bounded);
+    console.print(f64.nan);
+    console.print(-f64.inf);
+}
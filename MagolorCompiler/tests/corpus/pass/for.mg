@@ -0,0 +1,38 @@
+// `for i in start..end` counts from start up to, but not including, end. A
+// literal bound takes the other bound's type, so the counter can be an i64
+i32 fn first_multiple(i32: n, i32: limit) {
+    for i in 1..limit {
+        if (i % n == 0) {
+            return i;
+        }
+    }
+    return 0;
+}
+
+#[const]
+i64 fn first_square_over(i64: limit) {
+    for i in 0..limit {
+        if (i * i > limit) {
+            return i;
+        }
+    }
+    return limit;
+}
+
+void fn main() {
+    let i32 i = 100;
+    for i in 0..3 {
+        console.print(i);
+        for j in i..2 {
+            let i32 product = i * j;
+            console.debug(product);
+        }
+    }
+    for empty in 5..5 {
+        console.print("never printed");
+    }
+    console.debug(i);
+    let i32 multiple = first_multiple(7, 50);
+    let i64 root = first_square_over(1000i64);
+    console.debug(multiple, root);
+}
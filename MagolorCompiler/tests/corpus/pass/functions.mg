@@ -0,0 +1,17 @@
+// Parameters, return values and calls between functions
+i32 fn pick(i32: a, i32: b) {
+    if (a < b) {
+        return a;
+    }
+    return b;
+}
+
+i64 fn widen(i64: x) {
+    return x;
+}
+
+void fn main() {
+    let i32 smaller = pick(3, 4);
+    let i64 big = widen(5i64);
+    console.print(smaller);
+}
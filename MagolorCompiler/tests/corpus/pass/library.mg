@@ -0,0 +1,8 @@
+// No main: compiles to a module other files can `use`
+pub i32 fn answer() {
+    return 42;
+}
+
+void fn init() {
+    console.print("loaded");
+}
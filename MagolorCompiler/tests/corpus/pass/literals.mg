@@ -0,0 +1,15 @@
+void fn main() {
+    let i32 million = 1_000_000;
+    let i32 mask = 0xFF_FF;
+    let i64 wide = 0xFFFF_FFFF_FFi64;
+    let f32 ratio = 0.5;
+    let f64 precise = 2.25f64;
+    let f32 large = 1.5e10;
+    let f64 small = 2e-3f64;
+    let f32 scaled = 6.02E+23f32;
+    let bool done = true;
+    let char letter = 'a';
+    let char newline = '\n';
+    let string greeting = "hi"; // a trailing comment
+    console.print(greeting);
+}
@@ -0,0 +1,22 @@
+// ! binds tighter than &&, which binds tighter than ||
+bool fn ready(bool: flag) {
+    return flag;
+}
+
+void fn main() {
+    let i32 a = 1;
+    let i32 b = 2;
+    let i32 c = 3;
+    let bool done = false;
+    if (a < b && c != 0) {
+        console.print("both");
+    }
+    if a == 0 || b == 2 && !done {
+        console.print("either");
+    }
+    if !(a > b) && ready(true) {
+        console.print("negated");
+    } elif (a == 1 || done) && b > 0 {
+        console.print("grouped");
+    }
+}
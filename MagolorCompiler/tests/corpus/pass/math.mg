@@ -0,0 +1,15 @@
+// A literal argument takes the type of the others
+i64 fn distance(i64: a, i64: b) {
+    return math.abs(a - b);
+}
+
+void fn main() {
+    let i32 low = math.min(3, 7);
+    let i64 high = math.max(2i64, 9);
+    let f64 limited = math.clamp(1.5f64, 0.0, 1.0);
+    let f32 size = math.abs(0.5 - 3.0);
+    let i64 gap = distance(high, 4i64);
+    if (math.max(low, 0) > 1) {
+        console.print("positive");
+    }
+}
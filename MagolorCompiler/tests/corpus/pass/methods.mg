@@ -0,0 +1,35 @@
+// Methods on built-in types, called on variables of that type
+impl i32 {
+    i32 fn clamp(self, i32: low, i32: high) {
+        if (self < low) {
+            return low;
+        }
+        if (self > high) {
+            return high;
+        }
+        return self;
+    }
+
+    fn doubled(self) -> i32 {
+        return self * 2;
+    }
+
+    fn show(self) -> void {
+        console.print(self);
+    }
+}
+
+impl f64 {
+    fn doubled(self) -> f64 {
+        return self * 2.0f64;
+    }
+}
+
+void fn main() {
+    let i32 reading = 140;
+    let i32 level = reading.clamp(0, 100);
+    let i32 twice = level.doubled();
+    let ratio = 0.5f64;
+    let f64 whole = ratio.doubled();
+    twice.show();
+}
@@ -0,0 +1,25 @@
+// One expression grammar for arithmetic, comparisons and logic, with C's
+// precedence: unary operators, then * / %, + -, < > <= >=, == !=, && and ||
+bool fn inside(i32: value, i32: low, i32: high) {
+    return low <= value && value < high;
+}
+
+i64 fn offset(i64: base) {
+    return -base + 1;
+}
+
+void fn main() {
+    let i32 a = 7;
+    let i32 b = 2;
+    let i32 c = 3;
+    let i32 sum = a + b * c - -c;
+    let i32 negated = -(a - b) * c;
+    let f64 scaled = -1.5f64 * 2.0f64;
+    let i64 shifted = offset(-40i64);
+    let bool ordered = a - b > c == true;
+    let bool nested = (a < b) != (b < c) || !inside(a, 0, 10) && b == 2;
+    if a + b * c > 10 && -a < 0 {
+        console.print("precedence");
+    }
+    console.debug(sum, negated, scaled, shifted, ordered, nested);
+}
@@ -0,0 +1,8 @@
+// console, assert, strings and math are there without any `use`
+void fn main() {
+    let str name = "magolor";
+    let i32 size = strings.len(name);
+    let bool same = strings.eq(name, "magolor");
+    assert.that(true);
+    console.print(name);
+}
@@ -0,0 +1,38 @@
+// A returned value takes the function's return type: unsuffixed literals in
+// i64 and f64 functions, and calls to functions returning floats or strings
+i64 fn big() {
+    return 5;
+}
+
+f64 fn half() {
+    return 1.5;
+}
+
+i32 fn lowest() {
+    return -2147483648;
+}
+
+f64 fn twice_half() {
+    return half() * 2;
+}
+
+f64 fn same_half() {
+    return half();
+}
+
+string fn greeting() {
+    return "hello";
+}
+
+string fn same_greeting() {
+    return greeting();
+}
+
+void fn main() {
+    let i64 five = big();
+    let f64 one = twice_half();
+    let f64 other = same_half();
+    let i32 low = lowest();
+    let string text = same_greeting();
+    console.debug(five, one, other, low, text);
+}
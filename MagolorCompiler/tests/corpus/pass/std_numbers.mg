@@ -0,0 +1,10 @@
+// Standard library modules are written in Magolor and compiled into the
+// program that uses them
+use numbers;
+
+void fn main() {
+    let i32 divisor = gcd(12, 18);
+    let i32 multiple = lcm(4, 6);
+    let i64 big = power(2i64, 40);
+    console.print(divisor);
+}
@@ -0,0 +1,25 @@
+// Methods on strings, and string parameters alongside `self`
+impl string {
+    fn length(self) -> i32 {
+        return strings.len(self);
+    }
+
+    bool fn matches(self, string: other) {
+        return strings.eq(self, other);
+    }
+}
+
+string fn pick(bool: first, string: a, string: b) {
+    if (first) {
+        return a;
+    }
+    return b;
+}
+
+void fn main() {
+    let string name = "magolor";
+    let i32 size = name.length();
+    let bool same = name.matches("magolor");
+    let string chosen = pick(same, name, "other");
+    console.debug(size, same, chosen);
+}
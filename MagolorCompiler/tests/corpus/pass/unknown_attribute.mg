@@ -0,0 +1,4 @@
+#[fast]
+void fn main() {
+    console.print("x");
+}
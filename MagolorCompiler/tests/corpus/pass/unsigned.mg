@@ -0,0 +1,32 @@
+// u8, u32 and u64 divide, compare and convert as unsigned: 4_000_000_000u32
+// is bigger than 1, not negative, and 250u8 + 10 wraps around to 4. An
+// unsuffixed literal takes the unsigned type it's used with.
+#[const]
+u32 fn half(u32: n) {
+    return n / 2;
+}
+
+u64 fn widen(u64: n) {
+    return n * 2;
+}
+
+void fn main() {
+    let u32 big = 4_000_000_000u32;
+    let u8 byte = 250u8;
+    let u64 max = 18_446_744_073_709_551_615u64;
+    let u32 small = 7;
+    let wrapped = byte + 10;
+    let u32 quotient = big / 3;
+    let u32 remainder = big % small;
+    let bool bigger = big > 1;
+    let bool under = max > 0xFFFF_FFFFu64;
+    let f64 as_float = big * 1.0f64;
+    let u32 halved = half(4_000_000_000u32);
+    let u64 doubled = widen(9_000_000_000_000_000_000u64 / 2);
+    let u32 largest = math.max(big, small);
+    for i in 4_294_967_290u32..4_294_967_292u32 {
+        console.debug(i);
+    }
+    console.debug(big, byte, max, wrapped, quotient, remainder, bigger, under);
+    console.debug(as_float, halved, doubled, largest, max / 10);
+}
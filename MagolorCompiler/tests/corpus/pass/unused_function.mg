@@ -0,0 +1,7 @@
+i32 fn helper() {
+    return 1;
+}
+
+void fn main() {
+    console.print("helper is never called");
+}
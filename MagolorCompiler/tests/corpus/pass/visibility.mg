@@ -0,0 +1,13 @@
+// Other modules can only call the `pub` functions of a library, so a
+// private one that none of them reaches is dead
+pub i32 fn double(i32: x) {
+    return scale(x, 2);
+}
+
+i32 fn scale(i32: x, i32: factor) {
+    return x * factor;
+}
+
+i32 fn leftover() {
+    return 0;
+}
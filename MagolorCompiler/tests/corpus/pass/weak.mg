@@ -0,0 +1,13 @@
+// A default that C code linked with this library can replace by defining
+// its own log_level
+#[weak]
+pub i32 fn log_level() {
+    return 1;
+}
+
+// Callable from the other objects in a shared library but not exported
+// from it
+#[visibility("hidden")]
+pub i32 fn threshold() {
+    return log_level() + 2;
+}
@@ -0,0 +1,31 @@
+// `while` checks its condition before every pass, so a loop whose condition
+// starts out false never runs, and `return` leaves the loop along with the
+// function
+i32 fn first_over(i32: start, i32: limit) {
+    while start <= limit {
+        let i32 next = start * 2;
+        if (next > limit) {
+            return next;
+        }
+        return first_over(next, limit);
+    }
+    return start;
+}
+
+#[const]
+i32 fn settled(i32: n) {
+    while (n > 100) {
+        return settled(n / 2);
+    }
+    return n;
+}
+
+void fn main() {
+    let bool done = true;
+    while !done {
+        console.print("never printed");
+    }
+    let i32 over = first_over(3, 50);
+    let i32 small = settled(1000);
+    console.debug(over, small);
+}
@@ -0,0 +1,242 @@
+// The files `--emit` writes besides the IR. Each program under tests/emit
+// is compiled and what it emits must match the file next to it with that
+// output's extension. As with the corpus, rerun with MAGOLOR_BLESS=1 to
+// rewrite the expectations after an intended change, and review the diff.
+// The tests that run clang on the IR are ignored by default, as with the
+// torture program; `cargo test --test emit -- --ignored` runs them.
+
+use MagolorCompiler::modules::bindings;
+use MagolorCompiler::modules::cli::{self, CliCommand};
+use MagolorCompiler::modules::codegen::{self, Target, Toolchain};
+use MagolorCompiler::{CodegenOptions, Compiler};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const DIR: &str = "tests/emit";
+
+// Rust keywords come out as raw identifiers, and the names Rust can't
+// escape (self, crate, ...) get a trailing underscore and a #[link_name]
+#[test]
+fn rust_bindings() {
+    let (compiler, ir) = compile("ffi.mg", Target::Native);
+    let interface = compiler.interface().expect("no interface for ffi.mg");
+    expect("ffi.rs", &bindings::rust(&interface));
+
+    // Rust passes and expects a bool zero-extended, so the definitions
+    // have to say they are
+    assert!(ir.contains("define zeroext i1 @type(i1 zeroext %0, i32 %1)"), "bool isn't zeroext:\n{}", ir);
+    assert!(ir.contains("define zeroext i8 @checksum(ptr %0, i8 %1)"), "u8 isn't zeroext:\n{}", ir);
+}
+
+// There's no libc on wasm32-wasi: console.print goes out through fd_write
+// and exit through proc_exit, both imported from the WASI host
+#[test]
+fn wasi_imports() {
+    let (_, ir) = compile("hello.mg", Target::Wasm32Wasi);
+
+    assert!(ir.contains("declare i32 @__wasi_fd_write(i32, ptr, i32, ptr)"), "no fd_write:\n{}", ir);
+    assert!(ir.contains("declare void @__wasi_proc_exit(i32)"), "no proc_exit:\n{}", ir);
+    assert!(ir.contains("\"wasm-import-module\"=\"wasi_snapshot_preview1\""), "not imported from WASI:\n{}", ir);
+    assert!(ir.contains("\"wasm-import-name\"=\"fd_write\""), "fd_write isn't an import:\n{}", ir);
+    assert!(ir.contains("\"wasm-import-name\"=\"proc_exit\""), "proc_exit isn't an import:\n{}", ir);
+    assert!(!ir.contains("@puts("), "wasm build still calls libc's puts:\n{}", ir);
+}
+
+// Functions come out in source order, then the std ones the program calls,
+// and every compile of the same program gives the same IR, whatever order
+// the compiler's hash maps happen to iterate in
+#[test]
+fn emission_order() {
+    let (_, ir) = compile("order.mg", Target::Native);
+    let defined: Vec<&str> = ir
+        .lines()
+        .filter_map(|line| line.strip_prefix("define "))
+        .filter_map(|line| line.split('@').nth(1)?.split('(').next())
+        .collect();
+    assert_eq!(
+        defined[..7],
+        ["zeta", "i32.doubled", "alpha", "middle", "main", "gcd", "lcm"],
+        "functions out of order:\n{}",
+        ir
+    );
+
+    for _ in 0..4 {
+        let (_, again) = compile("order.mg", Target::Native);
+        assert!(again == ir, "a second compile gave different IR:\n{}\n---\n{}", ir, again);
+    }
+}
+
+// time.now() and random.next() read the host's clock and randomness: libc
+// natively, WASI's imports on wasm32-wasi and the loader's on a page
+#[test]
+fn clock_and_randomness() {
+    let (_, native) = compile("clock.mg", Target::Native);
+    assert!(native.contains("declare i64 @time(ptr)"), "native time.now doesn't use time():\n{}", native);
+    assert!(native.contains("declare i32 @getentropy(ptr, i64)"), "no getentropy:\n{}", native);
+
+    let msvc = CodegenOptions { toolchain: Toolchain::Msvc, ..Default::default() };
+    let (_, windows) = compile_with("clock.mg", msvc);
+    assert!(windows.contains("declare i64 @_time64(ptr)"), "MSVC time.now doesn't use _time64:\n{}", windows);
+    assert!(windows.contains("declare i32 @rand_s(ptr)"), "no rand_s:\n{}", windows);
+
+    let (_, wasi) = compile("clock.mg", Target::Wasm32Wasi);
+    assert!(wasi.contains("declare i32 @__wasi_clock_time_get(i32, i64, ptr)"), "no clock_time_get:\n{}", wasi);
+    assert!(wasi.contains("declare i32 @__wasi_random_get(ptr, i32)"), "no random_get:\n{}", wasi);
+    assert!(wasi.contains("\"wasm-import-name\"=\"clock_time_get\""), "clock_time_get isn't an import:\n{}", wasi);
+    assert!(wasi.contains("\"wasm-import-name\"=\"random_get\""), "random_get isn't an import:\n{}", wasi);
+    assert!(!wasi.contains("@time(") && !wasi.contains("@getentropy("), "wasm build still calls libc:\n{}", wasi);
+
+    let (_, browser) = compile("clock.mg", Target::Wasm32Unknown);
+    assert!(browser.contains("\"wasm-import-name\"=\"magolor_time\""), "magolor_time isn't an import:\n{}", browser);
+    assert!(browser.contains("\"wasm-import-name\"=\"magolor_random\""), "magolor_random isn't an import:\n{}", browser);
+    assert!(codegen::JS_GLUE.contains("magolor_time()"), "js glue doesn't provide magolor_time");
+    assert!(codegen::JS_GLUE.contains("magolor_random()"), "js glue doesn't provide magolor_random");
+
+    // Programs that never ask don't import either
+    let (_, hello) = compile("hello.mg", Target::Wasm32Wasi);
+    assert!(!hello.contains("clock_time_get") && !hello.contains("random_get"), "unused imports:\n{}", hello);
+}
+
+// Objects linked into one program share main's stack base, and wasm's
+// 64 KiB stack gets a limit it can actually reach
+#[test]
+fn stack_check() {
+    let (_, native) = compile("recursion.mg", Target::Native);
+    assert!(native.contains("@__magolor_stack_base = weak global i64 0"), "stack base isn't weak:\n{}", native);
+    assert!(native.contains("%stack_used, 7340032"), "wrong native limit:\n{}", native);
+
+    let (_, wasm) = compile("recursion.mg", Target::Wasm32Wasi);
+    assert!(wasm.contains("%stack_used, 49152"), "wrong wasm limit:\n{}", wasm);
+}
+
+// #[inline] and #[inline(never)] become LLVM's function attributes, and a
+// function without either gets neither
+#[test]
+fn inline_attributes() {
+    let (_, ir) = compile("inline.mg", Target::Native);
+
+    let twice = attributes_of(&ir, "twice");
+    assert!(twice.contains(&"alwaysinline") && !twice.contains(&"noinline"), "#[inline] gave {:?}", twice);
+    let thrice = attributes_of(&ir, "thrice");
+    assert!(thrice.contains(&"noinline") && !thrice.contains(&"alwaysinline"), "#[inline(never)] gave {:?}", thrice);
+    let main = attributes_of(&ir, "main");
+    assert!(!main.contains(&"alwaysinline") && !main.contains(&"noinline"), "main got {:?}", main);
+}
+
+// clang's -O2 pipeline unrolls a loop with a known trip count, unless
+// --no-unroll-loops turns the unroller off. Needs clang, like running the
+// torture program.
+#[test]
+#[ignore = "needs clang; run with --ignored"]
+fn loop_unrolling() {
+    let (_, ir) = compile("unroll.mg", Target::Native);
+    let calls = |ir: &str| ir.lines().filter(|line| line.contains("call") && line.contains("@show(")).count();
+
+    let o2 = CodegenOptions { opt_level: Some("2".to_string()), ..Default::default() };
+    let unrolled = optimize(&ir, "unrolled", &o2);
+    assert_eq!(calls(&unrolled), 4, "loop wasn't unrolled:\n{}", unrolled);
+    assert!(unrolled.contains("@show(i32 3)"), "unrolled calls don't pass constants:\n{}", unrolled);
+
+    let no_unroll = CodegenOptions { unroll_loops: Some(false), ..o2 };
+    let rolled = optimize(&ir, "rolled", &no_unroll);
+    assert_eq!(calls(&rolled), 1, "loop was unrolled anyway:\n{}", rolled);
+}
+
+// In a browser the page is the host: output goes to env.magolor_print,
+// which the --emit js-glue loader is what provides
+#[test]
+fn browser_imports() {
+    let (_, ir) = compile("hello.mg", Target::Wasm32Unknown);
+
+    assert!(ir.contains("declare void @__env_magolor_print(ptr, i32)"), "no magolor_print:\n{}", ir);
+    assert!(ir.contains("\"wasm-import-module\"=\"env\""), "not imported from env:\n{}", ir);
+    assert!(ir.contains("\"wasm-import-name\"=\"magolor_print\""), "magolor_print isn't an import:\n{}", ir);
+    assert!(!ir.contains("wasi_snapshot_preview1"), "browser build imports WASI:\n{}", ir);
+
+    assert!(codegen::JS_GLUE.contains("env: {"), "js glue has no env imports");
+    assert!(codegen::JS_GLUE.contains("magolor_print(ptr, len)"), "js glue doesn't provide magolor_print");
+}
+
+// The loader only makes sense for the target that imports magolor_print
+#[test]
+fn js_glue_needs_browser_target() {
+    assert!(check_args(&["--target", "wasm32-unknown-unknown", "--emit", "js-glue"]).is_ok());
+
+    let err = check_args(&["--target", "wasm32-wasi", "--emit", "js-glue"]).unwrap_err();
+    assert_eq!(err.0, "--emit js-glue requires --target wasm32-unknown-unknown");
+    assert!(check_args(&["--emit", "js-glue"]).is_err());
+}
+
+fn check_args(args: &[&str]) -> Result<(), cli::UsageError> {
+    let argv = ["magolor", "hello.mg"].iter().chain(args).map(|a| a.to_string()).collect();
+    match cli::parse_args(argv) {
+        CliCommand::Compile(files) => cli::check_options(&files[0]),
+        _ => panic!("{:?} isn't a compile command", args),
+    }
+}
+
+fn compile(program: &str, target: Target) -> (Compiler, String) {
+    compile_with(program, CodegenOptions { target, ..Default::default() })
+}
+
+fn compile_with(program: &str, options: CodegenOptions) -> (Compiler, String) {
+    let path = format!("{}/{}", DIR, program);
+    let source = fs::read_to_string(&path).unwrap_or_else(|e| panic!("cannot read {}: {}", path, e));
+    let mut compiler = Compiler::new();
+    compiler.set_codegen_options(options);
+    let (ir, diagnostics) = compiler.compile(&path, &source);
+    let ir = ir.unwrap_or_else(|| {
+        let rendered: Vec<String> =
+            diagnostics.sorted().into_iter().map(|d| compiler.source_map().render(d)).collect();
+        panic!("{} failed to compile:\n{}", path, rendered.join("\n"))
+    });
+    (compiler, ir)
+}
+
+// The attributes in the group `function`'s definition refers to
+fn attributes_of<'a>(ir: &'a str, function: &str) -> Vec<&'a str> {
+    let signature = format!("@{}(", function);
+    let define = ir
+        .lines()
+        .find(|line| line.starts_with("define") && line.contains(&signature))
+        .unwrap_or_else(|| panic!("no definition of {}:\n{}", function, ir));
+    let group = define.trim_end_matches(" {").rsplit(' ').next().unwrap_or_default();
+    if !group.starts_with('#') {
+        return Vec::new();
+    }
+    let prefix = format!("attributes {} = {{ ", group);
+    let attributes = ir
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .unwrap_or_else(|| panic!("no attribute group {}:\n{}", group, ir));
+    attributes.trim_end_matches(" }").split(' ').collect()
+}
+
+// The IR clang makes of `ir` with the flags a build with `options` passes it
+fn optimize(ir: &str, name: &str, options: &CodegenOptions) -> String {
+    let ir_file = env::temp_dir().join(format!("magolor-emit-{}-{}.ll", std::process::id(), name));
+    fs::write(&ir_file, ir).expect("cannot write the IR");
+    let mut clang = options.linker_command();
+    let program = clang.remove(0);
+    let output = Command::new(&program)
+        .args(&clang)
+        .args(["-S", "-emit-llvm", "-o", "-"])
+        .arg(&ir_file)
+        .output()
+        .unwrap_or_else(|e| panic!("cannot run {}: {}", program, e));
+    let _ = fs::remove_file(&ir_file);
+    assert!(output.status.success(), "{} failed:\n{}", program, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn expect(file: &str, actual: &str) {
+    let path = Path::new(DIR).join(file);
+    if env::var_os("MAGOLOR_BLESS").is_some() {
+        fs::write(&path, actual).unwrap_or_else(|e| panic!("cannot bless {}: {}", path.display(), e));
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_default();
+    assert_eq!(expected, actual, "output differs from {}", path.display());
+}
@@ -0,0 +1,5 @@
+void fn main() {
+    let i64 started = time.now();
+    let i64 roll = random.next();
+    console.print(started, roll);
+}
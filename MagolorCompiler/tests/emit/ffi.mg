@@ -0,0 +1,20 @@
+// Exported names that are Rust keywords, including ones Rust can't escape
+pub bool fn type(bool: ref, i32: self) {
+    return ref;
+}
+
+pub i32 fn self(i32: crate) {
+    return crate;
+}
+
+pub u8 fn checksum(string: text, char: separator) {
+    return 0;
+}
+
+pub void fn reset() {
+}
+
+// Not exported, so not in the bindings
+i32 fn helper() {
+    return 1;
+}
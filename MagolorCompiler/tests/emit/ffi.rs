@@ -0,0 +1,14 @@
+// Bindings for the Magolor module `ffi`, generated by magolor; do not edit.
+// Link the module's object or static library (`--emit staticlib` and
+// `cargo:rustc-link-lib=static=ffi` from a build script). Build it
+// with --no-main so its init() runs when the program starts.
+
+use std::ffi::c_char;
+
+unsafe extern "C" {
+    pub fn checksum(text: *const c_char, separator: c_char) -> u8;
+    pub fn reset();
+    #[link_name = "self"]
+    pub fn self_(crate_: i32) -> i32;
+    pub fn r#type(r#ref: bool, self_: i32) -> bool;
+}
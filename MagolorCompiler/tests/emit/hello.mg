@@ -0,0 +1,3 @@
+void fn main() {
+    console.print("Hello, world!");
+}
@@ -0,0 +1,13 @@
+#[inline]
+fn twice(i32: n) -> i32 {
+    return n * 2;
+}
+
+#[inline(never)]
+fn thrice(i32: n) -> i32 {
+    return n * 3;
+}
+
+void fn main() {
+    console.print(twice(4) + thrice(5));
+}
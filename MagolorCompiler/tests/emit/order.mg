@@ -0,0 +1,23 @@
+use numbers;
+
+fn zeta(i32: n) -> i32 {
+    return alpha(n) + 1;
+}
+
+impl i32 {
+    fn doubled(self) -> i32 {
+        return self * 2;
+    }
+}
+
+fn alpha(i32: n) -> i32 {
+    return n.doubled();
+}
+
+fn middle(i32: n) -> i32 {
+    return gcd(n, 12) + lcm(n, 4);
+}
+
+void fn main() {
+    console.print(zeta(3) + middle(8));
+}
@@ -0,0 +1,11 @@
+i32 fn depth(i32: n) {
+    if (n == 0) {
+        return 0;
+    }
+    return depth(n - 1) + 1;
+}
+
+void fn main() {
+    let i32 deepest = depth(10);
+    console.print(deepest);
+}
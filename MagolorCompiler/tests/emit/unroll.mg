@@ -0,0 +1,13 @@
+// A counted loop around a call that stays a call, so the optimized IR
+// shows how many times the loop body was copied
+#[inline(never)]
+i32 fn show(i32: n) {
+    console.print(n);
+    return n;
+}
+
+void fn main() {
+    for i in 0..4 {
+        let i32 shown = show(i);
+    }
+}
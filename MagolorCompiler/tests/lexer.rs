@@ -0,0 +1,126 @@
+// The lexer on its own, as the tools that read source without compiling
+// it use it, and the parser fed from it a token at a time.
+
+use MagolorCompiler::modules::parser::{AST, Items};
+use MagolorCompiler::modules::tokenizer::{KeywordCase, Token, TriviaKind, lex};
+use std::cell::Cell;
+
+const PROGRAM: &str = "// Doubles its argument
+fn double(i32: n) -> i32 {
+    /* no overflow check */ return n * 2; // trailing
+}
+
+/* the end */
+";
+
+#[test]
+fn trivia_rebuilds_the_source() {
+    let mut lexer = lex(PROGRAM, KeywordCase::default()).keep_trivia(true);
+    let mut rebuilt = String::new();
+    let mut comments = Vec::new();
+    loop {
+        let token = lexer.next();
+        for trivia in lexer.trivia() {
+            let text = &PROGRAM[trivia.span.start..trivia.span.end];
+            if trivia.kind != TriviaKind::Whitespace {
+                comments.push((trivia.kind, text));
+            }
+            rebuilt.push_str(text);
+        }
+        match token {
+            Some(Ok((_, span))) => rebuilt.push_str(&PROGRAM[span.start..span.end]),
+            Some(Err(e)) => panic!("unexpected lex error: {}", e.message()),
+            None => break,
+        }
+    }
+
+    assert_eq!(rebuilt, PROGRAM);
+    assert_eq!(
+        comments,
+        [
+            (TriviaKind::LineComment, "// Doubles its argument"),
+            (TriviaKind::BlockComment, "/* no overflow check */"),
+            (TriviaKind::LineComment, "// trailing"),
+            (TriviaKind::BlockComment, "/* the end */"),
+        ]
+    );
+}
+
+#[test]
+fn trivia_is_off_by_default() {
+    let mut lexer = lex(PROGRAM, KeywordCase::default());
+    while lexer.next().is_some() {
+        assert!(lexer.trivia().is_empty());
+    }
+    assert!(lexer.trivia().is_empty());
+}
+
+#[test]
+fn lexing_stops_when_the_caller_does() {
+    // Only the tokens asked for are lexed, so the error after them never is
+    let source = format!("let i32 x = 1; @ {}", "x ".repeat(1_000_000));
+    let mut lexer = lex(&source, KeywordCase::default());
+    let first: Vec<Token> = lexer.by_ref().take(3).map(|lexed| lexed.unwrap().0).collect();
+    assert_eq!(first, [Token::Let, Token::I32Type, Token::Ident("x".into())]);
+    assert_eq!(lexer.next().map(|lexed| lexed.unwrap().0), Some(Token::Eq));
+}
+
+#[test]
+fn tokens_and_errors_come_in_order() {
+    let source = "x @ \"open\ny";
+    let lexed: Vec<String> = lex(source, KeywordCase::default())
+        .map(|lexed| match lexed {
+            Ok((token, span)) => format!("{:?} at {}", token, span.start),
+            Err(e) => format!("{} at {}", e.message(), e.span.start),
+        })
+        .collect();
+    assert_eq!(
+        lexed,
+        [
+            "Ident(\"x\") at 0",
+            "unexpected character '@' at 2",
+            "unterminated string literal at 4",
+            // What the string was most likely meant to be
+            "String(\"open\") at 4",
+            "Ident(\"y\") at 10",
+        ]
+    );
+}
+
+#[test]
+fn items_are_parsed_as_their_tokens_arrive() {
+    let source = "fn one() -> i32 { return 1; }
+if ready { go(); } else { wait(); }
+fn two() -> i32 { return 2; }
+";
+    let lexed = Cell::new(0);
+    let tokens = lex(source, KeywordCase::default())
+        .map(Result::unwrap)
+        .inspect(|_| lexed.set(lexed.get() + 1));
+    let mut items = Items::new(tokens);
+
+    let first = items.next().unwrap().unwrap();
+    assert!(matches!(&first[..], [AST::FuncDef { name, .. }] if name == "one"));
+    assert_eq!(lexed.get(), 11, "read past the first function");
+
+    // The `if` has to see past its own `}` for an `else`, and no further
+    let second = items.next().unwrap().unwrap();
+    assert!(matches!(&second[..], [AST::If { else_body: Some(_), .. }]));
+    assert_eq!(lexed.get(), 26, "read past the if statement");
+
+    let third = items.next().unwrap().unwrap();
+    assert!(matches!(&third[..], [AST::FuncDef { name, .. }] if name == "two"));
+    assert!(items.next().is_none());
+}
+
+#[test]
+fn a_broken_item_is_skipped() {
+    let source = "fn one( { return 1; }\nfn two() -> i32 { return 2; }\n";
+    let mut items = Items::new(lex(source, KeywordCase::default()).map(Result::unwrap));
+
+    let (_, span) = items.next().unwrap().unwrap_err();
+    assert_eq!(&source[span.start..span.end], "fn one( { return 1; }");
+    let second = items.next().unwrap().unwrap();
+    assert!(matches!(&second[..], [AST::FuncDef { name, .. }] if name == "two"));
+    assert!(items.next().is_none());
+}
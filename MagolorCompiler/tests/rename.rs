@@ -0,0 +1,77 @@
+// The symbol index behind `magolor refs` and `magolor rename`, on a program
+// where a method and a free function share a name.
+
+use MagolorCompiler::modules::rename;
+use MagolorCompiler::modules::source::SourceFile;
+use MagolorCompiler::{Compiler, Symbol};
+
+const FILE: &str = "squares.mg";
+const PROGRAM: &str = "impl i32 {
+    fn squared(self) -> i32 {
+        let i32 result = self * self;
+        return result;
+    }
+}
+
+fn squared(i32: n) -> i32 {
+    return n * n;
+}
+
+void fn main() {
+    let i32 level = 3;
+    let i32 a = level.squared();
+    let i32 b = squared(level);
+    console.print(a + b);
+}
+";
+
+fn analyzed() -> Compiler {
+    let mut compiler = Compiler::new();
+    let ast = compiler.parse_source(SourceFile::from_string(FILE, PROGRAM.to_string()));
+    compiler.analyze(&ast);
+    compiler
+}
+
+// Offset of the `nth` occurrence of `text` in the program
+fn offset_of(text: &str, nth: usize) -> usize {
+    PROGRAM.match_indices(text).nth(nth).map(|(offset, _)| offset).expect("text not in the program")
+}
+
+#[test]
+fn methods_are_not_functions() {
+    let compiler = analyzed();
+    let index = compiler.symbol_index(FILE).expect("no index");
+
+    let method = Symbol::Method { ty: "i32".to_string(), name: "squared".to_string() };
+    assert_eq!(index.definition(&method).map(|span| span.start), Some(offset_of("squared", 0)));
+
+    // Only the plain call is the function's; `level.squared()` is the method's
+    let function = Symbol::Function("squared".to_string());
+    assert_eq!(index.definition(&function).map(|span| span.start), Some(offset_of("squared", 1)));
+    let calls: Vec<usize> = index.references(&function).iter().map(|span| span.start).collect();
+    assert_eq!(calls, [offset_of("squared", 3)]);
+
+    // The method's body is indexed under its own name
+    let local = Symbol::Local { function: "i32.squared".to_string(), name: "result".to_string() };
+    assert!(index.definition(&local).is_some(), "method locals aren't indexed");
+    assert_eq!(index.references(&local).len(), 1);
+
+    let found: Vec<String> = index.lookup("i32.squared").iter().map(|(symbol, _)| symbol.to_string()).collect();
+    assert_eq!(found, ["i32.squared"]);
+}
+
+#[test]
+fn rename_refuses_methods() {
+    let compiler = analyzed();
+    let index = compiler.symbol_index(FILE).expect("no index");
+
+    let err = rename::rename(compiler.source_map(), index, offset_of("squared", 0), "cubed").unwrap_err();
+    assert_eq!(err, "cannot rename method i32.squared: its calls can't be found without types");
+
+    // Renaming the function leaves the method and its call alone
+    let edits = rename::rename(compiler.source_map(), index, offset_of("squared", 1), "cubed").unwrap();
+    assert_eq!(edits.len(), 1);
+    let renamed = &edits[0].1;
+    assert!(renamed.contains("fn squared(self)") && renamed.contains("level.squared()"));
+    assert!(renamed.contains("fn cubed(i32: n)") && renamed.contains("cubed(level)"));
+}
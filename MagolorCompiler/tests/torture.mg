@@ -0,0 +1,180 @@
+// One program that leans on every part of the language at once. The
+// torture test compiles it, links it with clang, runs it and compares what
+// it prints with torture.stdout, so keep the two in step.
+use numbers;
+
+impl i32 {
+    fn squared(self) -> i32 {
+        return self * self;
+    }
+
+    i32 fn clamp(self, i32: low, i32: high) {
+        if (self < low) {
+            return low;
+        }
+        if (self > high) {
+            return high;
+        }
+        return self;
+    }
+}
+
+impl i64 {
+    fn doubled(self) -> i64 {
+        return self * 2i64;
+    }
+}
+
+#[const]
+i32 fn budget(i32: size) {
+    if (size > 5) {
+        return 100;
+    }
+    return 10;
+}
+
+fn factorial(i32: n) -> i32 {
+    if (n <= 1) {
+        return 1;
+    }
+    return n * factorial(n - 1);
+}
+
+i32 fn fib(i32: n) {
+    if (n < 2) {
+        return n;
+    }
+    return fib(n - 1) + fib(n - 2);
+}
+
+// Halves `n` until it's no more than `limit`
+i32 fn shrink(i32: n, i32: limit) {
+    while n > limit {
+        let i32 half = n / 2;
+        return shrink(half, limit);
+    }
+    return n;
+}
+
+bool fn between(i32: value, i32: low, i32: high) {
+    return value >= low && value <= high;
+}
+
+i64 fn area(i64: width, i64: height) {
+    return width * height;
+}
+
+// Prints what kind of number `n` is and gives it back
+#[inline]
+i32 fn classify(i32: n) {
+    if (n < 0) {
+        console.print("negative");
+    } elif (n == 0) {
+        console.print("zero");
+    } elif (n < 10) {
+        console.print("small");
+    } else {
+        console.print("large");
+    }
+    return n;
+}
+
+void fn main() {
+    console.print("torture");
+
+    // Bindings and arithmetic
+    const i32 LIMIT = 10;
+    const SCALE = LIMIT * 4 + 2;
+    let mut i32 total = SCALE - LIMIT;
+    let i32 a = 7;
+    let i32 b = 2;
+    let i32 mixed = a + b * 3;
+    let i32 grouped = (a + b) * 3;
+    let i32 rest = a % b - a / b;
+    let i32 hex = 0xFF;
+    let i32 million = 1_000_000;
+    console.debug(total, mixed, grouped, rest, hex, million);
+    let i32 halved = a / b;
+    let f32 exact = a / 2.0;
+    let i32 truncated = -a / b;
+    console.debug(halved, exact, truncated);
+    let u32 unsigned_big = 4_000_000_000u32;
+    let u8 byte = 250u8;
+    let bool above = unsigned_big > 1;
+    console.debug(unsigned_big / 3, byte + 10, above);
+
+    // Calls, recursion and compile-time functions
+    let i32 fact = factorial(5);
+    let i32 fifteenth = fib(15);
+    let i32 spent = budget(8);
+    let i64 big = area(3i64, 4i64) + 1;
+    console.debug(fact, fifteenth, spent, big);
+    console.print(fact + fifteenth);
+
+    // Methods on built-in types
+    let i32 reading = 140;
+    let i32 level = reading.clamp(0, 100);
+    let i32 square = level.squared();
+    let i64 twice = big.doubled();
+    console.debug(level, square, twice);
+
+    // Conditions and logic
+    let i32 below = classify(-5);
+    let i32 nothing = classify(0);
+    let i32 few = classify(a);
+    let i32 many = classify(million);
+    let bool done = false;
+    if (a < b && b != 0) {
+        console.print("wrong: &&");
+    } elif !(a < b) && between(a, 1, 9) {
+        console.print("logic");
+    }
+    if a == 0 || b == 2 && !done {
+        console.print("precedence");
+    }
+    while done {
+        console.print("wrong: while");
+    }
+    let i32 shrunk = shrink(1000, 100);
+    console.print(shrunk);
+    // Variables print as numbers, whatever their numeric type
+    console.print(exact, unsigned_big, byte, big);
+    let f64 unknown = f64.nan;
+    if unknown == unknown || unknown < f64.inf || unknown >= -f64.inf {
+        console.print("wrong: nan");
+    }
+    if unknown != unknown {
+        console.print(-f64.inf);
+    }
+    for k in 1..4 {
+        let i32 cube = k * k.squared();
+        console.debug(cube);
+    }
+    let bool inside = between(level, 0, 100);
+    let bool outside = between(reading, 0, 100);
+    console.debug(inside, outside);
+    console.debug(outside < inside, inside == outside);
+
+    // The prelude
+    let str name = "magolor";
+    let i32 length = strings.len(name);
+    let bool same = strings.eq(name, "magolor");
+    let i32 low = math.min(3, 7);
+    let i64 high = math.max(2i64, 9);
+    let i64 gap = math.abs(high - 20i64);
+    assert.that(same);
+    console.debug(name, length, same, low, high, gap);
+    console.debug('x');
+
+    // The standard library
+    let i32 divisor = gcd(12, 18);
+    let i32 multiple = lcm(4, 6);
+    let i64 huge = power(2i64, 40);
+    let i32 negative = sign(-3);
+    let bool even = is_even(multiple);
+    console.debug(divisor, multiple, huge, negative, even);
+
+    console.print("done");
+    process.exit(0);
+    console.print("never printed");
+}
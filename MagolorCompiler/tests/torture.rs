@@ -0,0 +1,83 @@
+// End-to-end gate: tests/torture.mg uses every feature of the language, so
+// a regression anywhere from the tokenizer to the IR shows up here. It's
+// compiled like any other program, linked with clang and run, and what it
+// prints must match tests/torture.stdout.
+//
+// Running it needs clang on the PATH, so that half is ignored by default
+// and `cargo test` reports it as such; run it with
+// `cargo test --test torture -- --ignored`, where a missing clang fails
+// the test. After an intended change in output, rerun that with
+// MAGOLOR_BLESS=1 to rewrite torture.stdout from what the program printed,
+// and review the diff.
+
+use MagolorCompiler::Compiler;
+use MagolorCompiler::modules::codegen::CodegenOptions;
+use std::env;
+use std::fs;
+use std::process::Command;
+
+const PROGRAM: &str = "tests/torture.mg";
+const EXPECTED: &str = "tests/torture.stdout";
+
+#[test]
+fn torture_compiles() {
+    compile();
+}
+
+#[test]
+#[ignore = "needs clang; run with --ignored"]
+fn torture_runs() {
+    let ir = compile();
+
+    let dir = env::temp_dir().join(format!("magolor-torture-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("cannot create a scratch directory");
+    let ir_file = dir.join("torture.ll");
+    fs::write(&ir_file, ir).expect("cannot write the IR");
+
+    let options = CodegenOptions::default();
+    let executable = options.artifact_path(&ir_file.to_string_lossy(), true);
+    let mut clang = options.linker_command();
+    let program = clang.remove(0);
+    let linked = Command::new(&program)
+        .args(&clang)
+        .arg(&ir_file)
+        .arg("-o")
+        .arg(&executable)
+        .output()
+        .unwrap_or_else(|e| panic!("cannot run {} to build the torture program: {}", program, e));
+    assert!(
+        linked.status.success(),
+        "{} couldn't build the torture program:\n{}",
+        program,
+        String::from_utf8_lossy(&linked.stderr)
+    );
+
+    let run = Command::new(&executable).output().expect("cannot run the torture program");
+    let _ = fs::remove_dir_all(&dir);
+    assert!(run.status.success(), "the torture program exited with {}", run.status);
+
+    let actual = String::from_utf8_lossy(&run.stdout);
+    if env::var_os("MAGOLOR_BLESS").is_some() {
+        fs::write(EXPECTED, actual.as_bytes()).expect("cannot bless the expected output");
+        return;
+    }
+    let expected = fs::read_to_string(EXPECTED).expect("cannot read the expected output");
+    assert!(
+        actual == expected,
+        "output differs from {}\n--- expected\n{}--- actual\n{}",
+        EXPECTED,
+        expected,
+        actual
+    );
+}
+
+fn compile() -> String {
+    let source = fs::read_to_string(PROGRAM).expect("cannot read the torture program");
+    let mut compiler = Compiler::new();
+    let (ir, diagnostics) = compiler.compile(PROGRAM, &source);
+    ir.unwrap_or_else(|| {
+        let rendered: Vec<String> =
+            diagnostics.sorted().into_iter().map(|d| compiler.source_map().render(d)).collect();
+        panic!("{} failed to compile:\n{}", PROGRAM, rendered.join("\n"))
+    })
+}